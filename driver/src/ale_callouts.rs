@@ -80,8 +80,8 @@ pub fn ale_layer_connect_v4(data: CalloutData) {
         local_port: data.get_value_u16(Fields::IpLocalPort as usize),
         remote_ip: get_ipv4_address(&data, Fields::IpRemoteAddress as usize),
         remote_port: data.get_value_u16(Fields::IpRemotePort as usize),
-        interface_index: 0,
-        sub_interface_index: 0,
+        interface_index: data.get_value_u32(Fields::InterfaceIndex as usize),
+        sub_interface_index: data.get_value_u32(Fields::SubInterfaceIndex as usize),
     };
 
     ale_layer_auth(data, ale_data);
@@ -180,12 +180,28 @@ fn ale_layer_auth(mut data: CalloutData, ale_data: AleLayerData) {
             })
     };
 
+    // New connections that match a rule or a process policy get that verdict and are not sent to the client.
+    // Rules are checked first.
+    let verdict = verdict.or_else(|| {
+        let verdict = device
+            .find_rule_verdict(&key, ale_data.direction)
+            .or_else(|| {
+                device
+                    .process_policy
+                    .get(ale_data.process_id, || data.get_process_path())
+            })?;
+        add_connection_with_verdict(device, &ale_data, &key, verdict)
+    });
+
     // Connection already in cache.
     if let Some(verdict) = verdict {
         crate::dbg!("processing existing connection: {} {}", key, verdict);
         match verdict {
             // No verdict yet
             Verdict::Undecided => {
+                if handle_pending_overflow(device, &mut data, None) {
+                    return;
+                }
                 crate::dbg!("saving packet: {}", key);
                 // Connection is already pended. Save packet and wait for verdict.
                 match save_packet(device, &mut data, &ale_data, false) {
@@ -195,6 +211,8 @@ fn ale_layer_auth(mut data: CalloutData, ale_data: AleLayerData) {
                             ale_data.process_id,
                             ale_data.direction,
                             true,
+                            ale_data.interface_index,
+                            ale_data.sub_interface_index,
                         );
                         if let Some(info) = info {
                             let _ = device.event_queue.push(info);
@@ -244,16 +262,29 @@ fn ale_layer_auth(mut data: CalloutData, ale_data: AleLayerData) {
             }
         }
     } else {
+        if handle_pending_overflow(device, &mut data, Some((&ale_data, &key))) {
+            return;
+        }
         crate::dbg!("pending connection: {} {}", key, ale_data.direction);
         // Only first packet of a connection can be pended: reauthorize == false
         let can_pend_connection = !ale_data.reauthorize;
         match save_packet(device, &mut data, &ale_data, can_pend_connection) {
             Ok(packet) => {
+                // Send the process path before the first connection info of the process, the process
+                // may exit before user-space resolves the PID.
+                if let Some(info) = device
+                    .announced_processes
+                    .announce(ale_data.process_id, || data.get_process_path())
+                {
+                    let _ = device.event_queue.push(info);
+                }
                 let info = device.packet_cache.push(
                     (key, packet),
                     ale_data.process_id,
                     ale_data.direction,
                     true,
+                    ale_data.interface_index,
+                    ale_data.sub_interface_index,
                 );
                 if let Some(info) = info {
                     let _ = device.event_queue.push(info);
@@ -281,6 +312,47 @@ fn ale_layer_auth(mut data: CalloutData, ale_data: AleLayerData) {
     }
 }
 
+/// Permits or blocks the packet without the client if the packet cache is full.
+/// A new connection is added to the cache with the verdict, the client never sees it.
+/// Returns true if the packet was handled.
+fn handle_pending_overflow(
+    device: &mut Device,
+    data: &mut CalloutData,
+    new_connection: Option<(&AleLayerData, &Key)>,
+) -> bool {
+    let Some(verdict) = device.pending_overflow_verdict() else {
+        return false;
+    };
+    if let Some((ale_data, key)) = new_connection {
+        add_connection_with_verdict(device, ale_data, key, verdict.to_permanent());
+    }
+    match verdict {
+        Verdict::Accept => data.action_permit(),
+        _ => data.action_block(),
+    }
+    true
+}
+
+/// Adds a new connection to the cache with a verdict that was decided in the driver.
+fn add_connection_with_verdict(
+    device: &mut Device,
+    ale_data: &AleLayerData,
+    key: &Key,
+    verdict: Verdict,
+) -> Option<Verdict> {
+    crate::dbg!("driver verdict for new connection: {} {}", key, verdict);
+    if ale_data.is_ipv6 {
+        let mut conn = ConnectionV6::from_key(key, ale_data.process_id, ale_data.direction).ok()?;
+        conn.verdict = verdict;
+        device.connection_cache.add_connection_v6(conn);
+    } else {
+        let mut conn = ConnectionV4::from_key(key, ale_data.process_id, ale_data.direction).ok()?;
+        conn.verdict = verdict;
+        device.connection_cache.add_connection_v4(conn);
+    }
+    Some(verdict)
+}
+
 fn save_packet(
     device: &Device,
     callout_data: &mut CalloutData,
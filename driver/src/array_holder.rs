@@ -1,25 +1,35 @@
-use core::cell::RefCell;
+use core::{
+    cell::RefCell,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
 use alloc::vec::Vec;
 
-pub struct ArrayHolder(RefCell<Option<Vec<u8>>>);
+pub struct ArrayHolder(RefCell<Option<Vec<u8>>>, AtomicUsize);
 unsafe impl Sync for ArrayHolder {}
 
 impl ArrayHolder {
     pub const fn default() -> Self {
-        Self(RefCell::new(None))
+        Self(RefCell::new(None), AtomicUsize::new(0))
     }
 
     pub fn save(&self, data: &[u8]) {
         if let Ok(mut opt) = self.0.try_borrow_mut() {
             opt.replace(data.to_vec());
+            self.1.store(data.len(), Ordering::Relaxed);
         }
     }
 
     pub fn load(&self) -> Option<Vec<u8>> {
         if let Ok(mut opt) = self.0.try_borrow_mut() {
+            self.1.store(0, Ordering::Relaxed);
             return opt.take();
         }
         None
     }
+
+    /// Returns the size of the saved array. Does not borrow the array.
+    pub fn size(&self) -> usize {
+        self.1.load(Ordering::Relaxed)
+    }
 }
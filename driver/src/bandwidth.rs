@@ -1,4 +1,7 @@
-use protocol::info::{BandwidthValueV4, BandwidthValueV6, Info};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use alloc::{collections::BTreeMap, vec::Vec};
+use protocol::info::{BandwidthValueProcess, BandwidthValueV4, BandwidthValueV6, Info};
 use smoltcp::wire::{IpProtocol, Ipv4Address, Ipv6Address};
 use wdk::rw_spin_lock::RwSpinLock;
 
@@ -15,27 +18,117 @@ where
     pub remote_port: u16,
 }
 
+// Per process stats are split by protocol.
+#[derive(Debug, Hash, PartialEq, Eq, Clone, Copy)]
+struct ProcessKey {
+    process_id: u64,
+    protocol: IpProtocol,
+}
+
+// Key for the protocols other than TCP and UDP. Ports are 0 if the protocol has none.
+type OtherKey<Address> = (IpProtocol, Key<Address>);
+
 struct Value {
     received_bytes: usize,
     transmitted_bytes: usize,
+    received_packets: usize,
+    transmitted_packets: usize,
+    // Windows system time in milliseconds.
+    first_seen_timestamp: u64,
+    last_seen_timestamp: u64,
+}
+
+impl Value {
+    fn add(&mut self, direction: Direction, timestamp: u64) {
+        match direction {
+            Direction::Tx(bytes_count, packets_count) => {
+                self.transmitted_bytes += bytes_count;
+                self.transmitted_packets += packets_count;
+            }
+            Direction::Rx(bytes_count, packets_count) => {
+                self.received_bytes += bytes_count;
+                self.received_packets += packets_count;
+            }
+        }
+        self.last_seen_timestamp = timestamp;
+    }
+
+    fn to_v4(&self, key: &Key<Ipv4Address>) -> BandwidthValueV4 {
+        BandwidthValueV4 {
+            local_ip: key.local_ip.0,
+            local_port: key.local_port,
+            remote_ip: key.remote_ip.0,
+            remote_port: key.remote_port,
+            transmitted_bytes: self.transmitted_bytes as u64,
+            received_bytes: self.received_bytes as u64,
+            transmitted_packets: self.transmitted_packets as u64,
+            received_packets: self.received_packets as u64,
+            first_seen_timestamp: self.first_seen_timestamp,
+            last_seen_timestamp: self.last_seen_timestamp,
+        }
+    }
+
+    fn to_v6(&self, key: &Key<Ipv6Address>) -> BandwidthValueV6 {
+        BandwidthValueV6 {
+            local_ip: key.local_ip.0,
+            local_port: key.local_port,
+            remote_ip: key.remote_ip.0,
+            remote_port: key.remote_port,
+            transmitted_bytes: self.transmitted_bytes as u64,
+            received_bytes: self.received_bytes as u64,
+            transmitted_packets: self.transmitted_packets as u64,
+            received_packets: self.received_packets as u64,
+            first_seen_timestamp: self.first_seen_timestamp,
+            last_seen_timestamp: self.last_seen_timestamp,
+        }
+    }
 }
 
+// Bytes and packets.
 enum Direction {
-    Tx(usize),
-    Rx(usize),
+    Tx(usize, usize),
+    Rx(usize, usize),
 }
+
+/// Number of entries in each map of `Bandwidth`.
+pub struct BandwidthEntriesCount {
+    pub tcp_v4: usize,
+    pub tcp_v6: usize,
+    pub udp_v4: usize,
+    pub udp_v6: usize,
+    pub other_v4: usize,
+    pub other_v6: usize,
+    pub process: usize,
+}
+
 pub struct Bandwidth {
     stats_tcp_v4: DeviceHashMap<Key<Ipv4Address>, Value>,
     stats_tcp_v4_lock: RwSpinLock,
+    stats_tcp_v4_count: AtomicUsize,
 
     stats_tcp_v6: DeviceHashMap<Key<Ipv6Address>, Value>,
     stats_tcp_v6_lock: RwSpinLock,
+    stats_tcp_v6_count: AtomicUsize,
 
     stats_udp_v4: DeviceHashMap<Key<Ipv4Address>, Value>,
     stats_udp_v4_lock: RwSpinLock,
+    stats_udp_v4_count: AtomicUsize,
 
     stats_udp_v6: DeviceHashMap<Key<Ipv6Address>, Value>,
     stats_udp_v6_lock: RwSpinLock,
+    stats_udp_v6_count: AtomicUsize,
+
+    stats_other_v4: DeviceHashMap<OtherKey<Ipv4Address>, Value>,
+    stats_other_v4_lock: RwSpinLock,
+    stats_other_v4_count: AtomicUsize,
+
+    stats_other_v6: DeviceHashMap<OtherKey<Ipv6Address>, Value>,
+    stats_other_v6_lock: RwSpinLock,
+    stats_other_v6_count: AtomicUsize,
+
+    stats_process: DeviceHashMap<ProcessKey, Value>,
+    stats_process_lock: RwSpinLock,
+    stats_process_count: AtomicUsize,
 }
 
 impl Bandwidth {
@@ -43,251 +136,401 @@ impl Bandwidth {
         Self {
             stats_tcp_v4: DeviceHashMap::new(),
             stats_tcp_v4_lock: RwSpinLock::default(),
+            stats_tcp_v4_count: AtomicUsize::new(0),
 
             stats_tcp_v6: DeviceHashMap::new(),
             stats_tcp_v6_lock: RwSpinLock::default(),
+            stats_tcp_v6_count: AtomicUsize::new(0),
 
             stats_udp_v4: DeviceHashMap::new(),
             stats_udp_v4_lock: RwSpinLock::default(),
+            stats_udp_v4_count: AtomicUsize::new(0),
 
             stats_udp_v6: DeviceHashMap::new(),
             stats_udp_v6_lock: RwSpinLock::default(),
+            stats_udp_v6_count: AtomicUsize::new(0),
+
+            stats_other_v4: DeviceHashMap::new(),
+            stats_other_v4_lock: RwSpinLock::default(),
+            stats_other_v4_count: AtomicUsize::new(0),
+
+            stats_other_v6: DeviceHashMap::new(),
+            stats_other_v6_lock: RwSpinLock::default(),
+            stats_other_v6_count: AtomicUsize::new(0),
+
+            stats_process: DeviceHashMap::new(),
+            stats_process_lock: RwSpinLock::default(),
+            stats_process_count: AtomicUsize::new(0),
         }
     }
 
-    pub fn get_all_updates_tcp_v4(&mut self) -> Option<Info> {
+    pub fn get_all_updates_tcp_v4(&mut self, max_frame_size: usize) -> Vec<Info> {
         let stats_map;
         {
             let _guard = self.stats_tcp_v4_lock.write_lock();
             if self.stats_tcp_v4.is_empty() {
-                return None;
+                return Vec::new();
             }
             stats_map = core::mem::replace(&mut self.stats_tcp_v4, DeviceHashMap::new());
+            self.stats_tcp_v4_count.store(0, Ordering::Relaxed);
         }
 
-        let mut values = alloc::vec::Vec::with_capacity(stats_map.len());
+        let mut values = Vec::with_capacity(stats_map.len());
         for (key, value) in stats_map.iter() {
-            values.push(BandwidthValueV4 {
-                local_ip: key.local_ip.0,
-                local_port: key.local_port,
-                remote_ip: key.remote_ip.0,
-                remote_port: key.remote_port,
-                transmitted_bytes: value.transmitted_bytes as u64,
-                received_bytes: value.received_bytes as u64,
-            });
+            values.push(value.to_v4(key));
         }
-        Some(protocol::info::bandiwth_stats_array_v4(
-            u8::from(IpProtocol::Tcp),
-            values,
-        ))
+        protocol::info::bandwidth_stats_frames_v4(u8::from(IpProtocol::Tcp), values, max_frame_size)
     }
 
-    pub fn get_all_updates_tcp_v6(&mut self) -> Option<Info> {
+    pub fn get_all_updates_tcp_v6(&mut self, max_frame_size: usize) -> Vec<Info> {
         let stats_map;
         {
             let _guard = self.stats_tcp_v6_lock.write_lock();
             if self.stats_tcp_v6.is_empty() {
-                return None;
+                return Vec::new();
             }
             stats_map = core::mem::replace(&mut self.stats_tcp_v6, DeviceHashMap::new());
+            self.stats_tcp_v6_count.store(0, Ordering::Relaxed);
         }
 
-        let mut values = alloc::vec::Vec::with_capacity(stats_map.len());
+        let mut values = Vec::with_capacity(stats_map.len());
         for (key, value) in stats_map.iter() {
-            values.push(BandwidthValueV6 {
-                local_ip: key.local_ip.0,
-                local_port: key.local_port,
-                remote_ip: key.remote_ip.0,
-                remote_port: key.remote_port,
-                transmitted_bytes: value.transmitted_bytes as u64,
-                received_bytes: value.received_bytes as u64,
-            });
+            values.push(value.to_v6(key));
         }
-        Some(protocol::info::bandiwth_stats_array_v6(
-            u8::from(IpProtocol::Tcp),
-            values,
-        ))
+        protocol::info::bandwidth_stats_frames_v6(u8::from(IpProtocol::Tcp), values, max_frame_size)
     }
 
-    pub fn get_all_updates_udp_v4(&mut self) -> Option<Info> {
+    pub fn get_all_updates_udp_v4(&mut self, max_frame_size: usize) -> Vec<Info> {
         let stats_map;
         {
             let _guard = self.stats_udp_v4_lock.write_lock();
             if self.stats_udp_v4.is_empty() {
-                return None;
+                return Vec::new();
             }
             stats_map = core::mem::replace(&mut self.stats_udp_v4, DeviceHashMap::new());
+            self.stats_udp_v4_count.store(0, Ordering::Relaxed);
         }
 
-        let mut values = alloc::vec::Vec::with_capacity(stats_map.len());
+        let mut values = Vec::with_capacity(stats_map.len());
         for (key, value) in stats_map.iter() {
-            values.push(BandwidthValueV4 {
-                local_ip: key.local_ip.0,
-                local_port: key.local_port,
-                remote_ip: key.remote_ip.0,
-                remote_port: key.remote_port,
-                transmitted_bytes: value.transmitted_bytes as u64,
-                received_bytes: value.received_bytes as u64,
-            });
+            values.push(value.to_v4(key));
         }
-        Some(protocol::info::bandiwth_stats_array_v4(
-            u8::from(IpProtocol::Udp),
-            values,
-        ))
+        protocol::info::bandwidth_stats_frames_v4(u8::from(IpProtocol::Udp), values, max_frame_size)
     }
 
-    pub fn get_all_updates_udp_v6(&mut self) -> Option<Info> {
+    pub fn get_all_updates_udp_v6(&mut self, max_frame_size: usize) -> Vec<Info> {
         let stats_map;
         {
             let _guard = self.stats_udp_v6_lock.write_lock();
-            if self.stats_tcp_v6.is_empty() {
-                return None;
+            if self.stats_udp_v6.is_empty() {
+                return Vec::new();
             }
-            stats_map = core::mem::replace(&mut self.stats_tcp_v6, DeviceHashMap::new());
+            stats_map = core::mem::replace(&mut self.stats_udp_v6, DeviceHashMap::new());
+            self.stats_udp_v6_count.store(0, Ordering::Relaxed);
         }
 
-        let mut values = alloc::vec::Vec::with_capacity(stats_map.len());
+        let mut values = Vec::with_capacity(stats_map.len());
         for (key, value) in stats_map.iter() {
-            values.push(BandwidthValueV6 {
-                local_ip: key.local_ip.0,
-                local_port: key.local_port,
-                remote_ip: key.remote_ip.0,
-                remote_port: key.remote_port,
+            values.push(value.to_v6(key));
+        }
+        protocol::info::bandwidth_stats_frames_v6(u8::from(IpProtocol::Udp), values, max_frame_size)
+    }
+
+    pub fn get_all_updates_other_v4(&mut self, max_frame_size: usize) -> Vec<Info> {
+        let stats_map;
+        {
+            let _guard = self.stats_other_v4_lock.write_lock();
+            if self.stats_other_v4.is_empty() {
+                return Vec::new();
+            }
+            stats_map = core::mem::replace(&mut self.stats_other_v4, DeviceHashMap::new());
+            self.stats_other_v4_count.store(0, Ordering::Relaxed);
+        }
+
+        // An info has the values of one protocol.
+        let mut protocols: BTreeMap<u8, Vec<BandwidthValueV4>> = BTreeMap::new();
+        for ((protocol, key), value) in stats_map.iter() {
+            protocols
+                .entry(u8::from(*protocol))
+                .or_default()
+                .push(value.to_v4(key));
+        }
+        protocols
+            .into_iter()
+            .flat_map(|(protocol, values)| {
+                protocol::info::bandwidth_stats_frames_v4(protocol, values, max_frame_size)
+            })
+            .collect()
+    }
+
+    pub fn get_all_updates_other_v6(&mut self, max_frame_size: usize) -> Vec<Info> {
+        let stats_map;
+        {
+            let _guard = self.stats_other_v6_lock.write_lock();
+            if self.stats_other_v6.is_empty() {
+                return Vec::new();
+            }
+            stats_map = core::mem::replace(&mut self.stats_other_v6, DeviceHashMap::new());
+            self.stats_other_v6_count.store(0, Ordering::Relaxed);
+        }
+
+        // An info has the values of one protocol.
+        let mut protocols: BTreeMap<u8, Vec<BandwidthValueV6>> = BTreeMap::new();
+        for ((protocol, key), value) in stats_map.iter() {
+            protocols
+                .entry(u8::from(*protocol))
+                .or_default()
+                .push(value.to_v6(key));
+        }
+        protocols
+            .into_iter()
+            .flat_map(|(protocol, values)| {
+                protocol::info::bandwidth_stats_frames_v6(protocol, values, max_frame_size)
+            })
+            .collect()
+    }
+
+    pub fn get_all_updates_process(&mut self, max_frame_size: usize) -> Vec<Info> {
+        let stats_map;
+        {
+            let _guard = self.stats_process_lock.write_lock();
+            if self.stats_process.is_empty() {
+                return Vec::new();
+            }
+            stats_map = core::mem::replace(&mut self.stats_process, DeviceHashMap::new());
+            self.stats_process_count.store(0, Ordering::Relaxed);
+        }
+
+        let mut values = Vec::with_capacity(stats_map.len());
+        for (key, value) in stats_map.iter() {
+            values.push(BandwidthValueProcess {
+                process_id: key.process_id,
+                protocol: u8::from(key.protocol),
                 transmitted_bytes: value.transmitted_bytes as u64,
                 received_bytes: value.received_bytes as u64,
             });
         }
-        Some(protocol::info::bandiwth_stats_array_v6(
-            u8::from(IpProtocol::Udp),
-            values,
-        ))
+        protocol::info::bandwidth_stats_per_process_frames(values, max_frame_size)
     }
 
-    pub fn update_tcp_v4_tx(&mut self, key: Key<Ipv4Address>, tx_bytes: usize) {
+    pub fn update_tcp_v4_tx(&mut self, key: Key<Ipv4Address>, tx_bytes: usize, tx_packets: usize) {
         Self::update(
             &mut self.stats_tcp_v4,
             &mut self.stats_tcp_v4_lock,
+            &self.stats_tcp_v4_count,
             key,
-            Direction::Tx(tx_bytes),
+            Direction::Tx(tx_bytes, tx_packets),
         );
     }
 
-    pub fn update_tcp_v4_rx(&mut self, key: Key<Ipv4Address>, rx_bytes: usize) {
+    pub fn update_tcp_v4_rx(&mut self, key: Key<Ipv4Address>, rx_bytes: usize, rx_packets: usize) {
         Self::update(
             &mut self.stats_tcp_v4,
             &mut self.stats_tcp_v4_lock,
+            &self.stats_tcp_v4_count,
             key,
-            Direction::Rx(rx_bytes),
+            Direction::Rx(rx_bytes, rx_packets),
         );
     }
 
-    pub fn update_tcp_v6_tx(&mut self, key: Key<Ipv6Address>, tx_bytes: usize) {
+    pub fn update_tcp_v6_tx(&mut self, key: Key<Ipv6Address>, tx_bytes: usize, tx_packets: usize) {
         Self::update(
             &mut self.stats_tcp_v6,
             &mut self.stats_tcp_v6_lock,
+            &self.stats_tcp_v6_count,
             key,
-            Direction::Tx(tx_bytes),
+            Direction::Tx(tx_bytes, tx_packets),
         );
     }
 
-    pub fn update_tcp_v6_rx(&mut self, key: Key<Ipv6Address>, rx_bytes: usize) {
+    pub fn update_tcp_v6_rx(&mut self, key: Key<Ipv6Address>, rx_bytes: usize, rx_packets: usize) {
         Self::update(
             &mut self.stats_tcp_v6,
             &mut self.stats_tcp_v6_lock,
+            &self.stats_tcp_v6_count,
             key,
-            Direction::Rx(rx_bytes),
+            Direction::Rx(rx_bytes, rx_packets),
         );
     }
 
-    pub fn update_udp_v4_tx(&mut self, key: Key<Ipv4Address>, tx_bytes: usize) {
+    pub fn update_udp_v4_tx(&mut self, key: Key<Ipv4Address>, tx_bytes: usize, tx_packets: usize) {
         Self::update(
             &mut self.stats_udp_v4,
             &mut self.stats_udp_v4_lock,
+            &self.stats_udp_v4_count,
             key,
-            Direction::Tx(tx_bytes),
+            Direction::Tx(tx_bytes, tx_packets),
         );
     }
 
-    pub fn update_udp_v4_rx(&mut self, key: Key<Ipv4Address>, rx_bytes: usize) {
+    pub fn update_udp_v4_rx(&mut self, key: Key<Ipv4Address>, rx_bytes: usize, rx_packets: usize) {
         Self::update(
             &mut self.stats_udp_v4,
             &mut self.stats_udp_v4_lock,
+            &self.stats_udp_v4_count,
             key,
-            Direction::Rx(rx_bytes),
+            Direction::Rx(rx_bytes, rx_packets),
         );
     }
 
-    pub fn update_udp_v6_tx(&mut self, key: Key<Ipv6Address>, tx_bytes: usize) {
+    pub fn update_udp_v6_tx(&mut self, key: Key<Ipv6Address>, tx_bytes: usize, tx_packets: usize) {
         Self::update(
             &mut self.stats_udp_v6,
             &mut self.stats_udp_v6_lock,
+            &self.stats_udp_v6_count,
             key,
-            Direction::Tx(tx_bytes),
+            Direction::Tx(tx_bytes, tx_packets),
         );
     }
 
-    pub fn update_udp_v6_rx(&mut self, key: Key<Ipv6Address>, rx_bytes: usize) {
+    pub fn update_udp_v6_rx(&mut self, key: Key<Ipv6Address>, rx_bytes: usize, rx_packets: usize) {
         Self::update(
             &mut self.stats_udp_v6,
             &mut self.stats_udp_v6_lock,
+            &self.stats_udp_v6_count,
             key,
-            Direction::Rx(rx_bytes),
+            Direction::Rx(rx_bytes, rx_packets),
+        );
+    }
+
+    pub fn update_other_v4_tx(
+        &mut self,
+        protocol: IpProtocol,
+        key: Key<Ipv4Address>,
+        tx_bytes: usize,
+        tx_packets: usize,
+    ) {
+        Self::update(
+            &mut self.stats_other_v4,
+            &mut self.stats_other_v4_lock,
+            &self.stats_other_v4_count,
+            (protocol, key),
+            Direction::Tx(tx_bytes, tx_packets),
+        );
+    }
+
+    pub fn update_other_v4_rx(
+        &mut self,
+        protocol: IpProtocol,
+        key: Key<Ipv4Address>,
+        rx_bytes: usize,
+        rx_packets: usize,
+    ) {
+        Self::update(
+            &mut self.stats_other_v4,
+            &mut self.stats_other_v4_lock,
+            &self.stats_other_v4_count,
+            (protocol, key),
+            Direction::Rx(rx_bytes, rx_packets),
+        );
+    }
+
+    pub fn update_other_v6_tx(
+        &mut self,
+        protocol: IpProtocol,
+        key: Key<Ipv6Address>,
+        tx_bytes: usize,
+        tx_packets: usize,
+    ) {
+        Self::update(
+            &mut self.stats_other_v6,
+            &mut self.stats_other_v6_lock,
+            &self.stats_other_v6_count,
+            (protocol, key),
+            Direction::Tx(tx_bytes, tx_packets),
         );
     }
 
-    fn update<Address: Eq + PartialEq + core::hash::Hash>(
-        map: &mut DeviceHashMap<Key<Address>, Value>,
+    pub fn update_other_v6_rx(
+        &mut self,
+        protocol: IpProtocol,
+        key: Key<Ipv6Address>,
+        rx_bytes: usize,
+        rx_packets: usize,
+    ) {
+        Self::update(
+            &mut self.stats_other_v6,
+            &mut self.stats_other_v6_lock,
+            &self.stats_other_v6_count,
+            (protocol, key),
+            Direction::Rx(rx_bytes, rx_packets),
+        );
+    }
+
+    pub fn update_process_tx(
+        &mut self,
+        process_id: u64,
+        protocol: IpProtocol,
+        tx_bytes: usize,
+        tx_packets: usize,
+    ) {
+        Self::update(
+            &mut self.stats_process,
+            &mut self.stats_process_lock,
+            &self.stats_process_count,
+            ProcessKey {
+                process_id,
+                protocol,
+            },
+            Direction::Tx(tx_bytes, tx_packets),
+        );
+    }
+
+    pub fn update_process_rx(
+        &mut self,
+        process_id: u64,
+        protocol: IpProtocol,
+        rx_bytes: usize,
+        rx_packets: usize,
+    ) {
+        Self::update(
+            &mut self.stats_process,
+            &mut self.stats_process_lock,
+            &self.stats_process_count,
+            ProcessKey {
+                process_id,
+                protocol,
+            },
+            Direction::Rx(rx_bytes, rx_packets),
+        );
+    }
+
+    fn update<K: Eq + PartialEq + core::hash::Hash>(
+        map: &mut DeviceHashMap<K, Value>,
         lock: &mut RwSpinLock,
-        key: Key<Address>,
-        bytes: Direction,
+        count: &AtomicUsize,
+        key: K,
+        direction: Direction,
     ) {
+        let timestamp = wdk::utils::get_system_timestamp_ms();
         let _guard = lock.write_lock();
         if let Some(value) = map.get_mut(&key) {
-            match bytes {
-                Direction::Tx(bytes_count) => value.transmitted_bytes += bytes_count,
-                Direction::Rx(bytes_count) => value.received_bytes += bytes_count,
-            }
+            value.add(direction, timestamp);
         } else {
-            let mut received_bytes = 0;
-            let mut transmitted_bytes = 0;
-            match bytes {
-                Direction::Tx(bytes_count) => transmitted_bytes += bytes_count,
-                Direction::Rx(bytes_count) => received_bytes += bytes_count,
-            }
-            map.insert(
-                key,
-                Value {
-                    received_bytes,
-                    transmitted_bytes,
-                },
-            );
+            let mut value = Value {
+                received_bytes: 0,
+                transmitted_bytes: 0,
+                received_packets: 0,
+                transmitted_packets: 0,
+                first_seen_timestamp: timestamp,
+                last_seen_timestamp: timestamp,
+            };
+            value.add(direction, timestamp);
+            map.insert(key, value);
+            count.store(map.len(), Ordering::Relaxed);
         }
     }
 
-    #[allow(dead_code)]
-    pub fn get_entries_count(&self) -> usize {
-        let mut size = 0;
-        {
-            let values = &self.stats_tcp_v4.values();
-            let _guard = self.stats_tcp_v4_lock.read_lock();
-            size += values.len();
-        }
-        {
-            let values = &self.stats_tcp_v6.values();
-            let _guard = self.stats_tcp_v6_lock.read_lock();
-            size += values.len();
-        }
-        {
-            let values = &self.stats_udp_v4.values();
-            let _guard = self.stats_udp_v4_lock.read_lock();
-            size += values.len();
+    /// Returns the number of entries in each map. Does not take the locks.
+    pub fn get_entries_count(&self) -> BandwidthEntriesCount {
+        BandwidthEntriesCount {
+            tcp_v4: self.stats_tcp_v4_count.load(Ordering::Relaxed),
+            tcp_v6: self.stats_tcp_v6_count.load(Ordering::Relaxed),
+            udp_v4: self.stats_udp_v4_count.load(Ordering::Relaxed),
+            udp_v6: self.stats_udp_v6_count.load(Ordering::Relaxed),
+            other_v4: self.stats_other_v4_count.load(Ordering::Relaxed),
+            other_v6: self.stats_other_v6_count.load(Ordering::Relaxed),
+            process: self.stats_process_count.load(Ordering::Relaxed),
         }
-        {
-            let values = &self.stats_udp_v6.values();
-            let _guard = self.stats_udp_v6_lock.read_lock();
-            size += values.len();
-        }
-
-        return size;
     }
 }
@@ -7,9 +7,10 @@ use core::{
     sync::atomic::{AtomicU64, Ordering},
 };
 use num_derive::FromPrimitive;
+use protocol::info::LogValue;
 use smoltcp::wire::{IpAddress, IpProtocol, Ipv4Address, Ipv6Address};
 
-use crate::connection_map::Key;
+use crate::{connection_map::Key, logger::LogField};
 
 pub static PM_DNS_PORT: u16 = 53;
 pub static PM_SPN_PORT: u16 = 717;
@@ -32,25 +33,37 @@ pub enum Verdict {
     Failed             = 10,
 }
 
-impl Display for Verdict {
+impl Verdict {
     #[rustfmt::skip]
-    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    pub fn as_str(&self) -> &'static str {
         match self {
-            Verdict::Undecided          => write!(f, "Undecided"),
-            Verdict::Undeterminable     => write!(f, "Undeterminable"),
-            Verdict::Accept             => write!(f, "Accept"),
-            Verdict::PermanentAccept    => write!(f, "PermanentAccept"),
-            Verdict::Block              => write!(f, "Block"),
-            Verdict::PermanentBlock     => write!(f, "PermanentBlock"),
-            Verdict::Drop               => write!(f, "Drop"),
-            Verdict::PermanentDrop      => write!(f, "PermanentDrop"),
-            Verdict::RedirectNameServer => write!(f, "RedirectNameServer"),
-            Verdict::RedirectTunnel     => write!(f, "RedirectTunnel"),
-            Verdict::Failed             => write!(f, "Failed"),
+            Verdict::Undecided          => "Undecided",
+            Verdict::Undeterminable     => "Undeterminable",
+            Verdict::Accept             => "Accept",
+            Verdict::PermanentAccept    => "PermanentAccept",
+            Verdict::Block              => "Block",
+            Verdict::PermanentBlock     => "PermanentBlock",
+            Verdict::Drop               => "Drop",
+            Verdict::PermanentDrop      => "PermanentDrop",
+            Verdict::RedirectNameServer => "RedirectNameServer",
+            Verdict::RedirectTunnel     => "RedirectTunnel",
+            Verdict::Failed             => "Failed",
         }
     }
 }
 
+impl Display for Verdict {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl LogField for Verdict {
+    fn log_value(&self) -> LogValue<'_> {
+        LogValue::Str(self.as_str())
+    }
+}
+
 #[allow(dead_code)]
 impl Verdict {
     /// Returns true if the verdict is a redirect.
@@ -69,6 +82,17 @@ impl Verdict {
                 | Verdict::RedirectTunnel
         )
     }
+
+    /// Returns the permanent form of a temporary verdict. Verdicts that are cached by the driver
+    /// must be permanent, a temporary verdict sends every packet of the connection to user space.
+    pub fn to_permanent(self) -> Verdict {
+        match self {
+            Verdict::Accept => Verdict::PermanentAccept,
+            Verdict::Block => Verdict::PermanentBlock,
+            Verdict::Drop => Verdict::PermanentDrop,
+            verdict => verdict,
+        }
+    }
 }
 
 /// Direction of the connection.
@@ -1,10 +1,14 @@
-use core::time::Duration;
+use core::{
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
 
 use crate::{
     connection::{Connection, ConnectionV4, ConnectionV6, RedirectInfo, Verdict},
     connection_map::{ConnectionMap, Key},
 };
 use alloc::{format, string::String, vec::Vec};
+use protocol::info::{ConnectionDumpValueV4, ConnectionDumpValueV6};
 
 use smoltcp::wire::IpProtocol;
 use wdk::rw_spin_lock::RwSpinLock;
@@ -14,6 +18,9 @@ pub struct ConnectionCache {
     connections_v6: ConnectionMap<ConnectionV6>,
     lock_v4: RwSpinLock,
     lock_v6: RwSpinLock,
+    // Copies of the map counts that can be read without taking the locks.
+    count_v4: AtomicUsize,
+    count_v6: AtomicUsize,
 }
 
 impl ConnectionCache {
@@ -23,17 +30,51 @@ impl ConnectionCache {
             connections_v6: ConnectionMap::new(),
             lock_v4: RwSpinLock::default(),
             lock_v6: RwSpinLock::default(),
+            count_v4: AtomicUsize::new(0),
+            count_v6: AtomicUsize::new(0),
         }
     }
 
     pub fn add_connection_v4(&mut self, connection: ConnectionV4) {
         let _guard = self.lock_v4.write_lock();
         self.connections_v4.add(connection);
+        self.count_v4
+            .store(self.connections_v4.get_count(), Ordering::Relaxed);
     }
 
     pub fn add_connection_v6(&mut self, connection: ConnectionV6) {
         let _guard = self.lock_v6.write_lock();
         self.connections_v6.add(connection);
+        self.count_v6
+            .store(self.connections_v6.get_count(), Ordering::Relaxed);
+    }
+
+    /// Adds connections that already have a verdict. Connections that are in the cache only get the new verdict.
+    pub fn load_connections_v4(&mut self, connections: Vec<ConnectionV4>) {
+        let _guard = self.lock_v4.write_lock();
+        for connection in connections {
+            if let Some(conn) = self.connections_v4.get_mut(&connection.get_key()) {
+                conn.verdict = connection.verdict;
+            } else {
+                self.connections_v4.add(connection);
+            }
+        }
+        self.count_v4
+            .store(self.connections_v4.get_count(), Ordering::Relaxed);
+    }
+
+    /// Same as `load_connections_v4` for ipv6 connections.
+    pub fn load_connections_v6(&mut self, connections: Vec<ConnectionV6>) {
+        let _guard = self.lock_v6.write_lock();
+        for connection in connections {
+            if let Some(conn) = self.connections_v6.get_mut(&connection.get_key()) {
+                conn.verdict = connection.verdict;
+            } else {
+                self.connections_v6.add(connection);
+            }
+        }
+        self.count_v6
+            .store(self.connections_v6.get_count(), Ordering::Relaxed);
     }
 
     pub fn update_connection(&mut self, key: Key, verdict: Verdict) -> Option<RedirectInfo> {
@@ -53,6 +94,48 @@ impl ConnectionCache {
         None
     }
 
+    /// Sets the verdict of a connection that is still undecided. Verdicts from the client are not overwritten.
+    pub fn decide_connection(&mut self, key: Key, verdict: Verdict) {
+        if key.is_ipv6() {
+            let _guard = self.lock_v6.write_lock();
+            if let Some(conn) = self.connections_v6.get_mut(&key) {
+                if matches!(conn.verdict, Verdict::Undecided) {
+                    conn.verdict = verdict;
+                }
+            }
+        } else {
+            let _guard = self.lock_v4.write_lock();
+            if let Some(conn) = self.connections_v4.get_mut(&key) {
+                if matches!(conn.verdict, Verdict::Undecided) {
+                    conn.verdict = verdict;
+                }
+            }
+        }
+    }
+
+    /// Updates multiple connections. Each lock is taken only once.
+    /// The result has the same order as `updates`.
+    pub fn update_connections(&mut self, updates: &[(Key, Verdict)]) -> Vec<Option<RedirectInfo>> {
+        let mut redirect_infos = Vec::with_capacity(updates.len());
+        let _guard_v4 = self.lock_v4.write_lock();
+        let _guard_v6 = self.lock_v6.write_lock();
+        for (key, verdict) in updates {
+            let redirect_info = if key.is_ipv6() {
+                self.connections_v6.get_mut(key).and_then(|conn| {
+                    conn.verdict = *verdict;
+                    conn.redirect_info()
+                })
+            } else {
+                self.connections_v4.get_mut(key).and_then(|conn| {
+                    conn.verdict = *verdict;
+                    conn.redirect_info()
+                })
+            };
+            redirect_infos.push(redirect_info);
+        }
+        redirect_infos
+    }
+
     pub fn read_connection_v4<T>(
         &self,
         key: &Key,
@@ -95,10 +178,14 @@ impl ConnectionCache {
         {
             let _guard = self.lock_v4.write_lock();
             self.connections_v4.clean_ended_connections();
+            self.count_v4
+                .store(self.connections_v4.get_count(), Ordering::Relaxed);
         }
         {
             let _guard = self.lock_v6.write_lock();
             self.connections_v6.clean_ended_connections();
+            self.count_v6
+                .store(self.connections_v6.get_count(), Ordering::Relaxed);
         }
     }
 
@@ -106,27 +193,74 @@ impl ConnectionCache {
         {
             let _guard = self.lock_v4.write_lock();
             self.connections_v4.clear();
+            self.count_v4.store(0, Ordering::Relaxed);
         }
         {
             let _guard = self.lock_v6.write_lock();
             self.connections_v6.clear();
+            self.count_v6.store(0, Ordering::Relaxed);
         }
     }
 
-    #[allow(dead_code)]
-    pub fn get_entries_count(&self) -> usize {
-        let mut size = 0;
-        {
-            let _guard = self.lock_v4.read_lock();
-            size += self.connections_v4.get_count();
-        }
+    /// Calls `send` with the ipv4 connections in chunks of about `chunk_size` entries.
+    /// The lock is taken separately for every chunk. Returns the number of connections.
+    pub fn dump_v4(
+        &self,
+        chunk_size: usize,
+        send: impl FnMut(Vec<ConnectionDumpValueV4>),
+    ) -> usize {
+        dump(
+            &self.connections_v4,
+            &self.lock_v4,
+            chunk_size,
+            |conn| ConnectionDumpValueV4 {
+                protocol: u8::from(conn.protocol),
+                local_ip: conn.local_address.0,
+                local_port: conn.local_port,
+                remote_ip: conn.remote_address.0,
+                remote_port: conn.remote_port,
+                direction: conn.get_direction() as u8,
+                verdict: conn.verdict as u8,
+                process_id: conn.process_id,
+                last_accessed_timestamp: conn.get_last_accessed_time(),
+                end_timestamp: conn.get_end_time(),
+            },
+            send,
+        )
+    }
 
-        {
-            let _guard = self.lock_v6.read_lock();
-            size += self.connections_v6.get_count();
-        }
+    /// Same as `dump_v4` for the ipv6 connections.
+    pub fn dump_v6(
+        &self,
+        chunk_size: usize,
+        send: impl FnMut(Vec<ConnectionDumpValueV6>),
+    ) -> usize {
+        dump(
+            &self.connections_v6,
+            &self.lock_v6,
+            chunk_size,
+            |conn| ConnectionDumpValueV6 {
+                protocol: u8::from(conn.protocol),
+                local_ip: conn.local_address.0,
+                local_port: conn.local_port,
+                remote_ip: conn.remote_address.0,
+                remote_port: conn.remote_port,
+                direction: conn.get_direction() as u8,
+                verdict: conn.verdict as u8,
+                process_id: conn.process_id,
+                last_accessed_timestamp: conn.get_last_accessed_time(),
+                end_timestamp: conn.get_end_time(),
+            },
+            send,
+        )
+    }
 
-        return size;
+    /// Returns the number of ipv4 and ipv6 connections. Does not take the locks.
+    pub fn get_entries_count(&self) -> (usize, usize) {
+        (
+            self.count_v4.load(Ordering::Relaxed),
+            self.count_v6.load(Ordering::Relaxed),
+        )
     }
 
     #[allow(dead_code)]
@@ -198,3 +332,38 @@ impl ConnectionCache {
         return info;
     }
 }
+
+fn dump<T: Connection + Clone, V>(
+    map: &ConnectionMap<T>,
+    lock: &RwSpinLock,
+    chunk_size: usize,
+    convert: fn(&T) -> V,
+    mut send: impl FnMut(Vec<V>),
+) -> usize {
+    let ports = {
+        let _guard = lock.read_lock();
+        map.ports()
+    };
+
+    // The cache can change between chunks. Ports that are added after this point are not included.
+    let mut count = 0;
+    let mut remaining = &ports[..];
+    while !remaining.is_empty() {
+        let mut values = Vec::with_capacity(chunk_size);
+        {
+            let _guard = lock.read_lock();
+            while let Some((port, rest)) = remaining.split_first() {
+                if values.len() >= chunk_size {
+                    break;
+                }
+                values.extend(map.get_port(port).iter().map(convert));
+                remaining = rest;
+            }
+        }
+        if !values.is_empty() {
+            count += values.len();
+            send(values);
+        }
+    }
+    count
+}
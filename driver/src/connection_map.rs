@@ -1,8 +1,9 @@
 use core::{fmt::Display, time::Duration};
 
-use crate::connection::Connection;
+use crate::{connection::Connection, logger::LogField};
 use alloc::vec::Vec;
 use hashbrown::HashMap;
+use protocol::info::{decode, LogConnection, LogValue};
 use smoltcp::wire::{IpAddress, IpProtocol};
 
 #[derive(Clone, Copy, PartialEq, PartialOrd, Eq, Ord)]
@@ -63,11 +64,32 @@ impl Key {
     }
 }
 
-pub struct ConnectionMap<T: Connection>(HashMap<(IpProtocol, u16), Vec<T>>);
+/// Converts the address to the type of the protocol crate.
+pub(crate) fn protocol_ip(ip: IpAddress) -> decode::IpAddress {
+    match ip {
+        IpAddress::Ipv4(ip) => decode::IpAddress::V4(ip.0),
+        IpAddress::Ipv6(ip) => decode::IpAddress::V6(ip.0),
+    }
+}
+
+impl LogField for Key {
+    fn log_value(&self) -> LogValue<'_> {
+        LogValue::Connection(LogConnection {
+            protocol: u8::from(self.protocol),
+            local_ip: protocol_ip(self.local_address),
+            local_port: self.local_port,
+            remote_ip: protocol_ip(self.remote_address),
+            remote_port: self.remote_port,
+        })
+    }
+}
+
+// The second value is the number of connections in the map.
+pub struct ConnectionMap<T: Connection>(HashMap<(IpProtocol, u16), Vec<T>>, usize);
 
 impl<T: Connection + Clone> ConnectionMap<T> {
     pub fn new() -> Self {
-        Self(HashMap::new())
+        Self(HashMap::new(), 0)
     }
 
     pub fn add(&mut self, conn: T) {
@@ -77,6 +99,7 @@ impl<T: Connection + Clone> ConnectionMap<T> {
         } else {
             self.0.insert(key, alloc::vec![conn]);
         }
+        self.1 += 1;
     }
 
     pub fn get_mut(&mut self, key: &Key) -> Option<&mut T> {
@@ -137,6 +160,7 @@ impl<T: Connection + Clone> ConnectionMap<T> {
 
     pub fn clear(&mut self) {
         self.0.clear();
+        self.1 = 0;
     }
 
     pub fn clean_ended_connections(&mut self) {
@@ -162,15 +186,24 @@ impl<T: Connection + Clone> ConnectionMap<T> {
             });
         }
         self.0.retain(|_, v| !v.is_empty());
+        self.1 = self.0.values().map(|connections| connections.len()).sum();
     }
 
-    #[allow(dead_code)]
     pub fn get_count(&self) -> usize {
-        let mut count = 0;
-        for conn in self.0.values() {
-            count += conn.len();
+        self.1
+    }
+
+    /// Returns the (protocol, local port) pairs that have connections.
+    pub fn ports(&self) -> Vec<(IpProtocol, u16)> {
+        self.0.keys().copied().collect()
+    }
+
+    /// Returns the connections on the given (protocol, local port) pair.
+    pub fn get_port(&self, port: &(IpProtocol, u16)) -> &[T] {
+        match self.0.get(port) {
+            Some(connections) => connections,
+            None => &[],
         }
-        return count;
     }
 
     pub fn iter(&self) -> hashbrown::hash_map::Iter<'_, (IpProtocol, u16), Vec<T>> {
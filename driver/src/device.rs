@@ -1,6 +1,18 @@
-use alloc::string::String;
+use core::sync::atomic::{AtomicU32, AtomicU64, AtomicU8, Ordering};
+
+use alloc::{format, string::String, vec::Vec};
 use num_traits::FromPrimitive;
-use protocol::{command::CommandType, info::Info};
+use protocol::{
+    command::{Command, OverflowPolicy, ParsedCommand, PAYLOAD_LIMIT_ANY, SUPPORTED_COMMANDS},
+    info::{
+        command_result_info, connection_dump_end_info, connection_dump_v4, connection_dump_v6,
+        handshake_info, memory_stats_info, pending_timeout_info, queue_overflow_info,
+        rule_list_info, verdict_batch_result_info, Info, MemoryStats, ResultCode, Severity,
+        VerdictError, LEGACY_INFO_TYPES, SUPPORTED_INFO_TYPES,
+    },
+    rules::RuleTable,
+    PROTOCOL_VERSION,
+};
 use smoltcp::wire::{IpAddress, IpProtocol, Ipv4Address, Ipv6Address};
 use wdk::{
     driver::Driver,
@@ -12,13 +24,51 @@ use wdk::{
     },
     ioqueue::{self, IOQueue},
     irp_helpers::{ReadRequest, WriteRequest},
+    rw_spin_lock::RwSpinLock,
+    timer::{self, Timer},
 };
 
 use crate::{
-    array_holder::ArrayHolder, bandwidth::Bandwidth, callouts, connection_cache::ConnectionCache,
-    connection_map::Key, dbg, err, id_cache::IdCache, logger, packet_util::Redirect,
+    array_holder::ArrayHolder,
+    bandwidth::Bandwidth,
+    callouts,
+    connection::{ConnectionV4, ConnectionV6, Direction, RedirectInfo, Verdict},
+    connection_cache::ConnectionCache,
+    connection_map::{protocol_ip, Key},
+    dbg, err,
+    id_cache::IdCache,
+    info, logger,
+    packet_util::Redirect,
+    process_info::AnnouncedProcesses,
+    process_policy::{self, ProcessPolicy},
+    rate_limit::{LimitKey, RateLimits, ThrottledPacket},
+    warn,
 };
 
+// Maximum number of connections in one dump info. The cache lock is released between chunks.
+const DUMP_CHUNK_SIZE: usize = 256;
+
+// Longest time between two checks for expired pending packets.
+const PENDING_SWEEP_INTERVAL_MS: u32 = 500;
+
+// Shortest bandwidth push interval. Shorter intervals are raised to this.
+const MIN_BANDWIDTH_PUSH_INTERVAL_MS: u32 = 100;
+
+// How often the packets that wait for their rate limit are checked.
+const RATE_LIMIT_RELEASE_INTERVAL_MS: u32 = 10;
+
+/// Failure of a command. Reported to the client if the command had a request id.
+struct CommandError {
+    code: ResultCode,
+    reason: String,
+}
+
+impl CommandError {
+    fn new(code: ResultCode, reason: String) -> Self {
+        Self { code, reason }
+    }
+}
+
 pub enum Packet {
     PacketLayer(NetBufferList, InjectInfo),
     AleLayer(ClassifyDefer),
@@ -34,6 +84,30 @@ pub struct Device {
     pub(crate) injector: Injector,
     pub(crate) network_allocator: NetworkAllocator,
     pub(crate) bandwidth_stats: Bandwidth,
+    // Sends the bandwidth stats periodically when the client enabled it.
+    bandwidth_push: Timer,
+    // Largest bandwidth stats info in bytes. 0 means no limit. Read by the push timer.
+    bandwidth_max_frame_size: AtomicU32,
+    // Verdicts for new connections that don't need to be sent to the client.
+    rule_table: RuleTable,
+    rule_table_lock: RwSpinLock,
+    pub(crate) process_policy: ProcessPolicy,
+    // Processes that the client got a process info for.
+    pub(crate) announced_processes: AnnouncedProcesses,
+    pub(crate) rate_limits: RateLimits,
+    // Injects the packets that waited for their rate limit. Runs while there are limits.
+    rate_limit_release: Timer,
+    // Pending packets that get no verdict in time get the timeout verdict. 0 disables the timeout.
+    pending_timeout: Timer,
+    // Timeout in ms in the low 32 bits and the timeout verdict above it. Written by the command and
+    // read by the timer, a single value can't be seen half updated.
+    pending_timeout_config: AtomicU64,
+    // `OverflowPolicy` that is used when the packet cache is full. Read by the callouts.
+    overflow_policy: AtomicU8,
+    // Packets that were not pended because the packet cache was full. Reported with a queue overflow info.
+    pending_overflows: AtomicU32,
+    // Info types that the client can decode. Set with the handshake command and read by `read`.
+    client_info_types: AtomicU64,
 }
 
 impl Device {
@@ -50,6 +124,10 @@ impl Device {
             return Err(err);
         }
 
+        if let Err(err) = wdk::utils::set_process_notify(process_policy::process_notify) {
+            return Err(alloc::format!("failed to register process notify: {}", err));
+        }
+
         Ok(Self {
             filter_engine,
             read_leftover: ArrayHolder::default(),
@@ -59,6 +137,19 @@ impl Device {
             injector: Injector::new(),
             network_allocator: NetworkAllocator::new(),
             bandwidth_stats: Bandwidth::new(),
+            bandwidth_push: Timer::new(bandwidth_push_callback),
+            bandwidth_max_frame_size: AtomicU32::new(0),
+            rule_table: RuleTable::new(),
+            rule_table_lock: RwSpinLock::default(),
+            process_policy: ProcessPolicy::new(),
+            announced_processes: AnnouncedProcesses::new(),
+            rate_limits: RateLimits::new(),
+            rate_limit_release: Timer::new(rate_limit_release_callback),
+            pending_timeout: Timer::new(pending_timeout_callback),
+            pending_timeout_config: AtomicU64::new(0),
+            overflow_policy: AtomicU8::new(OverflowPolicy::DropOldest as u8),
+            pending_overflows: AtomicU32::new(0),
+            client_info_types: AtomicU64::new(LEGACY_INFO_TYPES),
         })
     }
 
@@ -89,20 +180,30 @@ impl Device {
             }
         } else {
             // Noting left from before. Wait for next commands.
-            match self.event_queue.wait_and_pop() {
-                Ok(info) => {
-                    self.write_buffer(read_request, info);
-                }
-                Err(ioqueue::Status::Timeout) => {
-                    // Timeout. This will only trigger if pop function is called with timeout.
-                    read_request.timeout();
-                    return;
-                }
-                Err(err) => {
-                    // Queue failed. Send EOF, to notify user-space. Usually happens on rundown.
-                    err!("failed to pop value: {}", err);
-                    read_request.end_of_file();
-                    return;
+            loop {
+                match self.event_queue.wait_and_pop() {
+                    Ok(info) => {
+                        // There is space in the queue again.
+                        self.report_overflows();
+                        // Skip info types that the client can't decode.
+                        if let Some(info) =
+                            info.for_client(self.client_info_types.load(Ordering::Relaxed))
+                        {
+                            self.write_buffer(read_request, info);
+                            break;
+                        }
+                    }
+                    Err(ioqueue::Status::Timeout) => {
+                        // Timeout. This will only trigger if pop function is called with timeout.
+                        read_request.timeout();
+                        return;
+                    }
+                    Err(err) => {
+                        // Queue failed. Send EOF, to notify user-space. Usually happens on rundown.
+                        err!("failed to pop value: {}", err);
+                        read_request.end_of_file();
+                        return;
+                    }
                 }
             }
         }
@@ -111,7 +212,11 @@ impl Device {
         while read_request.free_space() > 5 {
             match self.event_queue.pop() {
                 Ok(info) => {
-                    self.write_buffer(read_request, info);
+                    if let Some(info) =
+                        info.for_client(self.client_info_types.load(Ordering::Relaxed))
+                    {
+                        self.write_buffer(read_request, info);
+                    }
                 }
                 Err(_) => {
                     break;
@@ -123,72 +228,74 @@ impl Device {
 
     // Called when handle.Write is called from user-space.
     pub fn write(&mut self, write_request: &mut WriteRequest) {
-        // Try parsing the command.
-        let mut buffer = write_request.get_buffer();
-        let command = protocol::command::parse_type(buffer);
-        let Some(command) = command else {
-            err!("Unknown command number: {}", buffer[0]);
-            return;
+        // Try parsing the command. It can be wrapped in a request with an id.
+        let request = Command::parse_request(write_request.get_buffer());
+        let result = match request.command {
+            Ok(command) => self.execute(command),
+            Err(err) => Err(CommandError::new(
+                ResultCode::InvalidCommand,
+                format!("failed to parse command: {}", err),
+            )),
         };
-        buffer = &buffer[1..];
 
+        if let Err(err) = &result {
+            err!("{}", err.reason);
+        }
+
+        // Only clients that sent a request id expect a result.
+        if let Some(request_id) = request.id {
+            let info = match result {
+                Ok(()) => command_result_info(request_id, ResultCode::Success, ""),
+                Err(err) => command_result_info(request_id, err.code, &err.reason),
+            };
+            _ = self.event_queue.push(info);
+        }
+    }
+
+    fn execute(&mut self, command: ParsedCommand) -> Result<(), CommandError> {
         let mut _classify_defer = None;
 
         match command {
-            CommandType::Shutdown => {
+            ParsedCommand::Shutdown => {
                 wdk::dbg!("Shutdown command");
                 self.shutdown();
             }
-            CommandType::Verdict => {
-                let verdict = protocol::command::parse_verdict(buffer);
+            ParsedCommand::Verdict(verdict) => {
                 wdk::dbg!("Verdict command");
                 // Received verdict decision for a specific connection.
-                if let Some((key, mut packet)) = self.packet_cache.pop_id(verdict.id) {
-                    if let Some(verdict) = FromPrimitive::from_u8(verdict.verdict) {
-                        dbg!("Verdict received {}: {}", key, verdict);
-                        // Add verdict in the cache.
-                        let redirect_info = self.connection_cache.update_connection(key, verdict);
-
-                        // if verdict.is_permanent() {
-                        //     dbg!(self.logger, "resetting filters {}: {}", key, verdict);
-                        //     _ = self.filter_engine.reset_all_filters();
-                        // }
-
-                        match verdict {
-                            crate::connection::Verdict::Accept
-                            | crate::connection::Verdict::PermanentAccept => {
-                                if let Err(err) = self.inject_packet(packet, false) {
-                                    err!("failed to inject packet: {}", err);
-                                } else {
-                                    dbg!("packet injected: {}", key);
-                                }
-                            }
-                            crate::connection::Verdict::RedirectNameServer
-                            | crate::connection::Verdict::RedirectTunnel => {
-                                if let Some(redirect_info) = redirect_info {
-                                    if let Err(err) = packet.redirect(redirect_info) {
-                                        err!("failed to redirect packet: {}", err);
-                                    }
-                                    if let Err(err) = self.inject_packet(packet, false) {
-                                        err!("failed to inject packet: {}", err);
-                                    }
-                                }
-                            }
-                            _ => {
-                                if let Err(err) = self.inject_packet(packet, true) {
-                                    err!("failed to inject packet: {}", err);
-                                }
-                            }
-                        }
-                    };
-                } else {
+                let (id, value) = (verdict.id, verdict.verdict);
+                // Check the verdict first, so the packet stays in the cache if it's invalid.
+                let Some(verdict) = Verdict::from_u8(value) else {
+                    return Err(CommandError::new(
+                        ResultCode::InvalidVerdict,
+                        format!("invalid verdict value: {}", value),
+                    ));
+                };
+                let Some((key, packet)) = self.packet_cache.pop_id(id) else {
                     // Id was not in the packet cache.
-                    let id = verdict.id;
-                    err!("Verdict invalid id: {}", id);
+                    return Err(CommandError::new(
+                        ResultCode::UnknownId,
+                        format!("Verdict invalid id: {}", id),
+                    ));
+                };
+
+                dbg!(key = key, verdict = verdict; "Verdict received");
+                // Add verdict in the cache.
+                let redirect_info = self.connection_cache.update_connection(key, verdict);
+
+                // if verdict.is_permanent() {
+                //     dbg!(self.logger, "resetting filters {}: {}", key, verdict);
+                //     _ = self.filter_engine.reset_all_filters();
+                // }
+
+                if let Err(err) = self.apply_verdict(key, packet, verdict, redirect_info) {
+                    return Err(CommandError::new(
+                        ResultCode::InjectFailed,
+                        format!("failed to inject packet: {}", err),
+                    ));
                 }
             }
-            CommandType::UpdateV4 => {
-                let update = protocol::command::parse_update_v4(buffer);
+            ParsedCommand::UpdateV4(update) => {
                 // Build the new action.
                 if let Some(verdict) = FromPrimitive::from_u8(update.verdict) {
                     // Update with new action.
@@ -208,11 +315,13 @@ impl Device {
                         verdict,
                     );
                 } else {
-                    err!("invalid verdict value: {}", update.verdict);
+                    return Err(CommandError::new(
+                        ResultCode::InvalidVerdict,
+                        format!("invalid verdict value: {}", update.verdict),
+                    ));
                 }
             }
-            CommandType::UpdateV6 => {
-                let update = protocol::command::parse_update_v6(buffer);
+            ParsedCommand::UpdateV6(update) => {
                 // Build the new action.
                 if let Some(verdict) = FromPrimitive::from_u8(update.verdict) {
                     // Update with new action.
@@ -232,66 +341,395 @@ impl Device {
                         verdict,
                     );
                 } else {
-                    err!("invalid verdict value: {}", update.verdict);
+                    return Err(CommandError::new(
+                        ResultCode::InvalidVerdict,
+                        format!("invalid verdict value: {}", update.verdict),
+                    ));
                 }
             }
-            CommandType::ClearCache => {
+            ParsedCommand::ClearCache => {
                 wdk::dbg!("ClearCache command");
                 self.connection_cache.clear();
                 if let Err(err) = self.filter_engine.reset_all_filters() {
-                    err!("failed to reset filters: {}", err);
+                    return Err(CommandError::new(
+                        ResultCode::Failed,
+                        format!("failed to reset filters: {}", err),
+                    ));
                 }
             }
-            CommandType::GetLogs => {
+            ParsedCommand::GetLogs => {
                 wdk::dbg!("GetLogs command");
                 let lines_vec = logger::flush();
                 for line in lines_vec {
                     let _ = self.event_queue.push(line);
                 }
             }
-            CommandType::GetBandwidthStats => {
+            ParsedCommand::GetBandwidthStats => {
                 wdk::dbg!("GetBandwidthStats command");
-                let stats = self.bandwidth_stats.get_all_updates_tcp_v4();
-                if let Some(stats) = stats {
-                    _ = self.event_queue.push(stats);
+                self.push_bandwidth_stats();
+            }
+            ParsedCommand::PrintMemoryStats => {
+                wdk::dbg!("PrintMemoryStats command");
+                _ = self
+                    .event_queue
+                    .push(memory_stats_info(self.memory_stats()));
+            }
+            ParsedCommand::CleanEndedConnections => {
+                wdk::dbg!("CleanEndedConnections command");
+                self.connection_cache.clean_ended_connections();
+            }
+            ParsedCommand::DumpConnections => {
+                wdk::dbg!("DumpConnections command");
+                let event_queue = &self.event_queue;
+                let mut count = self.connection_cache.dump_v4(DUMP_CHUNK_SIZE, |values| {
+                    _ = event_queue.push(connection_dump_v4(values));
+                });
+                count += self.connection_cache.dump_v6(DUMP_CHUNK_SIZE, |values| {
+                    _ = event_queue.push(connection_dump_v6(values));
+                });
+                _ = self
+                    .event_queue
+                    .push(connection_dump_end_info(count as u32));
+            }
+            ParsedCommand::LoadVerdicts(load) => {
+                wdk::dbg!("LoadVerdicts command");
+                // Check all entries first, nothing is loaded if one of them is invalid.
+                let mut connections_v4 = Vec::with_capacity(load.v4.len());
+                for entry in load.v4 {
+                    let (direction, verdict) =
+                        parse_loaded_verdict(entry.direction, entry.verdict)?;
+                    let key = Key {
+                        protocol: IpProtocol::from(entry.protocol),
+                        local_address: IpAddress::Ipv4(Ipv4Address::from_bytes(
+                            &entry.local_address,
+                        )),
+                        local_port: entry.local_port,
+                        remote_address: IpAddress::Ipv4(Ipv4Address::from_bytes(
+                            &entry.remote_address,
+                        )),
+                        remote_port: entry.remote_port,
+                    };
+                    let mut conn = ConnectionV4::from_key(&key, entry.process_id, direction)
+                        .map_err(|err| CommandError::new(ResultCode::InvalidCommand, err))?;
+                    conn.verdict = verdict;
+                    connections_v4.push(conn);
+                }
+
+                let mut connections_v6 = Vec::with_capacity(load.v6.len());
+                for entry in load.v6 {
+                    let (direction, verdict) =
+                        parse_loaded_verdict(entry.direction, entry.verdict)?;
+                    let key = Key {
+                        protocol: IpProtocol::from(entry.protocol),
+                        local_address: IpAddress::Ipv6(Ipv6Address::from_bytes(
+                            &entry.local_address,
+                        )),
+                        local_port: entry.local_port,
+                        remote_address: IpAddress::Ipv6(Ipv6Address::from_bytes(
+                            &entry.remote_address,
+                        )),
+                        remote_port: entry.remote_port,
+                    };
+                    let mut conn = ConnectionV6::from_key(&key, entry.process_id, direction)
+                        .map_err(|err| CommandError::new(ResultCode::InvalidCommand, err))?;
+                    conn.verdict = verdict;
+                    connections_v6.push(conn);
+                }
+
+                info!(
+                    "Loading {} ipv4 and {} ipv6 connection verdicts",
+                    connections_v4.len(),
+                    connections_v6.len()
+                );
+                self.connection_cache.load_connections_v4(connections_v4);
+                self.connection_cache.load_connections_v6(connections_v6);
+            }
+            ParsedCommand::AddRule(rule) => {
+                wdk::dbg!("AddRule command");
+                let (id, verdict) = (rule.id, rule.verdict);
+                if parse_policy_verdict(verdict)?.is_none() {
+                    return Err(CommandError::new(
+                        ResultCode::InvalidVerdict,
+                        format!("invalid verdict value: {}", verdict),
+                    ));
+                }
+                let _guard = self.rule_table_lock.write_lock();
+                if let Err(err) = self.rule_table.add(rule) {
+                    return Err(CommandError::new(
+                        ResultCode::InvalidCommand,
+                        format!("invalid rule {}: {}", id, err),
+                    ));
+                }
+            }
+            ParsedCommand::RemoveRule(remove) => {
+                wdk::dbg!("RemoveRule command");
+                let id = remove.id;
+                let _guard = self.rule_table_lock.write_lock();
+                if !self.rule_table.remove(id) {
+                    return Err(CommandError::new(
+                        ResultCode::Failed,
+                        format!("unknown rule id: {}", id),
+                    ));
+                }
+            }
+            ParsedCommand::ListRules => {
+                wdk::dbg!("ListRules command");
+                let info = {
+                    let _guard = self.rule_table_lock.read_lock();
+                    rule_list_info(self.rule_table.rules())
+                };
+                _ = self.event_queue.push(info);
+            }
+            ParsedCommand::SetProcessVerdict(process) => {
+                wdk::dbg!("SetProcessVerdict command");
+                // The verdict is cached for the connections of the process.
+                let verdict = parse_policy_verdict(process.verdict)?.map(Verdict::to_permanent);
+                self.process_policy.set_process(process.process_id, verdict);
+            }
+            ParsedCommand::SetPathVerdict(path) => {
+                wdk::dbg!("SetPathVerdict command");
+                let verdict = parse_policy_verdict(path.verdict)?.map(Verdict::to_permanent);
+                self.process_policy.set_path(&path.path, verdict);
+            }
+            ParsedCommand::SetPendingTimeout(timeout) => {
+                wdk::dbg!("SetPendingTimeout command");
+                let timeout_ms = timeout.timeout_ms;
+                if timeout_ms == 0 {
+                    self.pending_timeout.stop();
+                    self.pending_timeout_config.store(0, Ordering::Relaxed);
+                    return Ok(());
+                }
+                let Some(verdict) = parse_policy_verdict(timeout.verdict)? else {
+                    return Err(CommandError::new(
+                        ResultCode::InvalidVerdict,
+                        format!("invalid timeout verdict value: {}", timeout.verdict),
+                    ));
+                };
+                let config = ((verdict as u64) << 32) | timeout_ms as u64;
+                self.pending_timeout_config.store(config, Ordering::Relaxed);
+                if let Err(err) = self
+                    .pending_timeout
+                    .start(timeout_ms.min(PENDING_SWEEP_INTERVAL_MS))
+                {
+                    return Err(CommandError::new(ResultCode::Failed, err));
+                }
+            }
+            ParsedCommand::SetQueueLimits(limits) => {
+                wdk::dbg!("SetQueueLimits command");
+                let Some(overflow_policy) = OverflowPolicy::from_u8(limits.overflow_policy) else {
+                    return Err(CommandError::new(
+                        ResultCode::InvalidCommand,
+                        format!("invalid overflow policy: {}", limits.overflow_policy),
+                    ));
+                };
+                self.overflow_policy
+                    .store(overflow_policy as u8, Ordering::Relaxed);
+                self.packet_cache
+                    .set_max_len(limits.max_pending_packets as usize);
+                self.event_queue.set_max_len(limits.max_events as usize);
+            }
+            ParsedCommand::SetBandwidthPush(push) => {
+                wdk::dbg!("SetBandwidthPush command");
+                let (interval_ms, max_frame_size) = (push.interval_ms, push.max_frame_size);
+                if interval_ms > timer::MAX_PERIOD_MS {
+                    return Err(CommandError::new(
+                        ResultCode::InvalidCommand,
+                        format!("bandwidth push interval too long: {} ms", interval_ms),
+                    ));
+                }
+                self.bandwidth_max_frame_size
+                    .store(max_frame_size, Ordering::Relaxed);
+                if interval_ms == 0 {
+                    self.bandwidth_push.stop();
+                } else if let Err(err) = self
+                    .bandwidth_push
+                    .start(interval_ms.max(MIN_BANDWIDTH_PUSH_INTERVAL_MS))
+                {
+                    return Err(CommandError::new(ResultCode::Failed, err));
+                }
+            }
+            ParsedCommand::SetProcessRateLimit(limit) => {
+                wdk::dbg!("SetProcessRateLimit command");
+                self.set_rate_limit(LimitKey::Process(limit.process_id), limit.bytes_per_second);
+            }
+            ParsedCommand::SetConnectionRateLimitV4(limit) => {
+                wdk::dbg!("SetConnectionRateLimitV4 command");
+                let key = Key {
+                    protocol: IpProtocol::from(limit.protocol),
+                    local_address: IpAddress::Ipv4(Ipv4Address::from_bytes(&limit.local_address)),
+                    local_port: limit.local_port,
+                    remote_address: IpAddress::Ipv4(Ipv4Address::from_bytes(&limit.remote_address)),
+                    remote_port: limit.remote_port,
+                };
+                self.set_rate_limit(LimitKey::Connection(key), limit.bytes_per_second);
+            }
+            ParsedCommand::SetConnectionRateLimitV6(limit) => {
+                wdk::dbg!("SetConnectionRateLimitV6 command");
+                let key = Key {
+                    protocol: IpProtocol::from(limit.protocol),
+                    local_address: IpAddress::Ipv6(Ipv6Address::from_bytes(&limit.local_address)),
+                    local_port: limit.local_port,
+                    remote_address: IpAddress::Ipv6(Ipv6Address::from_bytes(&limit.remote_address)),
+                    remote_port: limit.remote_port,
+                };
+                self.set_rate_limit(LimitKey::Connection(key), limit.bytes_per_second);
+            }
+            ParsedCommand::Handshake(handshake) => {
+                let (version, info_types) = (handshake.version, handshake.info_types);
+                info!(
+                    "Handshake: client protocol version {}, driver protocol version {}",
+                    version, PROTOCOL_VERSION
+                );
+                self.client_info_types.store(info_types, Ordering::Relaxed);
+                // A new client doesn't know the processes of the previous one.
+                self.announced_processes.clear();
+                _ = self.event_queue.push(handshake_info(
+                    PROTOCOL_VERSION,
+                    SUPPORTED_COMMANDS,
+                    SUPPORTED_INFO_TYPES,
+                ));
+            }
+            ParsedCommand::SetLogLevel(level) => {
+                let severity = level.severity;
+                let Some(severity) = Severity::from_u8(severity) else {
+                    return Err(CommandError::new(
+                        ResultCode::InvalidCommand,
+                        format!("invalid severity: {}", severity),
+                    ));
+                };
+                logger::set_log_level(severity);
+                info!("Log level: {:?}", severity);
+            }
+            ParsedCommand::SetLogStreaming(streaming) => {
+                let max_lines_per_second = streaming.max_lines_per_second;
+                if max_lines_per_second > 0 {
+                    // Send the buffered lines first to keep the order.
+                    for line in logger::flush() {
+                        _ = self.event_queue.push(line);
+                    }
+                }
+                logger::set_streaming(max_lines_per_second);
+                info!("Log streaming: {} lines per second", max_lines_per_second);
+            }
+            ParsedCommand::SetPayloadLimit(limit) => {
+                let (protocol, direction, max_length) =
+                    (limit.protocol, limit.direction, limit.max_length);
+                if direction > Direction::Inbound as u8 && direction != PAYLOAD_LIMIT_ANY {
+                    return Err(CommandError::new(
+                        ResultCode::InvalidCommand,
+                        format!("invalid direction: {}", direction),
+                    ));
+                }
+                info!(
+                    "Payload limit: protocol {} direction {} max length {}",
+                    protocol, direction, max_length
+                );
+                self.packet_cache
+                    .set_payload_limit(protocol, direction, max_length);
+            }
+            ParsedCommand::VerdictBatch(verdicts) => {
+                wdk::dbg!("VerdictBatch command");
+                let mut failed = Vec::new();
+
+                // Invalid verdicts are reported and their packets stay in the cache.
+                let mut ids = Vec::with_capacity(verdicts.len());
+                let mut values: Vec<Verdict> = Vec::with_capacity(verdicts.len());
+                for entry in verdicts {
+                    let id = entry.id;
+                    match FromPrimitive::from_u8(entry.verdict) {
+                        Some(verdict) => {
+                            ids.push(id);
+                            values.push(verdict);
+                        }
+                        None => failed.push((id, VerdictError::InvalidVerdict)),
+                    }
                 }
 
-                let stats = self.bandwidth_stats.get_all_updates_tcp_v6();
-                if let Some(stats) = stats {
-                    _ = self.event_queue.push(stats);
+                let mut updates = Vec::with_capacity(ids.len());
+                let mut packets = Vec::with_capacity(ids.len());
+                let popped = self.packet_cache.pop_ids(&ids);
+                for ((id, verdict), value) in ids.into_iter().zip(values).zip(popped) {
+                    if let Some((key, packet)) = value {
+                        updates.push((key, verdict));
+                        packets.push((id, packet));
+                    } else {
+                        failed.push((id, VerdictError::UnknownId));
+                    }
                 }
 
-                let stats = self.bandwidth_stats.get_all_updates_udp_v4();
-                if let Some(stats) = stats {
-                    _ = self.event_queue.push(stats);
+                let redirect_infos = self.connection_cache.update_connections(&updates);
+                for (((key, verdict), (id, packet)), redirect_info) in
+                    updates.into_iter().zip(packets).zip(redirect_infos)
+                {
+                    dbg!(key = key, verdict = verdict; "Verdict received");
+                    if let Err(err) = self.apply_verdict(key, packet, verdict, redirect_info) {
+                        err!("failed to inject packet {}: {}", id, err);
+                        failed.push((id, VerdictError::InjectFailed));
+                    }
                 }
 
-                let stats = self.bandwidth_stats.get_all_updates_udp_v6();
-                if let Some(stats) = stats {
-                    _ = self.event_queue.push(stats);
+                _ = self.event_queue.push(verdict_batch_result_info(&failed));
+            }
+        }
+        Ok(())
+    }
+
+    /// Injects, redirects or drops a pending packet depending on the verdict.
+    fn apply_verdict(
+        &mut self,
+        key: Key,
+        mut packet: Packet,
+        verdict: Verdict,
+        redirect_info: Option<RedirectInfo>,
+    ) -> Result<(), String> {
+        match verdict {
+            Verdict::Accept | Verdict::PermanentAccept => {
+                self.inject_packet(packet, false)?;
+                dbg!(key = key; "packet injected");
+            }
+            Verdict::RedirectNameServer | Verdict::RedirectTunnel => {
+                if let Some(redirect_info) = redirect_info {
+                    if let Err(err) = packet.redirect(redirect_info) {
+                        err!("failed to redirect packet: {}", err);
+                    }
+                    self.inject_packet(packet, false)?;
                 }
             }
-            CommandType::PrintMemoryStats => {
-                // Getting the information takes a long time and interferes with the callouts causing the device to crash.
-                // TODO(vladimir): Make more optimized version
-                // info!(
-                //     "Packet cache: {} entries",
-                //     self.packet_cache.get_entries_count()
-                // );
-                // info!(
-                //     "BandwidthStats cache: {} entries",
-                //     self.bandwidth_stats.get_entries_count()
-                // );
-                // info!(
-                //     "Connection cache: {} entries\n {}",
-                //     self.connection_cache.get_entries_count(),
-                //     self.connection_cache.get_full_cache_info()
-                // );
-            }
-            CommandType::CleanEndedConnections => {
-                wdk::dbg!("CleanEndedConnections command");
-                self.connection_cache.clean_ended_connections();
+            Verdict::Block | Verdict::PermanentBlock => {
+                if let Packet::PacketLayer(nbl, inject_info) = &packet {
+                    if let Some(data) = nbl.get_data() {
+                        self.send_reject(data, inject_info);
+                    }
+                }
+                self.inject_packet(packet, true)?;
             }
+            _ => {
+                self.inject_packet(packet, true)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends a TCP reset or an ICMP port unreachable back to the sender of a blocked packet.
+    /// `packet` is the start of the blocked packet, see `protocol::reject::REJECT_INPUT_LEN`.
+    pub(crate) fn send_reject(&mut self, packet: &[u8], inject_info: &InjectInfo) {
+        let Some(reply) = protocol::reject::build_reject_packet(packet) else {
+            return;
+        };
+        let nbl = match NetBufferList::from_data(reply, &self.network_allocator) {
+            Ok(nbl) => nbl,
+            Err(err) => {
+                err!("failed to allocate reject packet: {}", err);
+                return;
+            }
+        };
+        // The reply goes in the opposite direction of the blocked packet.
+        let reply_info = InjectInfo {
+            inbound: !inject_info.inbound,
+            ..*inject_info
+        };
+        if let Err(err) = self.injector.inject_net_buffer_list(nbl, reply_info) {
+            err!("failed to inject reject packet: {}", err);
         }
     }
 
@@ -319,10 +757,223 @@ impl Device {
             }
         }
     }
+
+    /// Sets or removes a rate limit. Packets that waited for a removed limit are injected.
+    fn set_rate_limit(&mut self, key: LimitKey, bytes_per_second: u64) {
+        let released = self.rate_limits.set(key, bytes_per_second);
+        self.inject_throttled_packets(released);
+        if self.rate_limits.is_empty() {
+            self.rate_limit_release.stop();
+        } else if let Err(err) = self
+            .rate_limit_release
+            .start(RATE_LIMIT_RELEASE_INTERVAL_MS)
+        {
+            err!("failed to start rate limit timer: {}", err);
+        }
+    }
+
+    /// Injects packets that waited for their rate limit. They are not checked by the callouts again.
+    pub(crate) fn inject_throttled_packets(&mut self, packets: Vec<ThrottledPacket>) {
+        for (nbl, inject_info) in packets {
+            if let Err(err) = self.injector.inject_net_buffer_list(nbl, inject_info) {
+                err!("failed to inject throttled packet: {}", err);
+            }
+        }
+    }
+
+    /// Queues the bandwidth stats that were collected since the last call. Called from the
+    /// bandwidth push timer at DISPATCH_LEVEL too.
+    fn push_bandwidth_stats(&mut self) {
+        let max_frame_size = self.bandwidth_max_frame_size.load(Ordering::Relaxed) as usize;
+        let stats = [
+            self.bandwidth_stats.get_all_updates_tcp_v4(max_frame_size),
+            self.bandwidth_stats.get_all_updates_tcp_v6(max_frame_size),
+            self.bandwidth_stats.get_all_updates_udp_v4(max_frame_size),
+            self.bandwidth_stats.get_all_updates_udp_v6(max_frame_size),
+            self.bandwidth_stats
+                .get_all_updates_other_v4(max_frame_size),
+            self.bandwidth_stats
+                .get_all_updates_other_v6(max_frame_size),
+            self.bandwidth_stats.get_all_updates_process(max_frame_size),
+        ];
+        for info in stats.into_iter().flatten() {
+            _ = self.event_queue.push(info);
+        }
+    }
+
+    /// Applies the timeout verdict to the pending packets that waited longer than the timeout
+    /// and reports their ids to the client. Called from the timer at DISPATCH_LEVEL.
+    fn expire_pending_packets(&mut self) {
+        let config = self.pending_timeout_config.load(Ordering::Relaxed);
+        let timeout_ms = config as u32;
+        if timeout_ms == 0 {
+            return;
+        }
+        let Some(verdict) = Verdict::from_u8((config >> 32) as u8) else {
+            return;
+        };
+        let expired = self.packet_cache.pop_expired(timeout_ms as u64);
+        if expired.is_empty() {
+            return;
+        }
+
+        let mut ids = Vec::with_capacity(expired.len());
+        for (id, (key, packet)) in expired {
+            // Later packets of the connection don't wait for the client again.
+            self.connection_cache
+                .decide_connection(key, verdict.to_permanent());
+            if let Err(err) = self.apply_verdict(key, packet, verdict, None) {
+                err!("failed to apply timeout verdict: {}", err);
+            }
+            ids.push(id);
+        }
+        warn!("{} pending packets timed out: {}", ids.len(), verdict);
+        _ = self
+            .event_queue
+            .push(pending_timeout_info(verdict as u8, &ids));
+    }
+
+    /// Checks the packet cache limit before a packet is pended. Returns the verdict for the packet
+    /// if it should not be pended. With `DropOldest` the oldest pending packet is dropped instead.
+    /// Packets that are pended at the same time on other processors can exceed the limit.
+    pub(crate) fn pending_overflow_verdict(&mut self) -> Option<Verdict> {
+        if !self.packet_cache.is_full() {
+            return None;
+        }
+        self.pending_overflows.fetch_add(1, Ordering::Relaxed);
+        let overflow_policy =
+            OverflowPolicy::from_u8(self.overflow_policy.load(Ordering::Relaxed))?;
+        match overflow_policy {
+            OverflowPolicy::DropOldest => {
+                if let Some((id, (_, packet))) = self.packet_cache.pop_oldest() {
+                    dbg!("dropping oldest pending packet: {}", id);
+                    if let Err(err) = self.inject_packet(packet, true) {
+                        err!("failed to drop pending packet: {}", err);
+                    }
+                }
+                None
+            }
+            OverflowPolicy::FailOpen => Some(Verdict::Accept),
+            OverflowPolicy::FailClosed => Some(Verdict::Block),
+        }
+    }
+
+    /// Queues a queue overflow info if packets or infos were dropped since the last report.
+    fn report_overflows(&mut self) {
+        let pending_packets = self.pending_overflows.swap(0, Ordering::Relaxed);
+        let events = self.event_queue.take_dropped() as u32;
+        if pending_packets > 0 || events > 0 {
+            warn!(
+                "queue overflow: {} pending packets, {} events",
+                pending_packets, events
+            );
+            _ = self
+                .event_queue
+                .push(queue_overflow_info(pending_packets, events));
+        }
+    }
+
+    /// Returns the verdict of the first rule that matches the new connection.
+    pub(crate) fn find_rule_verdict(&self, key: &Key, direction: Direction) -> Option<Verdict> {
+        let _guard = self.rule_table_lock.read_lock();
+        let rule = self.rule_table.find(
+            u8::from(key.protocol),
+            direction as u8,
+            &protocol_ip(key.remote_address),
+            key.remote_port,
+        )?;
+        // The verdict is cached for the connection.
+        Verdict::from_u8(rule.verdict).map(Verdict::to_permanent)
+    }
+
+    /// Collects the memory stats. Only reads counters, none of the cache locks are taken.
+    fn memory_stats(&self) -> MemoryStats {
+        let (connections_v4, connections_v6) = self.connection_cache.get_entries_count();
+        let bandwidth = self.bandwidth_stats.get_entries_count();
+        let allocator = wdk::allocator::get_stats();
+        MemoryStats {
+            timestamp: wdk::utils::get_system_timestamp_ms(),
+            id_cache_entries: self.packet_cache.get_entries_count() as u64,
+            connections_v4: connections_v4 as u64,
+            connections_v6: connections_v6 as u64,
+            bandwidth_tcp_v4: bandwidth.tcp_v4 as u64,
+            bandwidth_tcp_v6: bandwidth.tcp_v6 as u64,
+            bandwidth_udp_v4: bandwidth.udp_v4 as u64,
+            bandwidth_udp_v6: bandwidth.udp_v6 as u64,
+            event_queue_depth: self.event_queue.count() as u64,
+            read_leftover_bytes: self.read_leftover.size() as u64,
+            allocated_bytes: allocator.allocated_bytes as u64,
+            allocation_count: allocator.allocation_count as u64,
+            total_allocations: allocator.total_allocations as u64,
+            bandwidth_process: bandwidth.process as u64,
+            bandwidth_other_v4: bandwidth.other_v4 as u64,
+            bandwidth_other_v6: bandwidth.other_v6 as u64,
+        }
+    }
+}
+
+/// Checks the direction and verdict of a `LoadVerdicts` entry.
+/// `Undecided` is rejected, it would make the callouts wait for a verdict that is never sent.
+fn parse_loaded_verdict(direction: u8, verdict: u8) -> Result<(Direction, Verdict), CommandError> {
+    let Some(direction) = Direction::from_u8(direction) else {
+        return Err(CommandError::new(
+            ResultCode::InvalidCommand,
+            format!("invalid direction value: {}", direction),
+        ));
+    };
+    match Verdict::from_u8(verdict) {
+        Some(Verdict::Undecided) | None => Err(CommandError::new(
+            ResultCode::InvalidVerdict,
+            format!("invalid verdict value: {}", verdict),
+        )),
+        Some(verdict) => Ok((direction, verdict)),
+    }
+}
+
+/// Checks the verdict of a rule or process policy command. `Undecided` is returned as `None`,
+/// it removes a process policy entry. Redirects are rejected, they need the redirect info of a connection.
+fn parse_policy_verdict(verdict: u8) -> Result<Option<Verdict>, CommandError> {
+    match Verdict::from_u8(verdict) {
+        Some(Verdict::Undecided) => Ok(None),
+        Some(Verdict::RedirectNameServer | Verdict::RedirectTunnel) | None => {
+            Err(CommandError::new(
+                ResultCode::InvalidVerdict,
+                format!("invalid verdict value: {}", verdict),
+            ))
+        }
+        Some(verdict) => Ok(Some(verdict)),
+    }
+}
+
+fn pending_timeout_callback() {
+    if let Some(device) = crate::entry::get_device() {
+        device.expire_pending_packets();
+    }
+}
+
+fn bandwidth_push_callback() {
+    if let Some(device) = crate::entry::get_device() {
+        device.push_bandwidth_stats();
+    }
+}
+
+fn rate_limit_release_callback() {
+    if let Some(device) = crate::entry::get_device() {
+        let packets = device.rate_limits.pop_ready();
+        device.inject_throttled_packets(packets);
+    }
 }
 
 impl Drop for Device {
     fn drop(&mut self) {
+        // The timer callbacks use the device.
+        self.pending_timeout.stop();
+        self.bandwidth_push.stop();
+        self.rate_limit_release.stop();
+        // Waits for running calls, the callback uses the device.
+        _ = wdk::utils::remove_process_notify(process_policy::process_notify);
+        // The logger must not push to the event queue of a dropped device.
+        logger::set_streaming(0);
         _ = logger::flush();
         // dbg!("Device Context drop called.");
     }
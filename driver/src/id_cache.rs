@@ -1,58 +1,163 @@
-use alloc::collections::VecDeque;
-use protocol::info::Info;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use alloc::vec::Vec;
+use protocol::{command::PAYLOAD_LIMIT_ANY, id_queue::IdQueue, info::Info};
 use smoltcp::wire::{IpAddress, IpProtocol};
 use wdk::rw_spin_lock::RwSpinLock;
 
 use crate::{connection::Direction, connection_map::Key, device::Packet};
 
-struct Entry<T> {
-    value: T,
-    id: u64,
+/// Maximum payload length copied into connection infos, like the pcap snaplen.
+/// The most specific limit for the protocol and direction is used.
+struct PayloadLimits {
+    // (protocol, direction, max_length). PAYLOAD_LIMIT_ANY matches every value.
+    limits: Vec<(u8, u8, u32)>,
+}
+
+impl PayloadLimits {
+    fn set(&mut self, protocol: u8, direction: u8, max_length: u32) {
+        let existing = self
+            .limits
+            .iter_mut()
+            .find(|(p, d, _)| *p == protocol && *d == direction);
+        if let Some(limit) = existing {
+            limit.2 = max_length;
+        } else {
+            self.limits.push((protocol, direction, max_length));
+        }
+    }
+
+    fn get(&self, protocol: u8, direction: u8) -> usize {
+        let mut best: Option<(u8, u32)> = None;
+        for &(p, d, max_length) in &self.limits {
+            if (p != protocol && p != PAYLOAD_LIMIT_ANY)
+                || (d != direction && d != PAYLOAD_LIMIT_ANY)
+            {
+                continue;
+            }
+            // Protocol and direction: 3, only protocol: 2, only direction: 1, any: 0
+            let score = (p == protocol) as u8 * 2 + (d == direction) as u8;
+            if !matches!(best, Some((best_score, _)) if best_score >= score) {
+                best = Some((score, max_length));
+            }
+        }
+        match best {
+            Some((_, max_length)) => max_length as usize,
+            None => usize::MAX,
+        }
+    }
 }
 
 pub struct IdCache {
-    values: VecDeque<Entry<(Key, Packet)>>,
+    values: IdQueue<(Key, Packet)>,
     lock: RwSpinLock,
-    next_id: u64,
+    payload_limits: PayloadLimits,
+    // Maximum number of pending packets, 0 for no limit. Checked by the caller before pushing.
+    max_len: AtomicUsize,
+    // Copy of the values length that can be read without taking the lock.
+    count: AtomicUsize,
 }
 
 impl IdCache {
     pub fn new() -> Self {
         Self {
-            values: VecDeque::with_capacity(1000),
+            values: IdQueue::with_capacity(1000),
             lock: RwSpinLock::default(),
-            next_id: 1, // 0 is invalid id
+            payload_limits: PayloadLimits { limits: Vec::new() },
+            max_len: AtomicUsize::new(0),
+            count: AtomicUsize::new(0),
         }
     }
 
+    /// Sets the maximum payload length for infos of new pending packets.
+    pub fn set_payload_limit(&mut self, protocol: u8, direction: u8, max_length: u32) {
+        let _guard = self.lock.write_lock();
+        self.payload_limits.set(protocol, direction, max_length);
+    }
+
+    /// Sets the maximum number of pending packets. 0 removes the limit. Packets already in the cache are kept.
+    pub fn set_max_len(&self, max_len: usize) {
+        self.max_len.store(max_len, Ordering::Relaxed);
+    }
+
+    /// Returns true if the cache has reached the limit. Does not take the lock.
+    pub fn is_full(&self) -> bool {
+        let max_len = self.max_len.load(Ordering::Relaxed);
+        max_len != 0 && self.count.load(Ordering::Relaxed) >= max_len
+    }
+
     pub fn push(
         &mut self,
         value: (Key, Packet),
         process_id: u64,
         direction: Direction,
         ale_layer: bool,
+        interface_index: u32,
+        sub_interface_index: u32,
     ) -> Option<Info> {
         let _guard = self.lock.write_lock();
-        let id = self.next_id;
-        let info = build_info(&value.0, id, process_id, direction, &value.1, ale_layer);
-        self.values.push_back(Entry { value, id });
-        self.next_id = self.next_id.wrapping_add(1); // Assuming this will not overflow.
+        let id = self.values.next_id();
+        let max_payload = self
+            .payload_limits
+            .get(u8::from(value.0.protocol), direction as u8);
+        let info = build_info(
+            &value.0,
+            id,
+            process_id,
+            direction,
+            &value.1,
+            ale_layer,
+            interface_index,
+            sub_interface_index,
+            max_payload,
+        );
+        self.values
+            .push(value, wdk::utils::get_system_timestamp_ms());
+        self.update_count();
 
         return info;
     }
 
     pub fn pop_id(&mut self, id: u64) -> Option<(Key, Packet)> {
         let _guard = self.lock.write_lock();
-        if let Ok(index) = self.values.binary_search_by_key(&id, |val| val.id) {
-            return Some(self.values.remove(index).unwrap().value);
-        }
-        None
+        let value = self.values.pop_id(id);
+        self.update_count();
+        value
     }
 
-    #[allow(dead_code)]
+    /// Pops multiple ids while holding the lock once. The result has the same order as `ids`.
+    pub fn pop_ids(&mut self, ids: &[u64]) -> Vec<Option<(Key, Packet)>> {
+        let _guard = self.lock.write_lock();
+        let values = ids.iter().map(|id| self.values.pop_id(*id)).collect();
+        self.update_count();
+        return values;
+    }
+
+    /// Removes the packet that was pushed first.
+    pub fn pop_oldest(&mut self) -> Option<(u64, (Key, Packet))> {
+        let _guard = self.lock.write_lock();
+        let value = self.values.pop_oldest();
+        self.update_count();
+        value
+    }
+
+    /// Removes the packets that were pushed more than `timeout_ms` ago. Returns them with their ids.
+    pub fn pop_expired(&mut self, timeout_ms: u64) -> Vec<(u64, (Key, Packet))> {
+        let now = wdk::utils::get_system_timestamp_ms();
+        let _guard = self.lock.write_lock();
+        let expired = self.values.pop_expired(now, timeout_ms);
+        self.update_count();
+        return expired;
+    }
+
+    /// Returns the number of pending packets. Does not take the lock.
     pub fn get_entries_count(&self) -> usize {
-        let _guard = self.lock.read_lock();
-        return self.values.len();
+        self.count.load(Ordering::Relaxed)
+    }
+
+    // Must be called with the write lock.
+    fn update_count(&self) {
+        self.count.store(self.values.len(), Ordering::Relaxed);
     }
 }
 
@@ -73,6 +178,8 @@ fn get_payload<'a>(packet: &'a Packet) -> Option<&'a [u8]> {
     }
 }
 
+/// Builds the extended connection info. It is converted for clients that don't support it, see `Info::for_client`.
+#[allow(clippy::too_many_arguments)]
 fn build_info(
     key: &Key,
     packet_id: u64,
@@ -80,6 +187,9 @@ fn build_info(
     direction: Direction,
     packet: &Packet,
     ale_layer: bool,
+    interface_index: u32,
+    sub_interface_index: u32,
+    max_payload: usize,
 ) -> Option<Info> {
     let (local_port, remote_port) = match key.protocol {
         IpProtocol::Tcp | IpProtocol::Udp => (key.local_port, key.remote_port),
@@ -96,10 +206,12 @@ fn build_info(
     if let Some(p) = get_payload(packet) {
         payload = p;
     }
+    let original_length = payload.len() as u32;
+    let payload = &payload[..payload.len().min(max_payload)];
 
     match (key.local_address, key.remote_address) {
         (IpAddress::Ipv6(local_ip), IpAddress::Ipv6(remote_ip)) if key.is_ipv6() => {
-            Some(protocol::info::connection_info_v6(
+            Some(protocol::info::connection_info_v6_ext(
                 packet_id,
                 process_id,
                 direction as u8,
@@ -109,11 +221,14 @@ fn build_info(
                 local_port,
                 remote_port,
                 payload_layer,
+                interface_index,
+                sub_interface_index,
+                original_length,
                 payload,
             ))
         }
         (IpAddress::Ipv4(local_ip), IpAddress::Ipv4(remote_ip)) => {
-            Some(protocol::info::connection_info_v4(
+            Some(protocol::info::connection_info_v4_ext(
                 packet_id,
                 process_id,
                 direction as u8,
@@ -123,6 +238,9 @@ fn build_info(
                 local_port,
                 remote_port,
                 payload_layer,
+                interface_index,
+                sub_interface_index,
+                original_length,
                 payload,
             ))
         }
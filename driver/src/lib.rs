@@ -19,6 +19,9 @@ mod id_cache;
 pub mod logger;
 mod packet_callouts;
 mod packet_util;
+mod process_info;
+mod process_policy;
+mod rate_limit;
 mod stream_callouts;
 
 use wdk::allocator::WindowsAllocator;
@@ -1,24 +1,131 @@
 use alloc::boxed::Box;
+use alloc::string::String;
 use alloc::vec::Vec;
 use core::{
     mem::MaybeUninit,
-    sync::atomic::{AtomicPtr, AtomicUsize, Ordering},
+    sync::atomic::{AtomicPtr, AtomicU32, AtomicU64, AtomicU8, AtomicUsize, Ordering},
 };
-use protocol::info::{Info, Severity};
+use protocol::info::{log_lines_dropped_info, log_record, Info, LogRecord, LogValue, Severity};
 
 #[cfg(not(debug_assertions))]
-pub const LOG_LEVEL: u8 = Severity::Warning as u8;
+pub const DEFAULT_LOG_LEVEL: u8 = Severity::Warning as u8;
 
 #[cfg(debug_assertions)]
-pub const LOG_LEVEL: u8 = Severity::Trace as u8;
+pub const DEFAULT_LOG_LEVEL: u8 = Severity::Trace as u8;
 
+// Minimum severity of recorded lines. Can be changed at runtime with the SetLogLevel command.
+static LOG_LEVEL: AtomicU8 = AtomicU8::new(DEFAULT_LOG_LEVEL);
+
+// Maximum size of the log message. Longer messages are cut and the record is marked as truncated.
 pub const MAX_LOG_LINE_SIZE: usize = 150;
 
 static mut LOG_LINES: [AtomicPtr<Info>; 1024] = unsafe { MaybeUninit::zeroed().assume_init() };
 static START_INDEX: AtomicUsize = unsafe { MaybeUninit::zeroed().assume_init() };
 static END_INDEX: AtomicUsize = unsafe { MaybeUninit::zeroed().assume_init() };
+// Lines that were overwritten before they were read. Reported with a LogLinesDropped info.
+static DROPPED_LINES: AtomicU32 = AtomicU32::new(0);
+
+// Lines per second that are pushed directly to the event queue. 0 disables streaming.
+static STREAM_RATE: AtomicU32 = AtomicU32::new(0);
+static STREAM_WINDOW_START: AtomicU64 = AtomicU64::new(0);
+static STREAM_WINDOW_COUNT: AtomicU32 = AtomicU32::new(0);
+
+pub fn log_level() -> u8 {
+    LOG_LEVEL.load(Ordering::Relaxed)
+}
+
+pub fn set_log_level(severity: Severity) {
+    LOG_LEVEL.store(severity as u8, Ordering::Relaxed);
+}
+
+/// Enables streaming of log lines to the event queue. Lines over the rate stay in the ring buffer
+/// and can be read with GetLogs. 0 disables streaming.
+pub fn set_streaming(max_lines_per_second: u32) {
+    STREAM_RATE.store(max_lines_per_second, Ordering::Relaxed);
+    STREAM_WINDOW_COUNT.store(0, Ordering::Relaxed);
+}
+
+fn stream_allowed() -> bool {
+    let rate = STREAM_RATE.load(Ordering::Relaxed);
+    if rate == 0 {
+        return false;
+    }
+
+    let now = wdk::utils::get_system_timestamp_ms();
+    let start = STREAM_WINDOW_START.load(Ordering::Relaxed);
+    if now.wrapping_sub(start) >= 1000 {
+        // Start a new window. Concurrent resets only let a few more lines through.
+        STREAM_WINDOW_START.store(now, Ordering::Relaxed);
+        STREAM_WINDOW_COUNT.store(0, Ordering::Relaxed);
+    }
+    STREAM_WINDOW_COUNT.fetch_add(1, Ordering::Relaxed) < rate
+}
+
+/// Types that can be structured log fields: `info!(pid = process_id; "message")`
+pub trait LogField {
+    fn log_value(&self) -> LogValue<'_>;
+}
+
+macro_rules! log_field_unsigned {
+    ($($t:ty),*) => {$(
+        impl LogField for $t {
+            fn log_value(&self) -> LogValue<'_> {
+                LogValue::U64(*self as u64)
+            }
+        }
+    )*};
+}
+
+macro_rules! log_field_signed {
+    ($($t:ty),*) => {$(
+        impl LogField for $t {
+            fn log_value(&self) -> LogValue<'_> {
+                LogValue::I64(*self as i64)
+            }
+        }
+    )*};
+}
+
+log_field_unsigned!(u8, u16, u32, u64, usize);
+log_field_signed!(i8, i16, i32, i64, isize);
+
+impl LogField for &str {
+    fn log_value(&self) -> LogValue<'_> {
+        LogValue::Str(self)
+    }
+}
+
+impl LogField for String {
+    fn log_value(&self) -> LogValue<'_> {
+        LogValue::Str(self.as_str())
+    }
+}
+
+/// Starts a log record for the current processor.
+pub fn new_record(severity: Severity, file: &str, line: u32) -> LogRecord {
+    log_record(
+        wdk::utils::get_system_timestamp_ms(),
+        severity,
+        wdk::utils::get_current_processor_number(),
+        wdk::utils::get_current_irql(),
+        file,
+        line,
+        MAX_LOG_LINE_SIZE,
+    )
+}
 
 pub fn add_line(log_line: Info) {
+    if stream_allowed() {
+        if let Some(device) = crate::entry::get_device() {
+            let dropped = DROPPED_LINES.swap(0, Ordering::Relaxed);
+            if dropped > 0 {
+                _ = device.event_queue.push(log_lines_dropped_info(dropped));
+            }
+            _ = device.event_queue.push(log_line);
+            return;
+        }
+    }
+
     let mut index = END_INDEX.fetch_add(1, Ordering::Acquire);
     unsafe {
         index %= LOG_LINES.len();
@@ -27,12 +134,17 @@ pub fn add_line(log_line: Info) {
         let old = ptr.swap(Box::into_raw(line), Ordering::SeqCst);
         if !old.is_null() {
             _ = Box::from_raw(old);
+            DROPPED_LINES.fetch_add(1, Ordering::Relaxed);
         }
     }
 }
 
 pub fn flush() -> Vec<Info> {
     let mut vec = Vec::new();
+    let dropped = DROPPED_LINES.swap(0, Ordering::Relaxed);
+    if dropped > 0 {
+        vec.push(log_lines_dropped_info(dropped));
+    }
     let end_index = END_INDEX.load(Ordering::Acquire);
     let start_index = START_INDEX.load(Ordering::Acquire);
     if end_index <= start_index {
@@ -55,20 +167,24 @@ pub fn flush() -> Vec<Info> {
 
 #[macro_export]
 macro_rules! log_internal {
-    ($log_line:expr, $($arg:tt)*) => ({
-        use core::fmt::Write;
-        _ = write!($log_line, "{}:{} ", file!(), line!());
-        _ = write!($log_line, $($arg)*);
-        $crate::logger::add_line($log_line);
+    ($severity:expr, $($key:ident = $value:expr),+ ; $($arg:tt)*) => ({
+        let mut record = $crate::logger::new_record($severity, file!(), line!());
+        _ = core::fmt::Write::write_fmt(&mut record, format_args!($($arg)*));
+        $(record.field(stringify!($key), $crate::logger::LogField::log_value(&$value));)+
+        $crate::logger::add_line(record.finish());
+    });
+    ($severity:expr, $($arg:tt)*) => ({
+        let mut record = $crate::logger::new_record($severity, file!(), line!());
+        _ = core::fmt::Write::write_fmt(&mut record, format_args!($($arg)*));
+        $crate::logger::add_line(record.finish());
     });
 }
 
 #[macro_export]
 macro_rules! crit {
     ($($arg:tt)*) => ({
-        if protocol::info::Severity::Critical as u8 >= $crate::logger::LOG_LEVEL {
-            let message = alloc::format!($($arg)*);
-            $crate::logger::add_line(protocol::info::Severity::Critical, alloc::format!("{}:{} ", file!(), line!()), message)
+        if protocol::info::Severity::Critical as u8 >= $crate::logger::log_level() {
+            $crate::log_internal!(protocol::info::Severity::Critical, $($arg)*);
         }
     });
 }
@@ -76,9 +192,8 @@ macro_rules! crit {
 #[macro_export]
 macro_rules! err {
     ($($arg:tt)*) => ({
-        if protocol::info::Severity::Error as u8 >= $crate::logger::LOG_LEVEL {
-            let mut log_line = protocol::info::log_line(protocol::info::Severity::Error, $crate::logger::MAX_LOG_LINE_SIZE);
-            $crate::log_internal!(log_line, $($arg)*);
+        if protocol::info::Severity::Error as u8 >= $crate::logger::log_level() {
+            $crate::log_internal!(protocol::info::Severity::Error, $($arg)*);
         }
     });
 }
@@ -86,9 +201,8 @@ macro_rules! err {
 #[macro_export]
 macro_rules! warn {
     ($($arg:tt)*) => ({
-        if protocol::info::Severity::Warning as u8 >= $crate::logger::LOG_LEVEL {
-            let mut log_line = protocol::info::log_line(protocol::info::Severity::Warning, $crate::logger::MAX_LOG_LINE_SIZE);
-            $crate::log_internal!(log_line, $($arg)*);
+        if protocol::info::Severity::Warning as u8 >= $crate::logger::log_level() {
+            $crate::log_internal!(protocol::info::Severity::Warning, $($arg)*);
         }
     });
 }
@@ -96,9 +210,8 @@ macro_rules! warn {
 #[macro_export]
 macro_rules! dbg {
     ($($arg:tt)*) => ({
-        if protocol::info::Severity::Debug as u8 >= $crate::logger::LOG_LEVEL {
-            let mut log_line = protocol::info::log_line(protocol::info::Severity::Debug, $crate::logger::MAX_LOG_LINE_SIZE);
-            $crate::log_internal!(log_line, $($arg)*);
+        if protocol::info::Severity::Debug as u8 >= $crate::logger::log_level() {
+            $crate::log_internal!(protocol::info::Severity::Debug, $($arg)*);
         }
     });
 }
@@ -106,9 +219,8 @@ macro_rules! dbg {
 #[macro_export]
 macro_rules! info {
     ($($arg:tt)*) => ({
-        if protocol::info::Severity::Info as u8 >= $crate::logger::LOG_LEVEL {
-            let mut log_line = protocol::info::log_line(protocol::info::Severity::Info, $crate::logger::MAX_LOG_LINE_SIZE);
-            $crate::log_internal!(log_line, $($arg)*);
+        if protocol::info::Severity::Info as u8 >= $crate::logger::log_level() {
+            $crate::log_internal!(protocol::info::Severity::Info, $($arg)*);
         }
     });
 }
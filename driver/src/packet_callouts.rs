@@ -1,4 +1,6 @@
 use alloc::string::String;
+use protocol::reject::REJECT_INPUT_LEN;
+use protocol::throttle::Admission;
 use smoltcp::wire::{IPV4_HEADER_LEN, IPV6_HEADER_LEN};
 use wdk::filter_engine::callout_data::CalloutData;
 use wdk::filter_engine::layer;
@@ -104,6 +106,77 @@ fn fast_track_pm_packets(key: &Key, direction: Direction) -> bool {
     return false;
 }
 
+/// Applies a verdict that was decided in the driver without asking user space.
+fn apply_driver_verdict(
+    device: &mut Device,
+    data: &mut CalloutData,
+    nbl: &NetBufferList,
+    inject_info: &InjectInfo,
+    key: &Key,
+    process_id: u64,
+    verdict: Verdict,
+) {
+    match verdict {
+        Verdict::Accept | Verdict::PermanentAccept => {
+            permit_packet(device, data, nbl, inject_info, key, process_id)
+        }
+        Verdict::Block | Verdict::PermanentBlock => {
+            reject_packet(device, nbl, inject_info);
+            data.action_block();
+        }
+        _ => data.block_and_absorb(),
+    }
+}
+
+/// Sends a reject for a packet that is blocked in the callout.
+fn reject_packet(device: &mut Device, nbl: &NetBufferList, inject_info: &InjectInfo) {
+    let len = (nbl.get_data_length() as usize).min(REJECT_INPUT_LEN);
+    let mut buffer = [0; REJECT_INPUT_LEN];
+    if nbl.read_bytes(&mut buffer[..len]).is_ok() {
+        device.send_reject(&buffer[..len], inject_info);
+    }
+}
+
+/// Permits a packet that is not over the rate limit of its connection or process. A packet that
+/// has to wait is copied to the queue of the limit and injected later, the original is absorbed.
+fn permit_packet(
+    device: &mut Device,
+    data: &mut CalloutData,
+    nbl: &NetBufferList,
+    inject_info: &InjectInfo,
+    key: &Key,
+    process_id: u64,
+) {
+    let Some(limit) = device.rate_limits.get_limit(key, process_id) else {
+        data.action_permit();
+        return;
+    };
+    let size = nbl.get_data_length() as u64;
+    match device.rate_limits.admit(&limit, size) {
+        Admission::Send => data.action_permit(),
+        Admission::Queue => {
+            let clone = match nbl.clone(&device.network_allocator) {
+                Ok(clone) => clone,
+                Err(err) => {
+                    err!("failed to clone packet: {}", err);
+                    data.action_permit();
+                    return;
+                }
+            };
+            if let Err(packet) = device
+                .rate_limits
+                .queue(&limit, (clone, *inject_info), size)
+            {
+                // The limit was removed in the meantime.
+                device.inject_throttled_packets(alloc::vec![packet]);
+            }
+            data.block_and_absorb();
+        }
+        // The queue of the limit is full, like a router the packet is dropped.
+        Admission::Drop => data.block_and_absorb(),
+    }
+}
+
 fn ip_packet_layer(
     mut data: CalloutData,
     ipv6: bool,
@@ -151,6 +224,13 @@ fn ip_packet_layer(
             return;
         }
 
+        let inject_info = InjectInfo {
+            ipv6,
+            inbound: matches!(direction, Direction::Inbound),
+            loopback: key.is_loopback(),
+            interface_index,
+            sub_interface_index,
+        };
         let mut is_tmp_verdict = false;
         let mut process_id = 0;
 
@@ -164,25 +244,39 @@ fn ip_packet_layer(
                 process_id = conn_info.process_id;
                 // Check if there is action for this connection.
                 match conn_info.verdict {
-                    Verdict::Undecided | Verdict::Accept | Verdict::Block | Verdict::Drop => {
-                        is_tmp_verdict = true
+                    Verdict::Undecided => {
+                        // The process can get a default verdict after the connection was pended.
+                        // Like in the ALE layer the verdict is cached for the connection.
+                        match device.process_policy.get_process(process_id) {
+                            Some(verdict) => {
+                                device.connection_cache.decide_connection(key, verdict);
+                                apply_driver_verdict(
+                                    device,
+                                    &mut data,
+                                    &nbl,
+                                    &inject_info,
+                                    &key,
+                                    process_id,
+                                    verdict,
+                                );
+                            }
+                            None => is_tmp_verdict = true,
+                        }
+                    }
+                    Verdict::Accept | Verdict::Block | Verdict::Drop => is_tmp_verdict = true,
+                    Verdict::PermanentAccept => {
+                        permit_packet(device, &mut data, &nbl, &inject_info, &key, process_id)
+                    }
+                    Verdict::PermanentBlock => {
+                        reject_packet(device, &nbl, &inject_info);
+                        data.action_block();
                     }
-                    Verdict::PermanentAccept => data.action_permit(),
-                    Verdict::PermanentBlock => data.action_block(),
                     Verdict::Undeterminable | Verdict::PermanentDrop | Verdict::Failed => {
                         data.block_and_absorb()
                     }
                     Verdict::RedirectNameServer | Verdict::RedirectTunnel => {
                         if let Some(redirect_info) = conn_info.redirect_info.take() {
-                            match clone_packet(
-                                device,
-                                nbl,
-                                direction,
-                                ipv6,
-                                key.is_loopback(),
-                                interface_index,
-                                sub_interface_index,
-                            ) {
+                            match clone_packet(device, nbl, inject_info) {
                                 Ok(mut packet) => {
                                     let _ = packet.redirect(redirect_info);
                                     if let Err(err) = device.inject_packet(packet, false) {
@@ -217,15 +311,19 @@ fn ip_packet_layer(
 
         // Clone packet and send to user space if it's a temporary verdict.
         if is_tmp_verdict {
-            let packet = match clone_packet(
-                device,
-                nbl,
-                direction,
-                ipv6,
-                key.is_loopback(),
-                interface_index,
-                sub_interface_index,
-            ) {
+            if let Some(verdict) = device.pending_overflow_verdict() {
+                apply_driver_verdict(
+                    device,
+                    &mut data,
+                    &nbl,
+                    &inject_info,
+                    &key,
+                    process_id,
+                    verdict,
+                );
+                continue;
+            }
+            let packet = match clone_packet(device, nbl, inject_info) {
                 Ok(p) => p,
                 Err(err) => {
                     err!("failed to clone packet: {}", err);
@@ -233,9 +331,14 @@ fn ip_packet_layer(
                 }
             };
 
-            let info = device
-                .packet_cache
-                .push((key, packet), process_id, direction, false);
+            let info = device.packet_cache.push(
+                (key, packet),
+                process_id,
+                direction,
+                false,
+                interface_index,
+                sub_interface_index,
+            );
             // Send to ZenithFence
             if let Some(info) = info {
                 let _ = device.event_queue.push(info);
@@ -248,27 +351,10 @@ fn ip_packet_layer(
 fn clone_packet(
     device: &mut Device,
     nbl: NetBufferList,
-    direction: Direction,
-    ipv6: bool,
-    loopback: bool,
-    interface_index: u32,
-    sub_interface_index: u32,
+    inject_info: InjectInfo,
 ) -> Result<Packet, String> {
     let clone = nbl.clone(&device.network_allocator)?;
-    let inbound = match direction {
-        Direction::Outbound => false,
-        Direction::Inbound => true,
-    };
-    Ok(Packet::PacketLayer(
-        clone,
-        InjectInfo {
-            ipv6,
-            inbound,
-            loopback,
-            interface_index,
-            sub_interface_index,
-        },
-    ))
+    Ok(Packet::PacketLayer(clone, inject_info))
 }
 
 fn get_connection_info(
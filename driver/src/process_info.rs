@@ -0,0 +1,55 @@
+use alloc::string::String;
+use hashbrown::HashSet;
+use protocol::info::{process_info, Info};
+use wdk::rw_spin_lock::RwSpinLock;
+
+/// Process ids that the client got a process info for. The process info is sent once, before the
+/// first connection of a process. The id is removed when the process exits, ids are reused.
+pub struct AnnouncedProcesses {
+    processes: HashSet<u64>,
+    lock: RwSpinLock,
+}
+
+impl AnnouncedProcesses {
+    pub fn new() -> Self {
+        Self {
+            processes: HashSet::new(),
+            lock: RwSpinLock::default(),
+        }
+    }
+
+    /// Returns the process info if the process was not announced yet. `get_path` is only called
+    /// for new processes, the process is not announced if it has no path.
+    pub fn announce(
+        &mut self,
+        process_id: u64,
+        get_path: impl FnOnce() -> Option<String>,
+    ) -> Option<Info> {
+        {
+            let _guard = self.lock.read_lock();
+            if self.processes.contains(&process_id) {
+                return None;
+            }
+        }
+
+        // Allocates, don't hold the lock.
+        let path = get_path()?;
+        let _guard = self.lock.write_lock();
+        if !self.processes.insert(process_id) {
+            // Announced by another packet in the meantime.
+            return None;
+        }
+        Some(process_info(process_id, &path))
+    }
+
+    pub fn process_exited(&mut self, process_id: u64) {
+        let _guard = self.lock.write_lock();
+        self.processes.remove(&process_id);
+    }
+
+    /// Forgets every process. Used for a new client, it gets the process infos again.
+    pub fn clear(&mut self) {
+        let _guard = self.lock.write_lock();
+        self.processes.clear();
+    }
+}
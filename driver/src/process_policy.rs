@@ -0,0 +1,80 @@
+use alloc::string::String;
+use num_traits::FromPrimitive;
+use protocol::process_policy::{PolicyLookup, ProcessPolicyTable};
+use wdk::rw_spin_lock::RwSpinLock;
+use windows_sys::Win32::Foundation::HANDLE;
+
+use crate::connection::Verdict;
+
+/// Default verdicts for new connections of a process, by process id or by executable path.
+/// Process id entries are removed when the process exits.
+pub struct ProcessPolicy {
+    table: ProcessPolicyTable,
+    lock: RwSpinLock,
+}
+
+impl ProcessPolicy {
+    pub fn new() -> Self {
+        Self {
+            table: ProcessPolicyTable::new(),
+            lock: RwSpinLock::default(),
+        }
+    }
+
+    /// Sets the verdict for the process. `None` removes the entry.
+    pub fn set_process(&mut self, process_id: u64, verdict: Option<Verdict>) {
+        let _guard = self.lock.write_lock();
+        self.table.set_process(process_id, verdict.map(|v| v as u8));
+    }
+
+    /// Sets the verdict for the executable path. `None` removes the entry.
+    pub fn set_path(&mut self, path: &str, verdict: Option<Verdict>) {
+        let _guard = self.lock.write_lock();
+        self.table.set_path(path, verdict.map(|v| v as u8));
+    }
+
+    /// Returns the verdict for the process id. Does not check the paths.
+    pub fn get_process(&self, process_id: u64) -> Option<Verdict> {
+        let _guard = self.lock.read_lock();
+        Verdict::from_u8(self.table.get_process(process_id)?)
+    }
+
+    /// Returns the verdict for the process. `get_path` is only called if there are path entries
+    /// and the process was not checked yet, the result is saved for the process id, also if no
+    /// path matched.
+    pub fn get(
+        &mut self,
+        process_id: u64,
+        get_path: impl FnOnce() -> Option<String>,
+    ) -> Option<Verdict> {
+        {
+            let _guard = self.lock.read_lock();
+            if let PolicyLookup::Verdict(verdict) = self.table.get(process_id) {
+                return Verdict::from_u8(verdict?);
+            }
+        }
+
+        // Allocates, don't hold the lock. A process without a path is saved too.
+        let path = get_path();
+        let _guard = self.lock.write_lock();
+        Verdict::from_u8(self.table.match_path(process_id, path.as_deref())?)
+    }
+
+    pub fn process_exited(&mut self, process_id: u64) {
+        let _guard = self.lock.write_lock();
+        self.table.process_exited(process_id);
+    }
+}
+
+/// Registered with `wdk::utils::set_process_notify`. Removes the entries, the rate limits and the
+/// announced process ids of processes that exit.
+pub unsafe extern "system" fn process_notify(_parent_id: HANDLE, process_id: HANDLE, create: u8) {
+    if create != 0 {
+        return;
+    }
+    if let Some(device) = crate::entry::get_device() {
+        device.process_policy.process_exited(process_id as u64);
+        device.rate_limits.process_exited(process_id as u64);
+        device.announced_processes.process_exited(process_id as u64);
+    }
+}
@@ -0,0 +1,107 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use alloc::vec::Vec;
+use protocol::throttle::{Admission, Throttle};
+use wdk::{
+    filter_engine::{net_buffer::NetBufferList, packet::InjectInfo},
+    rw_spin_lock::RwSpinLock,
+};
+
+use crate::connection_map::Key;
+
+/// What a bandwidth limit is set for.
+#[derive(Clone, Copy, PartialEq, PartialOrd, Eq, Ord)]
+pub enum LimitKey {
+    Connection(Key),
+    Process(u64),
+}
+
+/// Packet that waits for the tokens of its limit. Injected when it is released.
+pub type ThrottledPacket = (NetBufferList, InjectInfo);
+
+/// Bandwidth limits of connections and processes. Packets over a limit are copied to the queue
+/// of the limit and injected again when the limit has tokens, see `protocol::throttle`.
+pub struct RateLimits {
+    throttle: Throttle<LimitKey, ThrottledPacket>,
+    lock: RwSpinLock,
+    // Copy of the number of limits that can be read without taking the lock.
+    // Packets don't take the lock if there are no limits.
+    count: AtomicUsize,
+}
+
+impl RateLimits {
+    pub fn new() -> Self {
+        Self {
+            throttle: Throttle::new(),
+            lock: RwSpinLock::default(),
+            count: AtomicUsize::new(0),
+        }
+    }
+
+    /// Sets the limit in bytes per second. 0 removes the limit, the packets that were waiting
+    /// are returned and can be injected.
+    pub fn set(&mut self, key: LimitKey, bytes_per_second: u64) -> Vec<ThrottledPacket> {
+        let now = wdk::utils::get_system_timestamp_ms();
+        let _guard = self.lock.write_lock();
+        let released = self.throttle.set_limit(key, bytes_per_second, now);
+        self.count
+            .store(self.throttle.limit_count(), Ordering::Relaxed);
+        released
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count.load(Ordering::Relaxed) == 0
+    }
+
+    /// Returns the limit of a packet. The connection limit is used before the limit of the process.
+    pub fn get_limit(&self, key: &Key, process_id: u64) -> Option<LimitKey> {
+        if self.is_empty() {
+            return None;
+        }
+        let _guard = self.lock.read_lock();
+        let connection = LimitKey::Connection(*key);
+        if self.throttle.has_limit(&connection) {
+            return Some(connection);
+        }
+        let process = LimitKey::Process(process_id);
+        if process_id != 0 && self.throttle.has_limit(&process) {
+            return Some(process);
+        }
+        None
+    }
+
+    /// Decides if a packet of `size` bytes can be sent now, see `Throttle::admit`.
+    pub fn admit(&mut self, key: &LimitKey, size: u64) -> Admission {
+        let now = wdk::utils::get_system_timestamp_ms();
+        let _guard = self.lock.write_lock();
+        self.throttle.admit(key, size, now)
+    }
+
+    /// Queues a packet that got `Admission::Queue`. The packet is returned if the limit was
+    /// removed in the meantime.
+    pub fn queue(
+        &mut self,
+        key: &LimitKey,
+        packet: ThrottledPacket,
+        size: u64,
+    ) -> Result<(), ThrottledPacket> {
+        let _guard = self.lock.write_lock();
+        self.throttle.queue(key, packet, size)
+    }
+
+    /// Removes the packets that can be sent now.
+    pub fn pop_ready(&mut self) -> Vec<ThrottledPacket> {
+        let now = wdk::utils::get_system_timestamp_ms();
+        let _guard = self.lock.write_lock();
+        self.throttle.pop_ready(now)
+    }
+
+    /// Removes the limit of the process. Packets that were waiting are dropped.
+    pub fn process_exited(&mut self, process_id: u64) {
+        let _guard = self.lock.write_lock();
+        self.throttle
+            .retain_limits(|key| *key != LimitKey::Process(process_id));
+        self.count
+            .store(self.throttle.limit_count(), Ordering::Relaxed);
+    }
+}
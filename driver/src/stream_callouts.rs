@@ -1,18 +1,23 @@
-use smoltcp::wire::{Ipv4Address, Ipv6Address};
+use smoltcp::wire::{IpAddress, IpProtocol, Ipv4Address, Ipv6Address};
 use wdk::filter_engine::{callout_data::CalloutData, layer, net_buffer::NetBufferListIter};
 
-use crate::{bandwidth, connection::Direction};
+use crate::{
+    bandwidth,
+    connection::{ConnectionV4, ConnectionV6, Direction},
+    connection_map,
+    device::Device,
+};
 
 pub fn stream_layer_tcp_v4(data: CalloutData) {
     let Some(device) = crate::entry::get_device() else {
         return;
     };
     let mut direction = Direction::Outbound;
-    let data_length = if let Some(packet) = data.get_stream_callout_packet() {
+    let (data_length, packets) = if let Some(packet) = data.get_stream_callout_packet() {
         if packet.is_receive() {
             direction = Direction::Inbound;
         }
-        packet.get_data_len()
+        (packet.get_data_len(), packet.get_net_buffer_list_count())
     } else {
         return;
     };
@@ -39,6 +44,7 @@ pub fn stream_layer_tcp_v4(data: CalloutData) {
                     remote_port,
                 },
                 data_length,
+                packets,
             );
         }
         Direction::Inbound => {
@@ -50,9 +56,23 @@ pub fn stream_layer_tcp_v4(data: CalloutData) {
                     remote_port,
                 },
                 data_length,
+                packets,
             );
         }
     }
+    update_process_stats(
+        device,
+        connection_map::Key {
+            protocol: IpProtocol::Tcp,
+            local_address: IpAddress::Ipv4(local_ip),
+            local_port,
+            remote_address: IpAddress::Ipv4(remote_ip),
+            remote_port,
+        },
+        direction,
+        data_length,
+        packets,
+    );
 }
 
 pub fn stream_layer_tcp_v6(data: CalloutData) {
@@ -60,11 +80,11 @@ pub fn stream_layer_tcp_v6(data: CalloutData) {
         return;
     };
     let mut direction = Direction::Outbound;
-    let data_length = if let Some(packet) = data.get_stream_callout_packet() {
+    let (data_length, packets) = if let Some(packet) = data.get_stream_callout_packet() {
         if packet.is_receive() {
             direction = Direction::Inbound;
         }
-        packet.get_data_len()
+        (packet.get_data_len(), packet.get_net_buffer_list_count())
     } else {
         return;
     };
@@ -88,6 +108,7 @@ pub fn stream_layer_tcp_v6(data: CalloutData) {
                     remote_port,
                 },
                 data_length,
+                packets,
             );
         }
         Direction::Inbound => {
@@ -99,9 +120,23 @@ pub fn stream_layer_tcp_v6(data: CalloutData) {
                     remote_port,
                 },
                 data_length,
+                packets,
             );
         }
     }
+    update_process_stats(
+        device,
+        connection_map::Key {
+            protocol: IpProtocol::Tcp,
+            local_address: IpAddress::Ipv6(local_ip),
+            local_port,
+            remote_address: IpAddress::Ipv6(remote_ip),
+            remote_port,
+        },
+        direction,
+        data_length,
+        packets,
+    );
 }
 
 pub fn stream_layer_udp_v4(data: CalloutData) {
@@ -109,14 +144,17 @@ pub fn stream_layer_udp_v4(data: CalloutData) {
         return;
     };
     let mut data_length: usize = 0;
+    let mut packets: usize = 0;
     for nbl in NetBufferListIter::new(data.get_layer_data() as _) {
         data_length += nbl.get_data_length() as usize;
+        packets += 1;
     }
     type Fields = layer::FieldsDatagramDataV4;
     let mut direction = Direction::Inbound;
     if data.get_value_u8(Fields::Direction as usize) == 0 {
         direction = Direction::Outbound;
     }
+    let protocol = IpProtocol::from(data.get_value_u8(Fields::IpProtocol as usize));
 
     let local_ip = Ipv4Address::from_bytes(
         &data
@@ -130,30 +168,48 @@ pub fn stream_layer_udp_v4(data: CalloutData) {
             .to_be_bytes(),
     );
     let remote_port = data.get_value_u16(Fields::IpRemotePort as usize);
-    match direction {
-        Direction::Outbound => {
-            device.bandwidth_stats.update_udp_v4_tx(
-                bandwidth::Key {
-                    local_ip,
-                    local_port,
-                    remote_ip,
-                    remote_port,
-                },
-                data_length,
-            );
+    let key = bandwidth::Key {
+        local_ip,
+        local_port,
+        remote_ip,
+        remote_port,
+    };
+    // The datagram layer sees every protocol other than TCP.
+    match (protocol, direction) {
+        (IpProtocol::Udp, Direction::Outbound) => {
+            device
+                .bandwidth_stats
+                .update_udp_v4_tx(key, data_length, packets);
         }
-        Direction::Inbound => {
-            device.bandwidth_stats.update_udp_v4_rx(
-                bandwidth::Key {
-                    local_ip,
-                    local_port,
-                    remote_ip,
-                    remote_port,
-                },
-                data_length,
-            );
+        (IpProtocol::Udp, Direction::Inbound) => {
+            device
+                .bandwidth_stats
+                .update_udp_v4_rx(key, data_length, packets);
+        }
+        (_, Direction::Outbound) => {
+            device
+                .bandwidth_stats
+                .update_other_v4_tx(protocol, key, data_length, packets);
+        }
+        (_, Direction::Inbound) => {
+            device
+                .bandwidth_stats
+                .update_other_v4_rx(protocol, key, data_length, packets);
         }
     }
+    update_process_stats(
+        device,
+        connection_map::Key {
+            protocol,
+            local_address: IpAddress::Ipv4(local_ip),
+            local_port,
+            remote_address: IpAddress::Ipv4(remote_ip),
+            remote_port,
+        },
+        direction,
+        data_length,
+        packets,
+    );
 }
 
 pub fn stream_layer_udp_v6(data: CalloutData) {
@@ -161,14 +217,17 @@ pub fn stream_layer_udp_v6(data: CalloutData) {
         return;
     };
     let mut data_length: usize = 0;
+    let mut packets: usize = 0;
     for nbl in NetBufferListIter::new(data.get_layer_data() as _) {
         data_length += nbl.get_data_length() as usize;
+        packets += 1;
     }
     type Fields = layer::FieldsDatagramDataV6;
     let mut direction = Direction::Inbound;
     if data.get_value_u8(Fields::Direction as usize) == 0 {
         direction = Direction::Outbound;
     }
+    let protocol = IpProtocol::from(data.get_value_u8(Fields::IpProtocol as usize));
 
     let local_ip =
         Ipv6Address::from_bytes(data.get_value_byte_array16(Fields::IpLocalAddress as usize));
@@ -176,28 +235,81 @@ pub fn stream_layer_udp_v6(data: CalloutData) {
     let remote_ip =
         Ipv6Address::from_bytes(data.get_value_byte_array16(Fields::IpRemoteAddress as usize));
     let remote_port = data.get_value_u16(Fields::IpRemotePort as usize);
+    let key = bandwidth::Key {
+        local_ip,
+        local_port,
+        remote_ip,
+        remote_port,
+    };
+    // The datagram layer sees every protocol other than TCP.
+    match (protocol, direction) {
+        (IpProtocol::Udp, Direction::Outbound) => {
+            device
+                .bandwidth_stats
+                .update_udp_v6_tx(key, data_length, packets);
+        }
+        (IpProtocol::Udp, Direction::Inbound) => {
+            device
+                .bandwidth_stats
+                .update_udp_v6_rx(key, data_length, packets);
+        }
+        (_, Direction::Outbound) => {
+            device
+                .bandwidth_stats
+                .update_other_v6_tx(protocol, key, data_length, packets);
+        }
+        (_, Direction::Inbound) => {
+            device
+                .bandwidth_stats
+                .update_other_v6_rx(protocol, key, data_length, packets);
+        }
+    }
+    update_process_stats(
+        device,
+        connection_map::Key {
+            protocol,
+            local_address: IpAddress::Ipv6(local_ip),
+            local_port,
+            remote_address: IpAddress::Ipv6(remote_ip),
+            remote_port,
+        },
+        direction,
+        data_length,
+        packets,
+    );
+}
+
+/// Adds the bytes and packets to the process of the connection. Connections that are not in the
+/// connection cache are not counted per process.
+fn update_process_stats(
+    device: &mut Device,
+    key: connection_map::Key,
+    direction: Direction,
+    data_length: usize,
+    packets: usize,
+) {
+    let process_id = if key.is_ipv6() {
+        device
+            .connection_cache
+            .read_connection_v6(&key, |conn: &ConnectionV6| Some(conn.process_id))
+    } else {
+        device
+            .connection_cache
+            .read_connection_v4(&key, |conn: &ConnectionV4| Some(conn.process_id))
+    };
+    let Some(process_id) = process_id else {
+        return;
+    };
     match direction {
         Direction::Outbound => {
-            device.bandwidth_stats.update_udp_v6_tx(
-                bandwidth::Key {
-                    local_ip,
-                    local_port,
-                    remote_ip,
-                    remote_port,
-                },
-                data_length,
-            );
+            device
+                .bandwidth_stats
+                .update_process_tx(process_id, key.protocol, data_length, packets)
         }
         Direction::Inbound => {
-            device.bandwidth_stats.update_udp_v6_rx(
-                bandwidth::Key {
-                    local_ip,
-                    local_port,
-                    remote_ip,
-                    remote_port,
-                },
-                data_length,
-            );
+            device
+                .bandwidth_stats
+                .update_process_rx(process_id, key.protocol, data_length, packets)
         }
     }
 }
@@ -1,22 +1,57 @@
 package kext_interface
 
 import (
+	"bytes"
 	"encoding/binary"
 	"io"
 )
 
 const (
-	CommandShutdown              = 0
-	CommandVerdict               = 1
-	CommandUpdateV4              = 2
-	CommandUpdateV6              = 3
-	CommandClearCache            = 4
-	CommandGetLogs               = 5
-	CommandBandwidthStats        = 6
-	CommandPrintMemoryStats      = 7
-	CommandCleanEndedConnections = 8
+	CommandShutdown                 = 0
+	CommandVerdict                  = 1
+	CommandUpdateV4                 = 2
+	CommandUpdateV6                 = 3
+	CommandClearCache               = 4
+	CommandGetLogs                  = 5
+	CommandBandwidthStats           = 6
+	CommandPrintMemoryStats         = 7
+	CommandCleanEndedConnections    = 8
+	CommandHandshake                = 9
+	CommandVerdictBatch             = 10
+	CommandRequest                  = 11
+	CommandSetPayloadLimit          = 12
+	CommandSetLogLevel              = 13
+	CommandSetLogStreaming          = 14
+	CommandDumpConnections          = 15
+	CommandLoadVerdicts             = 16
+	CommandAddRule                  = 17
+	CommandRemoveRule               = 18
+	CommandListRules                = 19
+	CommandSetProcessVerdict        = 20
+	CommandSetPathVerdict           = 21
+	CommandSetPendingTimeout        = 22
+	CommandSetQueueLimits           = 23
+	CommandSetBandwidthPush         = 24
+	CommandSetProcessRateLimit      = 25
+	CommandSetConnectionRateLimitV4 = 26
+	CommandSetConnectionRateLimitV6 = 27
 )
 
+// What happens to a new packet when the pending packet limit is reached.
+// Make sure this is in sync with the Rust version.
+const (
+	// OverflowDropOldest drops the oldest pending packet and pends the new one.
+	OverflowDropOldest uint8 = 0
+	// OverflowFailOpen accepts the new packet without asking the client.
+	OverflowFailOpen uint8 = 1
+	// OverflowFailClosed blocks the new packet without asking the client.
+	OverflowFailClosed uint8 = 2
+)
+
+// ProtocolVersion is the version of the command and info protocol.
+// Make sure this is in sync with the Rust version.
+const ProtocolVersion = 3
+
 type KextVerdict uint8
 
 // Make sure this is in sync with the Rust version.
@@ -55,6 +90,72 @@ type RedirectV6 struct {
 	RemotePort    uint16
 }
 
+// Handshake declares the protocol version of the client and the info types it can decode.
+// Bit n of InfoTypes is set for the info type with value n.
+type Handshake struct {
+	command   uint8
+	Version   uint32
+	InfoTypes uint64
+}
+
+// PayloadLimitAny matches every protocol or direction in SetPayloadLimit.
+const PayloadLimitAny = 0xFF
+
+// SetPayloadLimit sets the maximum number of payload bytes copied into connection infos.
+// Protocol is an IP protocol number and Direction is 0 for outbound and 1 for inbound,
+// either can be PayloadLimitAny. The most specific limit is used. MaxLength 0xFFFFFFFF means no limit.
+type SetPayloadLimit struct {
+	command   uint8
+	Protocol  uint8
+	Direction uint8
+	MaxLength uint32
+}
+
+// LoadVerdictV4 is a connection with a known verdict for SendLoadVerdictsCommand.
+// Direction is 0 for outbound and 1 for inbound.
+type LoadVerdictV4 struct {
+	Protocol      uint8
+	LocalAddress  [4]byte
+	LocalPort     uint16
+	RemoteAddress [4]byte
+	RemotePort    uint16
+	Direction     uint8
+	Verdict       uint8
+	ProcessId     uint64
+}
+
+// LoadVerdictV6 is a connection with a known verdict for SendLoadVerdictsCommand.
+// Direction is 0 for outbound and 1 for inbound.
+type LoadVerdictV6 struct {
+	Protocol      uint8
+	LocalAddress  [16]byte
+	LocalPort     uint16
+	RemoteAddress [16]byte
+	RemotePort    uint16
+	Direction     uint8
+	Verdict       uint8
+	ProcessId     uint64
+}
+
+// RuleAny matches every protocol or direction in a Rule.
+const RuleAny = 0xFF
+
+// Rule gives a verdict to new connections to a remote network and port range, without asking the client.
+// Protocol is an IP protocol number and Direction is 0 for outbound and 1 for inbound, either can be RuleAny.
+// IpVersion is 4 or 6, an IPv4 address uses the first 4 bytes of RemoteAddress.
+// The port range includes both ends. Rules are checked in the order of their ids, the first match is used.
+type Rule struct {
+	Id              uint32
+	Protocol        uint8
+	Direction       uint8
+	IpVersion       uint8
+	RemoteAddress   [16]byte
+	PrefixLength    uint8
+	RemotePortStart uint16
+	RemotePortEnd   uint16
+	Verdict         uint8
+}
+
 type UpdateV4 struct {
 	command       uint8
 	Protocol      uint8
@@ -75,6 +176,28 @@ type UpdateV6 struct {
 	Verdict       uint8
 }
 
+// ConnectionRateLimitV4 limits the bandwidth of a connection, both directions together.
+// A BytesPerSecond of 0 removes the limit. A connection limit is used instead of the limit of the process.
+type ConnectionRateLimitV4 struct {
+	command        uint8
+	Protocol       uint8
+	LocalAddress   [4]byte
+	LocalPort      uint16
+	RemoteAddress  [4]byte
+	RemotePort     uint16
+	BytesPerSecond uint64
+}
+
+type ConnectionRateLimitV6 struct {
+	command        uint8
+	Protocol       uint8
+	LocalAddress   [16]byte
+	LocalPort      uint16
+	RemoteAddress  [16]byte
+	RemotePort     uint16
+	BytesPerSecond uint64
+}
+
 func SendShutdownCommand(writer io.Writer) error {
 	_, err := writer.Write([]byte{CommandShutdown})
 	return err
@@ -110,6 +233,8 @@ func SendGetBandwidthStatsCommand(writer io.Writer) error {
 	return err
 }
 
+// SendPrintMemoryStatsCommand requests the memory usage of the driver.
+// The driver replies with a MemoryStats info.
 func SendPrintMemoryStatsCommand(writer io.Writer) error {
 	_, err := writer.Write([]byte{CommandPrintMemoryStats})
 	return err
@@ -119,3 +244,192 @@ func SendCleanEndedConnectionsCommand(writer io.Writer) error {
 	_, err := writer.Write([]byte{CommandCleanEndedConnections})
 	return err
 }
+
+// SendDumpConnectionsCommand requests the content of the connection cache.
+// The driver replies with ConnectionDump infos followed by a ConnectionDumpEnd info.
+func SendDumpConnectionsCommand(writer io.Writer) error {
+	_, err := writer.Write([]byte{CommandDumpConnections})
+	return err
+}
+
+func SendHandshakeCommand(writer io.Writer, handshake Handshake) error {
+	handshake.command = CommandHandshake
+	return binary.Write(writer, binary.LittleEndian, handshake)
+}
+
+// SendVerdictBatchCommand sends verdicts for multiple pending packets with a single write.
+// The driver replies with a VerdictBatchResult info.
+func SendVerdictBatchCommand(writer io.Writer, verdicts []Verdict) error {
+	buf := make([]byte, 0, 5+len(verdicts)*9)
+	buf = append(buf, CommandVerdictBatch)
+	buf = binary.LittleEndian.AppendUint32(buf, uint32(len(verdicts)))
+	for _, verdict := range verdicts {
+		buf = binary.LittleEndian.AppendUint64(buf, verdict.Id)
+		buf = append(buf, verdict.Verdict)
+	}
+	_, err := writer.Write(buf)
+	return err
+}
+
+// SendRequest wraps the command written by send in a request with the given id.
+// The driver replies with a CommandResult info that has the same request id.
+func SendRequest(writer io.Writer, requestId uint64, send func(io.Writer) error) error {
+	var buf bytes.Buffer
+	buf.WriteByte(CommandRequest)
+	binary.Write(&buf, binary.LittleEndian, requestId)
+	err := send(&buf)
+	if err != nil {
+		return err
+	}
+	_, err = writer.Write(buf.Bytes())
+	return err
+}
+
+func SendSetPayloadLimitCommand(writer io.Writer, limit SetPayloadLimit) error {
+	limit.command = CommandSetPayloadLimit
+	return binary.Write(writer, binary.LittleEndian, limit)
+}
+
+// SendSetLogLevelCommand sets the minimum severity of log lines the driver records.
+// SeverityDisabled turns logging off.
+func SendSetLogLevelCommand(writer io.Writer, severity uint8) error {
+	_, err := writer.Write([]byte{CommandSetLogLevel, severity})
+	return err
+}
+
+// SendSetLogStreamingCommand makes the driver send log lines as they are recorded, without GetLogs.
+// At most maxLinesPerSecond lines are sent, the rest stay in the driver log buffer. 0 disables streaming.
+func SendSetLogStreamingCommand(writer io.Writer, maxLinesPerSecond uint32) error {
+	var buf bytes.Buffer
+	buf.WriteByte(CommandSetLogStreaming)
+	binary.Write(&buf, binary.LittleEndian, maxLinesPerSecond)
+	_, err := writer.Write(buf.Bytes())
+	return err
+}
+
+// SendLoadVerdictsCommand adds connections with known verdicts to the driver connection cache.
+// Packets of these connections are not sent to the client again. Use it after ClearCache,
+// when the client restarts. VerdictUndecided is not accepted.
+func SendLoadVerdictsCommand(writer io.Writer, v4 []LoadVerdictV4, v6 []LoadVerdictV6) error {
+	var buf bytes.Buffer
+	buf.WriteByte(CommandLoadVerdicts)
+	binary.Write(&buf, binary.LittleEndian, uint32(len(v4)))
+	for _, verdict := range v4 {
+		binary.Write(&buf, binary.LittleEndian, verdict)
+	}
+	binary.Write(&buf, binary.LittleEndian, uint32(len(v6)))
+	for _, verdict := range v6 {
+		binary.Write(&buf, binary.LittleEndian, verdict)
+	}
+	_, err := writer.Write(buf.Bytes())
+	return err
+}
+
+// SendAddRuleCommand adds a verdict rule to the driver. A rule with the same id is replaced.
+// The rule is used only for new connections. VerdictUndecided and redirects are not accepted,
+// temporary verdicts are cached as their permanent form.
+func SendAddRuleCommand(writer io.Writer, rule Rule) error {
+	var buf bytes.Buffer
+	buf.WriteByte(CommandAddRule)
+	binary.Write(&buf, binary.LittleEndian, rule)
+	_, err := writer.Write(buf.Bytes())
+	return err
+}
+
+// SendRemoveRuleCommand removes the verdict rule with the given id.
+func SendRemoveRuleCommand(writer io.Writer, id uint32) error {
+	var buf bytes.Buffer
+	buf.WriteByte(CommandRemoveRule)
+	binary.Write(&buf, binary.LittleEndian, id)
+	_, err := writer.Write(buf.Bytes())
+	return err
+}
+
+// SendListRulesCommand requests the verdict rules. The driver replies with a RuleList info.
+func SendListRulesCommand(writer io.Writer) error {
+	_, err := writer.Write([]byte{CommandListRules})
+	return err
+}
+
+// SendSetProcessVerdictCommand sets the default verdict for new connections of a process.
+// The entry is removed when the process exits. VerdictUndecided removes the entry.
+// Temporary verdicts are cached as their permanent form.
+func SendSetProcessVerdictCommand(writer io.Writer, processId uint64, verdict uint8) error {
+	var buf bytes.Buffer
+	buf.WriteByte(CommandSetProcessVerdict)
+	binary.Write(&buf, binary.LittleEndian, processId)
+	buf.WriteByte(verdict)
+	_, err := writer.Write(buf.Bytes())
+	return err
+}
+
+// SendSetPendingTimeoutCommand sets how long packets wait for a verdict. Packets that wait longer
+// get the given verdict and are reported with a PendingTimeout info. A timeout of 0 disables it.
+func SendSetPendingTimeoutCommand(writer io.Writer, timeoutMs uint32, verdict uint8) error {
+	var buf bytes.Buffer
+	buf.WriteByte(CommandSetPendingTimeout)
+	binary.Write(&buf, binary.LittleEndian, timeoutMs)
+	buf.WriteByte(verdict)
+	_, err := writer.Write(buf.Bytes())
+	return err
+}
+
+// SendSetQueueLimitsCommand limits the number of pending packets and of infos that wait to be read.
+// 0 removes a limit, there are no limits until a client sets them. Dropped packets and infos are
+// reported with a QueueOverflow info.
+func SendSetQueueLimitsCommand(writer io.Writer, maxPendingPackets uint32, maxEvents uint32, overflowPolicy uint8) error {
+	var buf bytes.Buffer
+	buf.WriteByte(CommandSetQueueLimits)
+	binary.Write(&buf, binary.LittleEndian, maxPendingPackets)
+	binary.Write(&buf, binary.LittleEndian, maxEvents)
+	buf.WriteByte(overflowPolicy)
+	_, err := writer.Write(buf.Bytes())
+	return err
+}
+
+// SendSetBandwidthPushCommand makes the kext send the bandwidth stats every intervalMs without
+// SendGetBandwidthStatsCommand. An interval of 0 disables it, intervals longer than math.MaxInt32
+// milliseconds are rejected. Bandwidth stats infos are split so one
+// info is not larger than maxFrameSize bytes, 0 removes the limit.
+func SendSetBandwidthPushCommand(writer io.Writer, intervalMs uint32, maxFrameSize uint32) error {
+	var buf bytes.Buffer
+	buf.WriteByte(CommandSetBandwidthPush)
+	binary.Write(&buf, binary.LittleEndian, intervalMs)
+	binary.Write(&buf, binary.LittleEndian, maxFrameSize)
+	_, err := writer.Write(buf.Bytes())
+	return err
+}
+
+// SendSetProcessRateLimitCommand limits the bandwidth of every connection of a process to bytesPerSecond,
+// both directions together. Packets over the limit are delayed by the kext. 0 removes the limit.
+// The limit is removed when the process exits.
+func SendSetProcessRateLimitCommand(writer io.Writer, processId uint64, bytesPerSecond uint64) error {
+	var buf bytes.Buffer
+	buf.WriteByte(CommandSetProcessRateLimit)
+	binary.Write(&buf, binary.LittleEndian, processId)
+	binary.Write(&buf, binary.LittleEndian, bytesPerSecond)
+	_, err := writer.Write(buf.Bytes())
+	return err
+}
+
+func SendSetConnectionRateLimitV4Command(writer io.Writer, limit ConnectionRateLimitV4) error {
+	limit.command = CommandSetConnectionRateLimitV4
+	return binary.Write(writer, binary.LittleEndian, limit)
+}
+
+func SendSetConnectionRateLimitV6Command(writer io.Writer, limit ConnectionRateLimitV6) error {
+	limit.command = CommandSetConnectionRateLimitV6
+	return binary.Write(writer, binary.LittleEndian, limit)
+}
+
+// SendSetPathVerdictCommand sets the default verdict for new connections of processes with the
+// given executable path. The path is in device form and not case sensitive. VerdictUndecided removes the entry.
+func SendSetPathVerdictCommand(writer io.Writer, verdict uint8, path string) error {
+	var buf bytes.Buffer
+	buf.WriteByte(CommandSetPathVerdict)
+	buf.WriteByte(verdict)
+	binary.Write(&buf, binary.LittleEndian, uint32(len(path)))
+	buf.WriteString(path)
+	_, err := writer.Write(buf.Bytes())
+	return err
+}
@@ -1,21 +1,44 @@
 package kext_interface
 
 import (
+	"bytes"
 	"encoding/binary"
 	"errors"
 	"io"
+	"net"
 )
 
 const (
-	InfoLogLine              = 0
-	InfoConnectionIpv4       = 1
-	InfoConnectionIpv6       = 2
-	InfoConnectionEndEventV4 = 3
-	InfoConnectionEndEventV6 = 4
-	InfoBandwidthStatsV4     = 5
-	InfoBandwidthStatsV6     = 6
+	InfoLogLine                  = 0
+	InfoConnectionIpv4           = 1
+	InfoConnectionIpv6           = 2
+	InfoConnectionEndEventV4     = 3
+	InfoConnectionEndEventV6     = 4
+	InfoBandwidthStatsV4         = 5
+	InfoBandwidthStatsV6         = 6
+	InfoHandshake                = 7
+	InfoVerdictBatchResult       = 8
+	InfoCommandResult            = 9
+	InfoProcessInfo              = 10
+	InfoConnectionIpv4Ext        = 11
+	InfoConnectionIpv6Ext        = 12
+	InfoLogLinesDropped          = 13
+	InfoLogRecord                = 14
+	InfoConnectionDumpV4         = 15
+	InfoConnectionDumpV6         = 16
+	InfoConnectionDumpEnd        = 17
+	InfoMemoryStats              = 18
+	InfoRuleList                 = 19
+	InfoPendingTimeout           = 20
+	InfoQueueOverflow            = 21
+	InfoBandwidthStatsPerProcess = 22
+	InfoBandwidthStatsV4Ext      = 23
+	InfoBandwidthStatsV6Ext      = 24
 )
 
+// SupportedInfoTypes is the bitset of info types that RecvInfo can decode.
+const SupportedInfoTypes uint64 = (1 << (InfoBandwidthStatsV6Ext + 1)) - 1
+
 var ErrorUnknownInfoType = errors.New("unknown info type")
 
 type connectionV4Internal struct {
@@ -32,7 +55,12 @@ type connectionV4Internal struct {
 
 type ConnectionV4 struct {
 	connectionV4Internal
-	Payload []byte
+	// Zero if the driver did not send interface information.
+	InterfaceIndex    uint32
+	SubInterfaceIndex uint32
+	// Length of the payload before it was cut to the payload limit.
+	OriginalLength uint32
+	Payload        []byte
 }
 
 func (c *ConnectionV4) Compare(other *ConnectionV4) bool {
@@ -60,7 +88,12 @@ type connectionV6Internal struct {
 
 type ConnectionV6 struct {
 	connectionV6Internal
-	Payload []byte
+	// Zero if the driver did not send interface information.
+	InterfaceIndex    uint32
+	SubInterfaceIndex uint32
+	// Length of the payload before it was cut to the payload limit.
+	OriginalLength uint32
+	Payload        []byte
 }
 
 func (c ConnectionV6) Compare(other *ConnectionV6) bool {
@@ -74,6 +107,12 @@ func (c ConnectionV6) Compare(other *ConnectionV6) bool {
 		c.RemotePort == other.RemotePort
 }
 
+type connectionExt struct {
+	InterfaceIndex    uint32
+	SubInterfaceIndex uint32
+	OriginalLength    uint32
+}
+
 type ConnectionEndV4 struct {
 	ProcessId  uint64
 	Direction  byte
@@ -94,12 +133,226 @@ type ConnectionEndV6 struct {
 	RemotePort uint16
 }
 
+// ProcessInfo is sent before the first connection of a process.
+type ProcessInfo struct {
+	ProcessId uint64
+	Path      string
+}
+
+// Make sure this is in sync with the Rust version.
+const (
+	SeverityTrace    = 1
+	SeverityDebug    = 2
+	SeverityInfo     = 3
+	SeverityWarning  = 4
+	SeverityError    = 5
+	SeverityCritical = 6
+	SeverityDisabled = 7
+)
+
 type LogLine struct {
 	Severity byte
 	Line     string
 }
 
+// LogRecord is a structured log line. Timestamp is the Windows system time in milliseconds.
+// Irql is 0xFF if the kext could not read it.
+type LogRecord struct {
+	Timestamp uint64
+	Severity  byte
+	// Truncated is set if the driver cut the message or a field.
+	Truncated bool
+	Cpu     uint32
+	Irql    uint8
+	File    string
+	Line    uint32
+	Message string
+	Fields  []LogField
+}
+
+// LogField is a key/value pair of a log record. Value is a uint64, int64, string or LogConnection.
+type LogField struct {
+	Key   string
+	Value any
+}
+
+type LogConnection struct {
+	Protocol   uint8
+	LocalIp    net.IP
+	LocalPort  uint16
+	RemoteIp   net.IP
+	RemotePort uint16
+}
+
+const (
+	logValueU64        = 0
+	logValueI64        = 1
+	logValueString     = 2
+	logValueConnection = 3
+	logRecordTruncated = 1
+)
+
+type logRecordHeader struct {
+	Timestamp uint64
+	Severity  byte
+	Flags     uint8
+	Cpu       uint32
+	Irql      uint8
+	Line      uint32
+}
+
+func readString16(reader io.Reader) (string, error) {
+	var size uint16
+	err := binary.Read(reader, binary.LittleEndian, &size)
+	if err != nil {
+		return "", err
+	}
+	value := make([]byte, size)
+	_, err = io.ReadFull(reader, value)
+	return string(value), err
+}
+
+func readLogIp(reader io.Reader) (net.IP, error) {
+	var version uint8
+	err := binary.Read(reader, binary.LittleEndian, &version)
+	if err != nil {
+		return nil, err
+	}
+	var ip net.IP
+	switch version {
+	case 4:
+		ip = make(net.IP, 4)
+	case 6:
+		ip = make(net.IP, 16)
+	default:
+		return nil, errors.New("invalid ip version")
+	}
+	_, err = io.ReadFull(reader, ip)
+	return ip, err
+}
+
+func readLogRecord(reader io.Reader) (*LogRecord, error) {
+	var header logRecordHeader
+	err := binary.Read(reader, binary.LittleEndian, &header)
+	if err != nil {
+		return nil, err
+	}
+	record := LogRecord{
+		Timestamp: header.Timestamp,
+		Severity:  header.Severity,
+		Truncated: header.Flags&logRecordTruncated != 0,
+		Cpu:       header.Cpu,
+		Irql:      header.Irql,
+		Line:      header.Line,
+	}
+	record.File, err = readString16(reader)
+	if err != nil {
+		return nil, err
+	}
+	record.Message, err = readString16(reader)
+	if err != nil {
+		return nil, err
+	}
+
+	var count uint8
+	err = binary.Read(reader, binary.LittleEndian, &count)
+	if err != nil {
+		return nil, err
+	}
+	record.Fields = make([]LogField, count)
+	for i := range record.Fields {
+		var keySize uint8
+		err = binary.Read(reader, binary.LittleEndian, &keySize)
+		if err != nil {
+			return nil, err
+		}
+		key := make([]byte, keySize)
+		_, err = io.ReadFull(reader, key)
+		if err != nil {
+			return nil, err
+		}
+		record.Fields[i].Key = string(key)
+
+		var kind uint8
+		err = binary.Read(reader, binary.LittleEndian, &kind)
+		if err != nil {
+			return nil, err
+		}
+		switch kind {
+		case logValueU64:
+			var value uint64
+			err = binary.Read(reader, binary.LittleEndian, &value)
+			record.Fields[i].Value = value
+		case logValueI64:
+			var value int64
+			err = binary.Read(reader, binary.LittleEndian, &value)
+			record.Fields[i].Value = value
+		case logValueString:
+			record.Fields[i].Value, err = readString16(reader)
+		case logValueConnection:
+			var conn LogConnection
+			err = binary.Read(reader, binary.LittleEndian, &conn.Protocol)
+			if err == nil {
+				conn.LocalIp, err = readLogIp(reader)
+			}
+			if err == nil {
+				err = binary.Read(reader, binary.LittleEndian, &conn.LocalPort)
+			}
+			if err == nil {
+				conn.RemoteIp, err = readLogIp(reader)
+			}
+			if err == nil {
+				err = binary.Read(reader, binary.LittleEndian, &conn.RemotePort)
+			}
+			record.Fields[i].Value = conn
+		default:
+			return nil, errors.New("invalid log field type")
+		}
+		if err != nil {
+			return nil, err
+		}
+	}
+	return &record, nil
+}
+
+// LogLinesDropped is the number of log lines that were lost before they were sent.
+type LogLinesDropped struct {
+	Count uint32
+}
+
+// BandwidthValueV4 is the traffic of a connection since the last bandwidth stats.
+// Packets are the IP packets for datagrams and the segments with data for TCP.
+// Timestamps are the Windows system time in milliseconds. Packets and timestamps are
+// zero if the driver sent the basic bandwidth stats.
 type BandwidthValueV4 struct {
+	LocalIP            [4]byte
+	LocalPort          uint16
+	RemoteIP           [4]byte
+	RemotePort         uint16
+	TransmittedBytes   uint64
+	ReceivedBytes      uint64
+	TransmittedPackets uint64
+	ReceivedPackets    uint64
+	FirstSeenTimestamp uint64
+	LastSeenTimestamp  uint64
+}
+
+// BandwidthValueV6 is the same as BandwidthValueV4 for IPv6 connections.
+type BandwidthValueV6 struct {
+	LocalIP            [16]byte
+	LocalPort          uint16
+	RemoteIP           [16]byte
+	RemotePort         uint16
+	TransmittedBytes   uint64
+	ReceivedBytes      uint64
+	TransmittedPackets uint64
+	ReceivedPackets    uint64
+	FirstSeenTimestamp uint64
+	LastSeenTimestamp  uint64
+}
+
+// bandwidthValueV4Basic is a value of the basic bandwidth stats, without packets and timestamps.
+type bandwidthValueV4Basic struct {
 	LocalIP          [4]byte
 	LocalPort        uint16
 	RemoteIP         [4]byte
@@ -108,7 +361,7 @@ type BandwidthValueV4 struct {
 	ReceivedBytes    uint64
 }
 
-type BandwidthValueV6 struct {
+type bandwidthValueV6Basic struct {
 	LocalIP          [16]byte
 	LocalPort        uint16
 	RemoteIP         [16]byte
@@ -123,13 +376,153 @@ type BandwidthStatsArray struct {
 	ValuesV6 []BandwidthValueV6
 }
 
+// BandwidthValueProcess is the number of bytes that a process sent and received over
+// one protocol since the last bandwidth stats.
+type BandwidthValueProcess struct {
+	ProcessId        uint64
+	Protocol         uint8
+	TransmittedBytes uint64
+	ReceivedBytes    uint64
+}
+
+// ConnectionDumpValueV4 is a connection from the connection cache.
+// Timestamps are the Windows system time in milliseconds. EndTimestamp is 0 for active connections.
+type ConnectionDumpValueV4 struct {
+	Protocol              uint8
+	LocalIp               [4]byte
+	LocalPort             uint16
+	RemoteIp              [4]byte
+	RemotePort            uint16
+	Direction             uint8
+	Verdict               uint8
+	ProcessId             uint64
+	LastAccessedTimestamp uint64
+	EndTimestamp          uint64
+}
+
+type ConnectionDumpValueV6 struct {
+	Protocol              uint8
+	LocalIp               [16]byte
+	LocalPort             uint16
+	RemoteIp              [16]byte
+	RemotePort            uint16
+	Direction             uint8
+	Verdict               uint8
+	ProcessId             uint64
+	LastAccessedTimestamp uint64
+	EndTimestamp          uint64
+}
+
+// ConnectionDump is a part of the reply to the DumpConnections command.
+type ConnectionDump struct {
+	ValuesV4 []ConnectionDumpValueV4
+	ValuesV6 []ConnectionDumpValueV6
+}
+
+// ConnectionDumpEnd is sent after the last part of a connection dump.
+// Count is the number of connections in all parts.
+type ConnectionDumpEnd struct {
+	Count uint32
+}
+
+// MemoryStats is the reply to the print memory stats command.
+// Timestamp is the Windows system time in milliseconds.
+type MemoryStats struct {
+	Timestamp         uint64
+	IdCacheEntries    uint64
+	ConnectionsV4     uint64
+	ConnectionsV6     uint64
+	BandwidthTCPV4    uint64
+	BandwidthTCPV6    uint64
+	BandwidthUDPV4    uint64
+	BandwidthUDPV6    uint64
+	EventQueueDepth   uint64
+	ReadLeftoverBytes uint64
+	AllocatedBytes    uint64
+	AllocationCount   uint64
+	TotalAllocations  uint64
+	BandwidthProcess  uint64
+	BandwidthOtherV4  uint64
+	BandwidthOtherV6  uint64
+}
+
+// DriverHandshake is the reply to the handshake command.
+// Bit n of Commands and InfoTypes is set for the command or info type with value n.
+type DriverHandshake struct {
+	Version   uint32
+	Commands  uint64
+	InfoTypes uint64
+}
+
+// Make sure this is in sync with the Rust version.
+const (
+	VerdictErrorUnknownId      = 1
+	VerdictErrorInvalidVerdict = 2
+	VerdictErrorInjectFailed   = 3
+)
+
+// VerdictFailure is an entry of a verdict batch that was not applied.
+type VerdictFailure struct {
+	Id    uint64
+	Error uint8
+}
+
+// VerdictBatchResult lists the entries of a verdict batch that were not applied.
+type VerdictBatchResult struct {
+	Failures []VerdictFailure
+}
+
+// Make sure this is in sync with the Rust version.
+const (
+	ResultSuccess        = 0
+	ResultInvalidCommand = 1
+	ResultUnknownId      = 2
+	ResultInvalidVerdict = 3
+	ResultInjectFailed   = 4
+	ResultFailed         = 5
+)
+
+// CommandResult is the reply to a command that was sent with SendRequest.
+type CommandResult struct {
+	RequestId uint64
+	Code      uint8
+	Reason    string
+}
+
+// PendingTimeout lists the pending packets that got no verdict before the timeout.
+// The driver handled them with Verdict. See SendSetPendingTimeoutCommand.
+type PendingTimeout struct {
+	Verdict uint8
+	Ids     []uint64
+}
+
+// QueueOverflow is the number of pending packets and infos that the driver dropped
+// since the last report, because a queue limit was reached. See SendSetQueueLimitsCommand.
+type QueueOverflow struct {
+	PendingPackets uint32
+	Events         uint32
+}
+
 type Info struct {
-	ConnectionV4    *ConnectionV4
-	ConnectionV6    *ConnectionV6
-	ConnectionEndV4 *ConnectionEndV4
-	ConnectionEndV6 *ConnectionEndV6
-	LogLine         *LogLine
-	BandwidthStats  *BandwidthStatsArray
+	ConnectionV4        *ConnectionV4
+	ConnectionV6        *ConnectionV6
+	ConnectionEndV4     *ConnectionEndV4
+	ConnectionEndV6     *ConnectionEndV6
+	LogLine             *LogLine
+	BandwidthStats      *BandwidthStatsArray
+	Handshake           *DriverHandshake
+	VerdictBatchResult  *VerdictBatchResult
+	CommandResult       *CommandResult
+	ProcessInfo         *ProcessInfo
+	LogLinesDropped     *LogLinesDropped
+	LogRecord           *LogRecord
+	ConnectionDump      *ConnectionDump
+	ConnectionDumpEnd   *ConnectionDumpEnd
+	MemoryStats         *MemoryStats
+	RuleList            []Rule
+	PendingTimeout      *PendingTimeout
+	QueueOverflow       *QueueOverflow
+	BandwidthPerProcess []BandwidthValueProcess
 }
 
 func RecvInfo(reader io.Reader) (*Info, error) {
@@ -145,37 +538,57 @@ func RecvInfo(reader io.Reader) (*Info, error) {
 
 	// Read data
 	switch infoType {
-	case InfoConnectionIpv4:
+	case InfoConnectionIpv4, InfoConnectionIpv4Ext:
 		{
 			var fixedSizeValues connectionV4Internal
 			err = binary.Read(reader, binary.LittleEndian, &fixedSizeValues)
 			if err != nil {
 				return nil, err
 			}
+			var ext connectionExt
+			if infoType == InfoConnectionIpv4Ext {
+				err = binary.Read(reader, binary.LittleEndian, &ext)
+				if err != nil {
+					return nil, err
+				}
+			}
 			// Read size of payload
 			var size uint32
 			err = binary.Read(reader, binary.LittleEndian, &size)
 			if err != nil {
 				return nil, err
 			}
-			newInfo := ConnectionV4{connectionV4Internal: fixedSizeValues, Payload: make([]byte, size)}
+			if infoType != InfoConnectionIpv4Ext {
+				ext.OriginalLength = size
+			}
+			newInfo := ConnectionV4{connectionV4Internal: fixedSizeValues, InterfaceIndex: ext.InterfaceIndex, SubInterfaceIndex: ext.SubInterfaceIndex, OriginalLength: ext.OriginalLength, Payload: make([]byte, size)}
 			err = binary.Read(reader, binary.LittleEndian, &newInfo.Payload)
 			return &Info{ConnectionV4: &newInfo}, nil
 		}
-	case InfoConnectionIpv6:
+	case InfoConnectionIpv6, InfoConnectionIpv6Ext:
 		{
 			var fixedSizeValues connectionV6Internal
 			err = binary.Read(reader, binary.LittleEndian, &fixedSizeValues)
 			if err != nil {
 				return nil, err
 			}
+			var ext connectionExt
+			if infoType == InfoConnectionIpv6Ext {
+				err = binary.Read(reader, binary.LittleEndian, &ext)
+				if err != nil {
+					return nil, err
+				}
+			}
 			// Read size of payload
 			var size uint32
 			err = binary.Read(reader, binary.LittleEndian, &size)
 			if err != nil {
 				return nil, err
 			}
-			newInfo := ConnectionV6{connectionV6Internal: fixedSizeValues, Payload: make([]byte, size)}
+			if infoType != InfoConnectionIpv6Ext {
+				ext.OriginalLength = size
+			}
+			newInfo := ConnectionV6{connectionV6Internal: fixedSizeValues, InterfaceIndex: ext.InterfaceIndex, SubInterfaceIndex: ext.SubInterfaceIndex, OriginalLength: ext.OriginalLength, Payload: make([]byte, size)}
 			err = binary.Read(reader, binary.LittleEndian, &newInfo.Payload)
 			return &Info{ConnectionV6: &newInfo}, nil
 		}
@@ -211,7 +624,7 @@ func RecvInfo(reader io.Reader) (*Info, error) {
 			logLine.Line = string(line)
 			return &Info{LogLine: &logLine}, nil
 		}
-	case InfoBandwidthStatsV4:
+	case InfoBandwidthStatsV4, InfoBandwidthStatsV4Ext:
 		{
 			// Read Protocol
 			var protocol uint8
@@ -228,12 +641,25 @@ func RecvInfo(reader io.Reader) (*Info, error) {
 			// Read array
 			var stats_array = make([]BandwidthValueV4, size)
 			for i := 0; i < int(size); i++ {
-				binary.Read(reader, binary.LittleEndian, &stats_array[i])
+				if infoType == InfoBandwidthStatsV4Ext {
+					binary.Read(reader, binary.LittleEndian, &stats_array[i])
+					continue
+				}
+				var basic bandwidthValueV4Basic
+				binary.Read(reader, binary.LittleEndian, &basic)
+				stats_array[i] = BandwidthValueV4{
+					LocalIP:          basic.LocalIP,
+					LocalPort:        basic.LocalPort,
+					RemoteIP:         basic.RemoteIP,
+					RemotePort:       basic.RemotePort,
+					TransmittedBytes: basic.TransmittedBytes,
+					ReceivedBytes:    basic.ReceivedBytes,
+				}
 			}
 
 			return &Info{BandwidthStats: &BandwidthStatsArray{Protocol: protocol, ValuesV4: stats_array}}, nil
 		}
-	case InfoBandwidthStatsV6:
+	case InfoBandwidthStatsV6, InfoBandwidthStatsV6Ext:
 		{
 			// Read Protocol
 			var protocol uint8
@@ -250,11 +676,213 @@ func RecvInfo(reader io.Reader) (*Info, error) {
 			// Read array
 			var stats_array = make([]BandwidthValueV6, size)
 			for i := 0; i < int(size); i++ {
-				binary.Read(reader, binary.LittleEndian, &stats_array[i])
+				if infoType == InfoBandwidthStatsV6Ext {
+					binary.Read(reader, binary.LittleEndian, &stats_array[i])
+					continue
+				}
+				var basic bandwidthValueV6Basic
+				binary.Read(reader, binary.LittleEndian, &basic)
+				stats_array[i] = BandwidthValueV6{
+					LocalIP:          basic.LocalIP,
+					LocalPort:        basic.LocalPort,
+					RemoteIP:         basic.RemoteIP,
+					RemotePort:       basic.RemotePort,
+					TransmittedBytes: basic.TransmittedBytes,
+					ReceivedBytes:    basic.ReceivedBytes,
+				}
 			}
 
 			return &Info{BandwidthStats: &BandwidthStatsArray{Protocol: protocol, ValuesV6: stats_array}}, nil
 		}
+	case InfoHandshake:
+		{
+			var handshake DriverHandshake
+			err = binary.Read(reader, binary.LittleEndian, &handshake)
+			if err != nil {
+				return nil, err
+			}
+			return &Info{Handshake: &handshake}, nil
+		}
+	case InfoVerdictBatchResult:
+		{
+			// Read size of array
+			var size uint32
+			err = binary.Read(reader, binary.LittleEndian, &size)
+			if err != nil {
+				return nil, err
+			}
+			// Read array
+			var failures = make([]VerdictFailure, size)
+			for i := 0; i < int(size); i++ {
+				binary.Read(reader, binary.LittleEndian, &failures[i])
+			}
+			return &Info{VerdictBatchResult: &VerdictBatchResult{Failures: failures}}, nil
+		}
+	case InfoCommandResult:
+		{
+			var result = CommandResult{}
+			err = binary.Read(reader, binary.LittleEndian, &result.RequestId)
+			if err != nil {
+				return nil, err
+			}
+			err = binary.Read(reader, binary.LittleEndian, &result.Code)
+			if err != nil {
+				return nil, err
+			}
+			// Read string
+			var reason = make([]byte, size-9) // -9 for the request id and the code.
+			err = binary.Read(reader, binary.LittleEndian, &reason)
+			result.Reason = string(reason)
+			return &Info{CommandResult: &result}, nil
+		}
+	case InfoProcessInfo:
+		{
+			var processInfo = ProcessInfo{}
+			err = binary.Read(reader, binary.LittleEndian, &processInfo.ProcessId)
+			if err != nil {
+				return nil, err
+			}
+			// Read string
+			var path = make([]byte, size-8) // -8 for the process id.
+			err = binary.Read(reader, binary.LittleEndian, &path)
+			processInfo.Path = string(path)
+			return &Info{ProcessInfo: &processInfo}, nil
+		}
+	case InfoLogLinesDropped:
+		{
+			var dropped LogLinesDropped
+			err = binary.Read(reader, binary.LittleEndian, &dropped)
+			if err != nil {
+				return nil, err
+			}
+			return &Info{LogLinesDropped: &dropped}, nil
+		}
+	case InfoConnectionDumpV4:
+		{
+			// Read size of array
+			var size uint32
+			err = binary.Read(reader, binary.LittleEndian, &size)
+			if err != nil {
+				return nil, err
+			}
+			// Read array
+			var values = make([]ConnectionDumpValueV4, size)
+			err = binary.Read(reader, binary.LittleEndian, values)
+			if err != nil {
+				return nil, err
+			}
+			return &Info{ConnectionDump: &ConnectionDump{ValuesV4: values}}, nil
+		}
+	case InfoConnectionDumpV6:
+		{
+			// Read size of array
+			var size uint32
+			err = binary.Read(reader, binary.LittleEndian, &size)
+			if err != nil {
+				return nil, err
+			}
+			// Read array
+			var values = make([]ConnectionDumpValueV6, size)
+			err = binary.Read(reader, binary.LittleEndian, values)
+			if err != nil {
+				return nil, err
+			}
+			return &Info{ConnectionDump: &ConnectionDump{ValuesV6: values}}, nil
+		}
+	case InfoConnectionDumpEnd:
+		{
+			var end ConnectionDumpEnd
+			err = binary.Read(reader, binary.LittleEndian, &end)
+			if err != nil {
+				return nil, err
+			}
+			return &Info{ConnectionDumpEnd: &end}, nil
+		}
+	case InfoMemoryStats:
+		{
+			var stats MemoryStats
+			err = binary.Read(reader, binary.LittleEndian, &stats)
+			if err != nil {
+				return nil, err
+			}
+			return &Info{MemoryStats: &stats}, nil
+		}
+	case InfoRuleList:
+		{
+			// Read size of array
+			var size uint32
+			err = binary.Read(reader, binary.LittleEndian, &size)
+			if err != nil {
+				return nil, err
+			}
+			// Read array
+			var rules = make([]Rule, size)
+			err = binary.Read(reader, binary.LittleEndian, rules)
+			if err != nil {
+				return nil, err
+			}
+			return &Info{RuleList: rules}, nil
+		}
+	case InfoPendingTimeout:
+		{
+			var timeout PendingTimeout
+			err = binary.Read(reader, binary.LittleEndian, &timeout.Verdict)
+			if err != nil {
+				return nil, err
+			}
+			// Read size of array
+			var size uint32
+			err = binary.Read(reader, binary.LittleEndian, &size)
+			if err != nil {
+				return nil, err
+			}
+			// Read array
+			timeout.Ids = make([]uint64, size)
+			err = binary.Read(reader, binary.LittleEndian, timeout.Ids)
+			if err != nil {
+				return nil, err
+			}
+			return &Info{PendingTimeout: &timeout}, nil
+		}
+	case InfoQueueOverflow:
+		{
+			var overflow QueueOverflow
+			err = binary.Read(reader, binary.LittleEndian, &overflow)
+			if err != nil {
+				return nil, err
+			}
+			return &Info{QueueOverflow: &overflow}, nil
+		}
+	case InfoBandwidthStatsPerProcess:
+		{
+			// Read size of array
+			var size uint32
+			err = binary.Read(reader, binary.LittleEndian, &size)
+			if err != nil {
+				return nil, err
+			}
+			// Read array
+			var values = make([]BandwidthValueProcess, size)
+			err = binary.Read(reader, binary.LittleEndian, values)
+			if err != nil {
+				return nil, err
+			}
+			return &Info{BandwidthPerProcess: values}, nil
+		}
+	case InfoLogRecord:
+		{
+			// Read the whole frame, so a bad record does not break the stream.
+			var data = make([]byte, size)
+			_, err = io.ReadFull(reader, data)
+			if err != nil {
+				return nil, err
+			}
+			record, err := readLogRecord(bytes.NewReader(data))
+			if err != nil {
+				return nil, err
+			}
+			return &Info{LogRecord: record}, nil
+		}
 	}
 
 	unknownData := make([]byte, size)
@@ -4,10 +4,25 @@ import (
 	"bytes"
 	"io"
 	"math/rand"
+	"net"
 	"os"
+	"reflect"
 	"testing"
 )
 
+// testRule must match test_rule in protocol/src/rules.rs.
+var testRule = Rule{
+	Id:              1,
+	Protocol:        6,
+	Direction:       0,
+	IpVersion:       4,
+	RemoteAddress:   [16]byte{10, 1},
+	PrefixLength:    16,
+	RemotePortStart: 80,
+	RemotePortEnd:   443,
+	Verdict:         5,
+}
+
 func TestRustInfoFile(t *testing.T) {
 	file, err := os.Open("../protocol/rust_info_test.bin")
 	if err != nil {
@@ -49,6 +64,11 @@ func TestRustInfoFile(t *testing.T) {
 			if !bytes.Equal(conn.Payload, []byte{1, 2, 3, 4, 5, 6, 7, 8, 9, 10}) {
 				t.Errorf("unexpected ConnectionV4 payload: %+v\n", conn.Payload)
 			}
+			// Zero for the basic info, set for the extended info.
+			if !(conn.InterfaceIndex == 0 && conn.SubInterfaceIndex == 0 && conn.OriginalLength == 10) &&
+				!(conn.InterfaceIndex == 8 && conn.SubInterfaceIndex == 9 && conn.OriginalLength == 20) {
+				t.Errorf("unexpected ConnectionV4 extension: %d %d %d\n", conn.InterfaceIndex, conn.SubInterfaceIndex, conn.OriginalLength)
+			}
 		} else if info.ConnectionV6 != nil {
 			conn := info.ConnectionV6
 			expected := connectionV6Internal{
@@ -68,6 +88,11 @@ func TestRustInfoFile(t *testing.T) {
 			if !bytes.Equal(conn.Payload, []byte{1, 2, 3, 4, 5, 6, 7, 8, 9, 10}) {
 				t.Errorf("unexpected ConnectionV6 payload: %+v\n", conn.Payload)
 			}
+			// Zero for the basic info, set for the extended info.
+			if !(conn.InterfaceIndex == 0 && conn.SubInterfaceIndex == 0 && conn.OriginalLength == 10) &&
+				!(conn.InterfaceIndex == 8 && conn.SubInterfaceIndex == 9 && conn.OriginalLength == 20) {
+				t.Errorf("unexpected ConnectionV6 extension: %d %d %d\n", conn.InterfaceIndex, conn.SubInterfaceIndex, conn.OriginalLength)
+			}
 		} else if info.ConnectionEndV4 != nil {
 			endEvent := info.ConnectionEndV4
 			expected := ConnectionEndV4{
@@ -96,6 +121,140 @@ func TestRustInfoFile(t *testing.T) {
 			if *endEvent != expected {
 				t.Errorf("unexpected ConnectionEndV6: %+v\n", endEvent)
 			}
+		} else if info.Handshake != nil {
+			expected := DriverHandshake{
+				Version:   1,
+				Commands:  2,
+				InfoTypes: 3,
+			}
+			if *info.Handshake != expected {
+				t.Errorf("unexpected Handshake: %+v\n", info.Handshake)
+			}
+		} else if info.VerdictBatchResult != nil {
+			expected := []VerdictFailure{
+				{Id: 1, Error: VerdictErrorUnknownId},
+				{Id: 2, Error: VerdictErrorInvalidVerdict},
+				{Id: 3, Error: VerdictErrorInjectFailed},
+			}
+			if !reflect.DeepEqual(info.VerdictBatchResult.Failures, expected) {
+				t.Errorf("unexpected VerdictFailures: %+v\n", info.VerdictBatchResult.Failures)
+			}
+		} else if info.CommandResult != nil {
+			expected := CommandResult{
+				RequestId: 1,
+				Code:      ResultUnknownId,
+				Reason:    "test reason",
+			}
+			if *info.CommandResult != expected {
+				t.Errorf("unexpected CommandResult: %+v\n", info.CommandResult)
+			}
+		} else if info.ProcessInfo != nil {
+			expected := ProcessInfo{
+				ProcessId: 1,
+				Path:      "C:\\Windows\\System32\\svchost.exe",
+			}
+			if *info.ProcessInfo != expected {
+				t.Errorf("unexpected ProcessInfo: %+v\n", info.ProcessInfo)
+			}
+		} else if info.LogRecord != nil {
+			expected := LogRecord{
+				Timestamp: 1,
+				Severity:  SeverityWarning,
+				Cpu:       2,
+				Irql:      3,
+				File:      "file.rs",
+				Line:      4,
+				Message:   "test message",
+				Fields: []LogField{
+					{Key: "pid", Value: uint64(5)},
+					{Key: "delta", Value: int64(-6)},
+					{Key: "verdict", Value: "Accept"},
+					{Key: "key", Value: LogConnection{
+						Protocol:   6,
+						LocalIp:    net.IP{1, 2, 3, 4},
+						LocalPort:  7,
+						RemoteIp:   net.IP{2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17},
+						RemotePort: 8,
+					}},
+				},
+			}
+			if !reflect.DeepEqual(*info.LogRecord, expected) {
+				t.Errorf("unexpected LogRecord: %+v\n", info.LogRecord)
+			}
+		} else if info.LogLinesDropped != nil {
+			if info.LogLinesDropped.Count != 1 {
+				t.Errorf("unexpected LogLinesDropped: %+v\n", info.LogLinesDropped)
+			}
+		} else if info.ConnectionDump != nil {
+			dump := info.ConnectionDump
+			if dump.ValuesV4 != nil {
+				value := ConnectionDumpValueV4{
+					Protocol:              1,
+					LocalIp:               [4]byte{1, 2, 3, 4},
+					LocalPort:             2,
+					RemoteIp:              [4]byte{2, 3, 4, 5},
+					RemotePort:            3,
+					Direction:             4,
+					Verdict:               5,
+					ProcessId:             6,
+					LastAccessedTimestamp: 7,
+					EndTimestamp:          8,
+				}
+				ended := value
+				value.EndTimestamp = 0
+				if !reflect.DeepEqual(dump.ValuesV4, []ConnectionDumpValueV4{ended, value}) {
+					t.Errorf("unexpected ConnectionDump: %+v\n", dump.ValuesV4)
+				}
+			} else {
+				value := ConnectionDumpValueV6{
+					Protocol:              1,
+					LocalIp:               [16]byte{1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16},
+					LocalPort:             2,
+					RemoteIp:              [16]byte{2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17},
+					RemotePort:            3,
+					Direction:             4,
+					Verdict:               5,
+					ProcessId:             6,
+					LastAccessedTimestamp: 7,
+					EndTimestamp:          8,
+				}
+				ended := value
+				value.EndTimestamp = 0
+				if !reflect.DeepEqual(dump.ValuesV6, []ConnectionDumpValueV6{ended, value}) {
+					t.Errorf("unexpected ConnectionDump: %+v\n", dump.ValuesV6)
+				}
+			}
+		} else if info.ConnectionDumpEnd != nil {
+			if info.ConnectionDumpEnd.Count != 4 {
+				t.Errorf("unexpected ConnectionDumpEnd: %+v\n", info.ConnectionDumpEnd)
+			}
+		} else if info.RuleList != nil {
+			if !reflect.DeepEqual(info.RuleList, []Rule{testRule, testRule}) {
+				t.Errorf("unexpected RuleList: %+v\n", info.RuleList)
+			}
+		} else if info.PendingTimeout != nil {
+			expected := PendingTimeout{Verdict: 2, Ids: []uint64{1, 2, 3}}
+			if !reflect.DeepEqual(*info.PendingTimeout, expected) {
+				t.Errorf("unexpected PendingTimeout: %+v\n", info.PendingTimeout)
+			}
+		} else if info.QueueOverflow != nil {
+			expected := QueueOverflow{PendingPackets: 1, Events: 2}
+			if *info.QueueOverflow != expected {
+				t.Errorf("unexpected QueueOverflow: %+v\n", info.QueueOverflow)
+			}
+		} else if info.BandwidthPerProcess != nil {
+			expected := []BandwidthValueProcess{
+				{ProcessId: 1, Protocol: 6, TransmittedBytes: 2, ReceivedBytes: 3},
+				{ProcessId: 4, Protocol: 17, TransmittedBytes: 5, ReceivedBytes: 6},
+			}
+			if !reflect.DeepEqual(info.BandwidthPerProcess, expected) {
+				t.Errorf("unexpected BandwidthPerProcess: %+v\n", info.BandwidthPerProcess)
+			}
+		} else if info.MemoryStats != nil {
+			expected := MemoryStats{1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16}
+			if *info.MemoryStats != expected {
+				t.Errorf("unexpected MemoryStats: %+v\n", info.MemoryStats)
+			}
 		} else if info.BandwidthStats != nil {
 			stats := info.BandwidthStats
 			if stats.Protocol != 1 {
@@ -107,25 +266,39 @@ func TestRustInfoFile(t *testing.T) {
 					t.Errorf("unexpected Bandwidth stats value length: %d\n", len(stats.ValuesV4))
 				}
 				expected1 := BandwidthValueV4{
-					LocalIP:          [4]byte{1, 2, 3, 4},
-					LocalPort:        1,
-					RemoteIP:         [4]byte{2, 3, 4, 5},
-					RemotePort:       2,
-					TransmittedBytes: 3,
-					ReceivedBytes:    4,
-				}
-				if stats.ValuesV4[0] != expected1 {
+					LocalIP:            [4]byte{1, 2, 3, 4},
+					LocalPort:          1,
+					RemoteIP:           [4]byte{2, 3, 4, 5},
+					RemotePort:         2,
+					TransmittedBytes:   3,
+					ReceivedBytes:      4,
+					TransmittedPackets: 9,
+					ReceivedPackets:    10,
+					FirstSeenTimestamp: 11,
+					LastSeenTimestamp:  12,
+				}
+				// Packets and timestamps are zero for the basic info, set for the extended info.
+				basic := func(value BandwidthValueV4) BandwidthValueV4 {
+					value.TransmittedPackets, value.ReceivedPackets = 0, 0
+					value.FirstSeenTimestamp, value.LastSeenTimestamp = 0, 0
+					return value
+				}
+				if stats.ValuesV4[0] != expected1 && stats.ValuesV4[0] != basic(expected1) {
 					t.Errorf("unexpected Bandwidth stats value: %+v expected: %+v\n", stats.ValuesV4[0], expected1)
 				}
 				expected2 := BandwidthValueV4{
-					LocalIP:          [4]byte{1, 2, 3, 4},
-					LocalPort:        5,
-					RemoteIP:         [4]byte{2, 3, 4, 5},
-					RemotePort:       6,
-					TransmittedBytes: 7,
-					ReceivedBytes:    8,
-				}
-				if stats.ValuesV4[1] != expected2 {
+					LocalIP:            [4]byte{1, 2, 3, 4},
+					LocalPort:          5,
+					RemoteIP:           [4]byte{2, 3, 4, 5},
+					RemotePort:         6,
+					TransmittedBytes:   7,
+					ReceivedBytes:      8,
+					TransmittedPackets: 13,
+					ReceivedPackets:    14,
+					FirstSeenTimestamp: 15,
+					LastSeenTimestamp:  16,
+				}
+				if stats.ValuesV4[1] != expected2 && stats.ValuesV4[1] != basic(expected2) {
 					t.Errorf("unexpected Bandwidth stats value: %+v expected: %+v\n", stats.ValuesV4[1], expected2)
 				}
 
@@ -135,25 +308,39 @@ func TestRustInfoFile(t *testing.T) {
 				}
 
 				expected1 := BandwidthValueV6{
-					LocalIP:          [16]byte{1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16},
-					LocalPort:        1,
-					RemoteIP:         [16]byte{2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17},
-					RemotePort:       2,
-					TransmittedBytes: 3,
-					ReceivedBytes:    4,
-				}
-				if stats.ValuesV6[0] != expected1 {
+					LocalIP:            [16]byte{1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16},
+					LocalPort:          1,
+					RemoteIP:           [16]byte{2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17},
+					RemotePort:         2,
+					TransmittedBytes:   3,
+					ReceivedBytes:      4,
+					TransmittedPackets: 9,
+					ReceivedPackets:    10,
+					FirstSeenTimestamp: 11,
+					LastSeenTimestamp:  12,
+				}
+				// Packets and timestamps are zero for the basic info, set for the extended info.
+				basic := func(value BandwidthValueV6) BandwidthValueV6 {
+					value.TransmittedPackets, value.ReceivedPackets = 0, 0
+					value.FirstSeenTimestamp, value.LastSeenTimestamp = 0, 0
+					return value
+				}
+				if stats.ValuesV6[0] != expected1 && stats.ValuesV6[0] != basic(expected1) {
 					t.Errorf("unexpected Bandwidth stats value: %+v expected: %+v\n", stats.ValuesV6[0], expected1)
 				}
 				expected2 := BandwidthValueV6{
-					LocalIP:          [16]byte{1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16},
-					LocalPort:        5,
-					RemoteIP:         [16]byte{2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17},
-					RemotePort:       6,
-					TransmittedBytes: 7,
-					ReceivedBytes:    8,
-				}
-				if stats.ValuesV6[1] != expected2 {
+					LocalIP:            [16]byte{1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16},
+					LocalPort:          5,
+					RemoteIP:           [16]byte{2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17},
+					RemotePort:         6,
+					TransmittedBytes:   7,
+					ReceivedBytes:      8,
+					TransmittedPackets: 13,
+					ReceivedPackets:    14,
+					FirstSeenTimestamp: 15,
+					LastSeenTimestamp:  16,
+				}
+				if stats.ValuesV6[1] != expected2 && stats.ValuesV6[1] != basic(expected2) {
 					t.Errorf("unexpected Bandwidth stats value: %+v expected: %+v\n", stats.ValuesV6[1], expected2)
 				}
 
@@ -177,6 +364,24 @@ func TestGenerateCommandFile(t *testing.T) {
 		CommandGetLogs,
 		CommandBandwidthStats,
 		CommandCleanEndedConnections,
+		CommandDumpConnections,
+		CommandHandshake,
+		CommandVerdictBatch,
+		CommandSetPayloadLimit,
+		CommandSetLogLevel,
+		CommandSetLogStreaming,
+		CommandLoadVerdicts,
+		CommandAddRule,
+		CommandRemoveRule,
+		CommandListRules,
+		CommandSetProcessVerdict,
+		CommandSetPathVerdict,
+		CommandSetPendingTimeout,
+		CommandSetQueueLimits,
+		CommandSetBandwidthPush,
+		CommandSetProcessRateLimit,
+		CommandSetConnectionRateLimitV4,
+		CommandSetConnectionRateLimitV6,
 	}
 
 	selected := make([]byte, 5000)
@@ -240,6 +445,134 @@ func TestGenerateCommandFile(t *testing.T) {
 			{
 				SendCleanEndedConnectionsCommand(file)
 			}
+		case CommandDumpConnections:
+			{
+				SendDumpConnectionsCommand(file)
+			}
+		case CommandVerdictBatch:
+			{
+				SendVerdictBatchCommand(file, []Verdict{
+					{Id: 1, Verdict: 2},
+					{Id: 3, Verdict: 4},
+				})
+			}
+		case CommandSetPayloadLimit:
+			{
+				SendSetPayloadLimitCommand(file, SetPayloadLimit{
+					Protocol:  17,
+					Direction: PayloadLimitAny,
+					MaxLength: 100,
+				})
+			}
+		case CommandSetLogLevel:
+			{
+				SendSetLogLevelCommand(file, SeverityDebug)
+			}
+		case CommandSetLogStreaming:
+			{
+				SendSetLogStreamingCommand(file, 100)
+			}
+		case CommandAddRule:
+			{
+				SendAddRuleCommand(file, testRule)
+			}
+		case CommandRemoveRule:
+			{
+				SendRemoveRuleCommand(file, 3)
+			}
+		case CommandListRules:
+			{
+				SendListRulesCommand(file)
+			}
+		case CommandSetProcessVerdict:
+			{
+				SendSetProcessVerdictCommand(file, 4, 5)
+			}
+		case CommandSetPathVerdict:
+			{
+				SendSetPathVerdictCommand(file, 3, `\device\harddiskvolume1\windows\system32\svchost.exe`)
+			}
+		case CommandSetPendingTimeout:
+			{
+				SendSetPendingTimeoutCommand(file, 5000, 1)
+			}
+		case CommandSetQueueLimits:
+			{
+				SendSetQueueLimitsCommand(file, 1000, 2000, OverflowFailClosed)
+			}
+		case CommandSetBandwidthPush:
+			{
+				SendSetBandwidthPushCommand(file, 1000, 4096)
+			}
+		case CommandSetProcessRateLimit:
+			{
+				SendSetProcessRateLimitCommand(file, 4, 100000)
+			}
+		case CommandSetConnectionRateLimitV4:
+			{
+				SendSetConnectionRateLimitV4Command(file, ConnectionRateLimitV4{
+					Protocol:       6,
+					LocalAddress:   [4]byte{1, 2, 3, 4},
+					LocalPort:      2,
+					RemoteAddress:  [4]byte{2, 3, 4, 5},
+					RemotePort:     3,
+					BytesPerSecond: 100000,
+				})
+			}
+		case CommandSetConnectionRateLimitV6:
+			{
+				SendSetConnectionRateLimitV6Command(file, ConnectionRateLimitV6{
+					Protocol:       17,
+					LocalAddress:   [16]byte{1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16},
+					LocalPort:      2,
+					RemoteAddress:  [16]byte{2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17},
+					RemotePort:     3,
+					BytesPerSecond: 100000,
+				})
+			}
+		case CommandLoadVerdicts:
+			{
+				SendLoadVerdictsCommand(file, []LoadVerdictV4{
+					{
+						Protocol:      6,
+						LocalAddress:  [4]byte{1, 2, 3, 4},
+						LocalPort:     2,
+						RemoteAddress: [4]byte{2, 3, 4, 5},
+						RemotePort:    3,
+						Direction:     1,
+						Verdict:       2,
+						ProcessId:     4,
+					},
+				}, []LoadVerdictV6{
+					{
+						Protocol:      17,
+						LocalAddress:  [16]byte{1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16},
+						LocalPort:     2,
+						RemoteAddress: [16]byte{2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17},
+						RemotePort:    3,
+						Direction:     0,
+						Verdict:       4,
+						ProcessId:     5,
+					},
+					{
+						Protocol:      6,
+						LocalAddress:  [16]byte{1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16},
+						LocalPort:     6,
+						RemoteAddress: [16]byte{2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17},
+						RemotePort:    7,
+						Direction:     1,
+						Verdict:       5,
+						ProcessId:     8,
+					},
+				})
+			}
+		case CommandHandshake:
+			{
+				SendHandshakeCommand(file, Handshake{
+					Version:   1,
+					InfoTypes: 2,
+				})
+			}
 		}
 	}
 
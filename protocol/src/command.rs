@@ -1,23 +1,48 @@
 // Commands from user space
 
+use alloc::{string::String, vec::Vec};
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
 
+use crate::{reader::Reader, rules::Rule};
+
 #[repr(u8)]
-#[derive(Clone, Copy, FromPrimitive)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, FromPrimitive)]
 #[rustfmt::skip]
 pub enum CommandType {
-    Shutdown              = 0,
-    Verdict               = 1,
-    UpdateV4              = 2,
-    UpdateV6              = 3,
-    ClearCache            = 4,
-    GetLogs               = 5,
-    GetBandwidthStats     = 6,
-    PrintMemoryStats      = 7,
-    CleanEndedConnections = 8,
+    Shutdown                 = 0,
+    Verdict                  = 1,
+    UpdateV4                 = 2,
+    UpdateV6                 = 3,
+    ClearCache               = 4,
+    GetLogs                  = 5,
+    GetBandwidthStats        = 6,
+    PrintMemoryStats         = 7,
+    CleanEndedConnections    = 8,
+    Handshake                = 9,
+    VerdictBatch             = 10,
+    Request                  = 11,
+    SetPayloadLimit          = 12,
+    SetLogLevel              = 13,
+    SetLogStreaming          = 14,
+    DumpConnections          = 15,
+    LoadVerdicts             = 16,
+    AddRule                  = 17,
+    RemoveRule               = 18,
+    ListRules                = 19,
+    SetProcessVerdict        = 20,
+    SetPathVerdict           = 21,
+    SetPendingTimeout        = 22,
+    SetQueueLimits           = 23,
+    SetBandwidthPush         = 24,
+    SetProcessRateLimit      = 25,
+    SetConnectionRateLimitV4 = 26,
+    SetConnectionRateLimitV6 = 27,
 }
 
+/// Bitset of the commands this build understands. Bit `n` is set for the `CommandType` with value `n`.
+pub const SUPPORTED_COMMANDS: u64 = (1 << (CommandType::SetConnectionRateLimitV6 as u64 + 1)) - 1;
+
 #[repr(C, packed)]
 pub struct Command {
     pub command_type: CommandType,
@@ -25,14 +50,14 @@ pub struct Command {
 }
 
 #[repr(C, packed)]
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub struct Verdict {
     pub id: u64,
     pub verdict: u8,
 }
 
 #[repr(C, packed)]
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub struct UpdateV4 {
     pub protocol: u8,
     pub local_address: [u8; 4],
@@ -43,7 +68,7 @@ pub struct UpdateV4 {
 }
 
 #[repr(C, packed)]
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub struct UpdateV6 {
     pub protocol: u8,
     pub local_address: [u8; 16],
@@ -53,106 +78,1448 @@ pub struct UpdateV6 {
     pub verdict: u8,
 }
 
-pub fn parse_type(bytes: &[u8]) -> Option<CommandType> {
-    FromPrimitive::from_u8(bytes[0])
+/// Sent by the client to declare its protocol version and the info types it can decode.
+/// The driver replies with a handshake info.
+#[repr(C, packed)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Handshake {
+    pub version: u32,
+    /// Bitset of supported `InfoType`s. Bit `n` is set for the info type with value `n`.
+    pub info_types: u64,
 }
 
-pub fn parse_verdict(bytes: &[u8]) -> &Verdict {
-    as_type(bytes)
+/// Matches every protocol or direction in `SetPayloadLimit`.
+pub const PAYLOAD_LIMIT_ANY: u8 = 0xFF;
+
+/// Sets the maximum number of payload bytes copied into connection infos.
+/// `protocol` is an IP protocol number and `direction` is 0 for outbound and 1 for inbound,
+/// either can be `PAYLOAD_LIMIT_ANY`. The most specific limit is used. `u32::MAX` means no limit.
+#[repr(C, packed)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct SetPayloadLimit {
+    pub protocol: u8,
+    pub direction: u8,
+    pub max_length: u32,
 }
 
-pub fn parse_update_v4(bytes: &[u8]) -> &UpdateV4 {
-    as_type(bytes)
+/// Sets the minimum severity of log lines the driver records. `severity` is a `Severity` value,
+/// `Severity::Disabled` turns logging off.
+#[repr(C, packed)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct SetLogLevel {
+    pub severity: u8,
 }
 
-pub fn parse_update_v6(bytes: &[u8]) -> &UpdateV6 {
-    as_type(bytes)
+/// Enables forwarding of log lines to the client as they are recorded, without `GetLogs`.
+/// At most `max_lines_per_second` lines are forwarded, the rest stay in the log buffer.
+/// 0 disables streaming.
+#[repr(C, packed)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct SetLogStreaming {
+    pub max_lines_per_second: u32,
 }
 
-fn as_type<T>(bytes: &[u8]) -> &T {
-    let ptr: *const u8 = &bytes[0];
-    let t_ptr: *const T = ptr as _;
-    unsafe { t_ptr.as_ref().unwrap() }
+/// Connection with a known verdict, see `LoadVerdicts`. `direction` is 0 for outbound and 1 for inbound.
+#[repr(C, packed)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct LoadVerdictV4 {
+    pub protocol: u8,
+    pub local_address: [u8; 4],
+    pub local_port: u16,
+    pub remote_address: [u8; 4],
+    pub remote_port: u16,
+    pub direction: u8,
+    pub verdict: u8,
+    pub process_id: u64,
 }
 
+/// Connection with a known verdict, see `LoadVerdicts`. `direction` is 0 for outbound and 1 for inbound.
+#[repr(C, packed)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct LoadVerdictV6 {
+    pub protocol: u8,
+    pub local_address: [u8; 16],
+    pub local_port: u16,
+    pub remote_address: [u8; 16],
+    pub remote_port: u16,
+    pub direction: u8,
+    pub verdict: u8,
+    pub process_id: u64,
+}
+
+/// Adds connections with their verdicts to the connection cache, so packets of the connections
+/// don't have to be sent to the client again. Used after a client restart.
+/// Format: [count_v4: u32, count_v4 * LoadVerdictV4, count_v6: u32, count_v6 * LoadVerdictV6]
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct LoadVerdicts {
+    pub v4: Vec<LoadVerdictV4>,
+    pub v6: Vec<LoadVerdictV6>,
+}
+
+/// Removes the verdict rule with the given id.
+#[repr(C, packed)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct RemoveRule {
+    pub id: u32,
+}
+
+/// Sets the verdict for new connections of a process. `Undecided` (0) removes the entry.
+/// The entry is removed when the process exits. Temporary verdicts are cached as their permanent form.
+#[repr(C, packed)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct SetProcessVerdict {
+    pub process_id: u64,
+    pub verdict: u8,
+}
+
+/// Sets the verdict for new connections of every process with the given executable path.
+/// The path is the NT device path that the filter engine reports, it is compared case-insensitively.
+/// `Undecided` (0) removes the entry. Format: [verdict: u8, path_len: u32, path: utf-8]
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SetPathVerdict {
+    pub verdict: u8,
+    pub path: String,
+}
+
+/// Sets how long packets wait for a verdict. Expired packets get `verdict` and are reported
+/// with a pending timeout info. A timeout of 0 disables the expiry, this is the default.
+#[repr(C, packed)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct SetPendingTimeout {
+    pub timeout_ms: u32,
+    pub verdict: u8,
+}
+
+/// What happens to a new packet when the pending packet limit is reached.
+/// With `FailOpen` and `FailClosed` a new connection keeps the verdict of its first packet.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, FromPrimitive)]
+pub enum OverflowPolicy {
+    /// The oldest pending packet is dropped and the new packet is pended.
+    DropOldest = 0,
+    /// The new packet is accepted without asking the client.
+    FailOpen = 1,
+    /// The new packet is blocked without asking the client.
+    FailClosed = 2,
+}
+
+/// Limits the number of pending packets and of infos that wait to be read. 0 removes a limit,
+/// there are no limits until a client sets them.
+/// `overflow_policy` is an `OverflowPolicy` value. Infos that don't fit in the queue are dropped.
+/// Dropped packets and infos are reported with a queue overflow info.
+#[repr(C, packed)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct SetQueueLimits {
+    pub max_pending_packets: u32,
+    pub max_events: u32,
+    pub overflow_policy: u8,
+}
+
+/// Makes the driver send the bandwidth stats every `interval_ms` without a `GetBandwidthStats`
+/// command. An interval of 0 disables it, this is the default. Intervals longer than `i32::MAX`
+/// milliseconds are rejected. Bandwidth stats infos are split so that one info is not larger
+/// than `max_frame_size` bytes, 0 removes the limit.
+/// The limit applies to `GetBandwidthStats` too.
+#[repr(C, packed)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct SetBandwidthPush {
+    pub interval_ms: u32,
+    pub max_frame_size: u32,
+}
+
+/// Limits the bandwidth of every connection of a process to `bytes_per_second`, both directions
+/// together. 0 removes the limit. The limit is removed when the process exits.
+#[repr(C, packed)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct SetProcessRateLimit {
+    pub process_id: u64,
+    pub bytes_per_second: u64,
+}
+
+/// Limits the bandwidth of a connection to `bytes_per_second`, both directions together.
+/// 0 removes the limit. A connection limit is used instead of the limit of the process.
+#[repr(C, packed)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct SetConnectionRateLimitV4 {
+    pub protocol: u8,
+    pub local_address: [u8; 4],
+    pub local_port: u16,
+    pub remote_address: [u8; 4],
+    pub remote_port: u16,
+    pub bytes_per_second: u64,
+}
+
+/// Same as `SetConnectionRateLimitV4`, for ipv6 connections.
+#[repr(C, packed)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct SetConnectionRateLimitV6 {
+    pub protocol: u8,
+    pub local_address: [u8; 16],
+    pub local_port: u16,
+    pub remote_address: [u8; 16],
+    pub remote_port: u16,
+    pub bytes_per_second: u64,
+}
+
+/// Command with its parsed value.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ParsedCommand {
+    Shutdown,
+    Verdict(Verdict),
+    UpdateV4(UpdateV4),
+    UpdateV6(UpdateV6),
+    ClearCache,
+    GetLogs,
+    GetBandwidthStats,
+    PrintMemoryStats,
+    CleanEndedConnections,
+    Handshake(Handshake),
+    /// Verdicts for multiple pending packets. Format: [count: u32, count * Verdict]
+    VerdictBatch(Vec<Verdict>),
+    SetPayloadLimit(SetPayloadLimit),
+    SetLogLevel(SetLogLevel),
+    SetLogStreaming(SetLogStreaming),
+    /// Sends the connection cache as connection dump infos, followed by a dump end info.
+    DumpConnections,
+    LoadVerdicts(LoadVerdicts),
+    /// Adds a verdict rule or replaces the rule with the same id.
+    AddRule(Rule),
+    RemoveRule(RemoveRule),
+    /// Sends the verdict rules as a rule list info.
+    ListRules,
+    SetProcessVerdict(SetProcessVerdict),
+    SetPathVerdict(SetPathVerdict),
+    SetPendingTimeout(SetPendingTimeout),
+    SetQueueLimits(SetQueueLimits),
+    SetBandwidthPush(SetBandwidthPush),
+    SetProcessRateLimit(SetProcessRateLimit),
+    SetConnectionRateLimitV4(SetConnectionRateLimitV4),
+    SetConnectionRateLimitV6(SetConnectionRateLimitV6),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ParseError {
+    /// The write buffer was empty.
+    Empty,
+    /// First byte is not a valid `CommandType`.
+    UnknownCommand(u8),
+    /// The buffer is shorter than the command requires.
+    TooShort(CommandType),
+    /// The buffer is longer than the command requires.
+    TrailingBytes(CommandType),
+    /// A request envelope inside of a request envelope.
+    NestedRequest,
+    /// A string in the command is not valid utf-8.
+    InvalidUtf8(CommandType),
+}
+
+impl core::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ParseError::Empty => write!(f, "empty command"),
+            ParseError::UnknownCommand(t) => write!(f, "unknown command number: {}", t),
+            ParseError::TooShort(t) => write!(f, "command too short: {:?}", t),
+            ParseError::TrailingBytes(t) => write!(f, "trailing bytes after command: {:?}", t),
+            ParseError::NestedRequest => write!(f, "nested request"),
+            ParseError::InvalidUtf8(t) => write!(f, "invalid utf-8 in command: {:?}", t),
+        }
+    }
+}
+
+impl Command {
+    /// Parses a single command. `bytes` must contain exactly one command.
+    pub fn parse(bytes: &[u8]) -> Result<ParsedCommand, ParseError> {
+        let Some((&command_type, value)) = bytes.split_first() else {
+            return Err(ParseError::Empty);
+        };
+        let Some(command_type) = CommandType::from_u8(command_type) else {
+            return Err(ParseError::UnknownCommand(command_type));
+        };
+
+        let mut reader = Reader::new(value);
+        let command = match command_type {
+            CommandType::Request => return Err(ParseError::NestedRequest),
+            CommandType::Shutdown => Some(ParsedCommand::Shutdown),
+            CommandType::Verdict => parse_verdict(&mut reader).map(ParsedCommand::Verdict),
+            CommandType::UpdateV4 => parse_update_v4(&mut reader).map(ParsedCommand::UpdateV4),
+            CommandType::UpdateV6 => parse_update_v6(&mut reader).map(ParsedCommand::UpdateV6),
+            CommandType::ClearCache => Some(ParsedCommand::ClearCache),
+            CommandType::GetLogs => Some(ParsedCommand::GetLogs),
+            CommandType::GetBandwidthStats => Some(ParsedCommand::GetBandwidthStats),
+            CommandType::PrintMemoryStats => Some(ParsedCommand::PrintMemoryStats),
+            CommandType::CleanEndedConnections => Some(ParsedCommand::CleanEndedConnections),
+            CommandType::DumpConnections => Some(ParsedCommand::DumpConnections),
+            CommandType::Handshake => parse_handshake(&mut reader).map(ParsedCommand::Handshake),
+            CommandType::VerdictBatch => {
+                parse_verdict_batch(&mut reader).map(ParsedCommand::VerdictBatch)
+            }
+            CommandType::SetPayloadLimit => {
+                parse_set_payload_limit(&mut reader).map(ParsedCommand::SetPayloadLimit)
+            }
+            CommandType::SetLogLevel => {
+                parse_set_log_level(&mut reader).map(ParsedCommand::SetLogLevel)
+            }
+            CommandType::SetLogStreaming => {
+                parse_set_log_streaming(&mut reader).map(ParsedCommand::SetLogStreaming)
+            }
+            CommandType::LoadVerdicts => {
+                parse_load_verdicts(&mut reader).map(ParsedCommand::LoadVerdicts)
+            }
+            CommandType::AddRule => Rule::parse(&mut reader).map(ParsedCommand::AddRule),
+            CommandType::RemoveRule => {
+                parse_remove_rule(&mut reader).map(ParsedCommand::RemoveRule)
+            }
+            CommandType::ListRules => Some(ParsedCommand::ListRules),
+            CommandType::SetProcessVerdict => {
+                parse_set_process_verdict(&mut reader).map(ParsedCommand::SetProcessVerdict)
+            }
+            CommandType::SetPathVerdict => Some(ParsedCommand::SetPathVerdict(
+                parse_set_path_verdict(&mut reader)?,
+            )),
+            CommandType::SetPendingTimeout => {
+                parse_set_pending_timeout(&mut reader).map(ParsedCommand::SetPendingTimeout)
+            }
+            CommandType::SetQueueLimits => {
+                parse_set_queue_limits(&mut reader).map(ParsedCommand::SetQueueLimits)
+            }
+            CommandType::SetBandwidthPush => {
+                parse_set_bandwidth_push(&mut reader).map(ParsedCommand::SetBandwidthPush)
+            }
+            CommandType::SetProcessRateLimit => {
+                parse_set_process_rate_limit(&mut reader).map(ParsedCommand::SetProcessRateLimit)
+            }
+            CommandType::SetConnectionRateLimitV4 => {
+                parse_set_connection_rate_limit_v4(&mut reader)
+                    .map(ParsedCommand::SetConnectionRateLimitV4)
+            }
+            CommandType::SetConnectionRateLimitV6 => {
+                parse_set_connection_rate_limit_v6(&mut reader)
+                    .map(ParsedCommand::SetConnectionRateLimitV6)
+            }
+        };
+
+        let Some(command) = command else {
+            return Err(ParseError::TooShort(command_type));
+        };
+        if !reader.is_empty() {
+            return Err(ParseError::TrailingBytes(command_type));
+        }
+        Ok(command)
+    }
+
+    /// Parses a command that can be wrapped in a request envelope:
+    /// [CommandType::Request, request_id: u64, command...]
+    /// The request id is returned even if the wrapped command is not valid,
+    /// so the error can be reported back to the client.
+    pub fn parse_request(bytes: &[u8]) -> Request {
+        if bytes.first() != Some(&(CommandType::Request as u8)) {
+            return Request {
+                id: None,
+                command: Command::parse(bytes),
+            };
+        }
+
+        let mut reader = Reader::new(&bytes[1..]);
+        match reader.u64() {
+            Some(id) => Request {
+                id: Some(id),
+                command: Command::parse(reader.rest()),
+            },
+            None => Request {
+                id: None,
+                command: Err(ParseError::TooShort(CommandType::Request)),
+            },
+        }
+    }
+}
+
+/// Command with the id of its request envelope. Commands sent without an envelope have no id.
+/// The driver answers every request that has an id with a command result info.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Request {
+    pub id: Option<u64>,
+    pub command: Result<ParsedCommand, ParseError>,
+}
+
+impl ParsedCommand {
+    pub fn command_type(&self) -> CommandType {
+        match self {
+            ParsedCommand::Shutdown => CommandType::Shutdown,
+            ParsedCommand::Verdict(_) => CommandType::Verdict,
+            ParsedCommand::UpdateV4(_) => CommandType::UpdateV4,
+            ParsedCommand::UpdateV6(_) => CommandType::UpdateV6,
+            ParsedCommand::ClearCache => CommandType::ClearCache,
+            ParsedCommand::GetLogs => CommandType::GetLogs,
+            ParsedCommand::GetBandwidthStats => CommandType::GetBandwidthStats,
+            ParsedCommand::PrintMemoryStats => CommandType::PrintMemoryStats,
+            ParsedCommand::CleanEndedConnections => CommandType::CleanEndedConnections,
+            ParsedCommand::Handshake(_) => CommandType::Handshake,
+            ParsedCommand::VerdictBatch(_) => CommandType::VerdictBatch,
+            ParsedCommand::SetPayloadLimit(_) => CommandType::SetPayloadLimit,
+            ParsedCommand::SetLogLevel(_) => CommandType::SetLogLevel,
+            ParsedCommand::SetLogStreaming(_) => CommandType::SetLogStreaming,
+            ParsedCommand::DumpConnections => CommandType::DumpConnections,
+            ParsedCommand::LoadVerdicts(_) => CommandType::LoadVerdicts,
+            ParsedCommand::AddRule(_) => CommandType::AddRule,
+            ParsedCommand::RemoveRule(_) => CommandType::RemoveRule,
+            ParsedCommand::ListRules => CommandType::ListRules,
+            ParsedCommand::SetProcessVerdict(_) => CommandType::SetProcessVerdict,
+            ParsedCommand::SetPathVerdict(_) => CommandType::SetPathVerdict,
+            ParsedCommand::SetPendingTimeout(_) => CommandType::SetPendingTimeout,
+            ParsedCommand::SetQueueLimits(_) => CommandType::SetQueueLimits,
+            ParsedCommand::SetBandwidthPush(_) => CommandType::SetBandwidthPush,
+            ParsedCommand::SetProcessRateLimit(_) => CommandType::SetProcessRateLimit,
+            ParsedCommand::SetConnectionRateLimitV4(_) => CommandType::SetConnectionRateLimitV4,
+            ParsedCommand::SetConnectionRateLimitV6(_) => CommandType::SetConnectionRateLimitV6,
+        }
+    }
+
+    /// Serializes the command. The result can be written directly to the driver.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(1 + core::mem::size_of::<UpdateV6>());
+        bytes.push(self.command_type() as u8);
+        match self {
+            ParsedCommand::Verdict(verdict) => verdict.push(&mut bytes),
+            ParsedCommand::UpdateV4(update) => update.push(&mut bytes),
+            ParsedCommand::UpdateV6(update) => update.push(&mut bytes),
+            ParsedCommand::Handshake(handshake) => handshake.push(&mut bytes),
+            ParsedCommand::SetPayloadLimit(limit) => limit.push(&mut bytes),
+            ParsedCommand::SetLogLevel(level) => bytes.push(level.severity),
+            ParsedCommand::AddRule(rule) => rule.push(&mut bytes),
+            ParsedCommand::SetProcessVerdict(process) => {
+                let process_id = process.process_id;
+                bytes.extend_from_slice(&process_id.to_le_bytes());
+                bytes.push(process.verdict);
+            }
+            ParsedCommand::SetPathVerdict(path) => {
+                bytes.push(path.verdict);
+                bytes.extend_from_slice(&(path.path.len() as u32).to_le_bytes());
+                bytes.extend_from_slice(path.path.as_bytes());
+            }
+            ParsedCommand::SetPendingTimeout(timeout) => {
+                let timeout_ms = timeout.timeout_ms;
+                bytes.extend_from_slice(&timeout_ms.to_le_bytes());
+                bytes.push(timeout.verdict);
+            }
+            ParsedCommand::SetQueueLimits(limits) => {
+                let (max_pending_packets, max_events) =
+                    (limits.max_pending_packets, limits.max_events);
+                bytes.extend_from_slice(&max_pending_packets.to_le_bytes());
+                bytes.extend_from_slice(&max_events.to_le_bytes());
+                bytes.push(limits.overflow_policy);
+            }
+            ParsedCommand::SetBandwidthPush(push) => {
+                let (interval_ms, max_frame_size) = (push.interval_ms, push.max_frame_size);
+                bytes.extend_from_slice(&interval_ms.to_le_bytes());
+                bytes.extend_from_slice(&max_frame_size.to_le_bytes());
+            }
+            ParsedCommand::SetProcessRateLimit(limit) => {
+                let (process_id, bytes_per_second) = (limit.process_id, limit.bytes_per_second);
+                bytes.extend_from_slice(&process_id.to_le_bytes());
+                bytes.extend_from_slice(&bytes_per_second.to_le_bytes());
+            }
+            ParsedCommand::SetConnectionRateLimitV4(limit) => limit.push(&mut bytes),
+            ParsedCommand::SetConnectionRateLimitV6(limit) => limit.push(&mut bytes),
+            ParsedCommand::RemoveRule(remove) => {
+                let id = remove.id;
+                bytes.extend_from_slice(&id.to_le_bytes());
+            }
+            ParsedCommand::SetLogStreaming(streaming) => {
+                let max_lines_per_second = streaming.max_lines_per_second;
+                bytes.extend_from_slice(&max_lines_per_second.to_le_bytes());
+            }
+            ParsedCommand::VerdictBatch(verdicts) => {
+                bytes.reserve(verdicts.len() * core::mem::size_of::<Verdict>());
+                bytes.extend_from_slice(&(verdicts.len() as u32).to_le_bytes());
+                for verdict in verdicts {
+                    verdict.push(&mut bytes);
+                }
+            }
+            ParsedCommand::LoadVerdicts(load) => {
+                bytes.reserve(
+                    8 + load.v4.len() * core::mem::size_of::<LoadVerdictV4>()
+                        + load.v6.len() * core::mem::size_of::<LoadVerdictV6>(),
+                );
+                bytes.extend_from_slice(&(load.v4.len() as u32).to_le_bytes());
+                for verdict in &load.v4 {
+                    verdict.push(&mut bytes);
+                }
+                bytes.extend_from_slice(&(load.v6.len() as u32).to_le_bytes());
+                for verdict in &load.v6 {
+                    verdict.push(&mut bytes);
+                }
+            }
+            ParsedCommand::Shutdown
+            | ParsedCommand::ClearCache
+            | ParsedCommand::GetLogs
+            | ParsedCommand::GetBandwidthStats
+            | ParsedCommand::PrintMemoryStats
+            | ParsedCommand::CleanEndedConnections
+            | ParsedCommand::DumpConnections
+            | ParsedCommand::ListRules => {}
+        }
+        bytes
+    }
+
+    /// Serializes the command wrapped in a request envelope with the given id.
+    pub fn to_request_bytes(&self, request_id: u64) -> Vec<u8> {
+        let command = self.to_bytes();
+        let mut bytes = Vec::with_capacity(1 + 8 + command.len());
+        bytes.push(CommandType::Request as u8);
+        bytes.extend_from_slice(&request_id.to_le_bytes());
+        bytes.extend_from_slice(&command);
+        bytes
+    }
+}
+
+impl Verdict {
+    fn push(&self, bytes: &mut Vec<u8>) {
+        let id = self.id;
+        bytes.extend_from_slice(&id.to_le_bytes());
+        bytes.push(self.verdict);
+    }
+}
+
+impl UpdateV4 {
+    fn push(&self, bytes: &mut Vec<u8>) {
+        let (local_port, remote_port) = (self.local_port, self.remote_port);
+        bytes.push(self.protocol);
+        bytes.extend_from_slice(&self.local_address);
+        bytes.extend_from_slice(&local_port.to_le_bytes());
+        bytes.extend_from_slice(&self.remote_address);
+        bytes.extend_from_slice(&remote_port.to_le_bytes());
+        bytes.push(self.verdict);
+    }
+}
+
+impl UpdateV6 {
+    fn push(&self, bytes: &mut Vec<u8>) {
+        let (local_port, remote_port) = (self.local_port, self.remote_port);
+        bytes.push(self.protocol);
+        bytes.extend_from_slice(&self.local_address);
+        bytes.extend_from_slice(&local_port.to_le_bytes());
+        bytes.extend_from_slice(&self.remote_address);
+        bytes.extend_from_slice(&remote_port.to_le_bytes());
+        bytes.push(self.verdict);
+    }
+}
+
+impl LoadVerdictV4 {
+    fn push(&self, bytes: &mut Vec<u8>) {
+        let (local_port, remote_port, process_id) =
+            (self.local_port, self.remote_port, self.process_id);
+        bytes.push(self.protocol);
+        bytes.extend_from_slice(&self.local_address);
+        bytes.extend_from_slice(&local_port.to_le_bytes());
+        bytes.extend_from_slice(&self.remote_address);
+        bytes.extend_from_slice(&remote_port.to_le_bytes());
+        bytes.push(self.direction);
+        bytes.push(self.verdict);
+        bytes.extend_from_slice(&process_id.to_le_bytes());
+    }
+}
+
+impl LoadVerdictV6 {
+    fn push(&self, bytes: &mut Vec<u8>) {
+        let (local_port, remote_port, process_id) =
+            (self.local_port, self.remote_port, self.process_id);
+        bytes.push(self.protocol);
+        bytes.extend_from_slice(&self.local_address);
+        bytes.extend_from_slice(&local_port.to_le_bytes());
+        bytes.extend_from_slice(&self.remote_address);
+        bytes.extend_from_slice(&remote_port.to_le_bytes());
+        bytes.push(self.direction);
+        bytes.push(self.verdict);
+        bytes.extend_from_slice(&process_id.to_le_bytes());
+    }
+}
+
+impl SetConnectionRateLimitV4 {
+    fn push(&self, bytes: &mut Vec<u8>) {
+        let (local_port, remote_port, bytes_per_second) =
+            (self.local_port, self.remote_port, self.bytes_per_second);
+        bytes.push(self.protocol);
+        bytes.extend_from_slice(&self.local_address);
+        bytes.extend_from_slice(&local_port.to_le_bytes());
+        bytes.extend_from_slice(&self.remote_address);
+        bytes.extend_from_slice(&remote_port.to_le_bytes());
+        bytes.extend_from_slice(&bytes_per_second.to_le_bytes());
+    }
+}
+
+impl SetConnectionRateLimitV6 {
+    fn push(&self, bytes: &mut Vec<u8>) {
+        let (local_port, remote_port, bytes_per_second) =
+            (self.local_port, self.remote_port, self.bytes_per_second);
+        bytes.push(self.protocol);
+        bytes.extend_from_slice(&self.local_address);
+        bytes.extend_from_slice(&local_port.to_le_bytes());
+        bytes.extend_from_slice(&self.remote_address);
+        bytes.extend_from_slice(&remote_port.to_le_bytes());
+        bytes.extend_from_slice(&bytes_per_second.to_le_bytes());
+    }
+}
+
+impl Handshake {
+    fn push(&self, bytes: &mut Vec<u8>) {
+        let (version, info_types) = (self.version, self.info_types);
+        bytes.extend_from_slice(&version.to_le_bytes());
+        bytes.extend_from_slice(&info_types.to_le_bytes());
+    }
+}
+
+impl SetPayloadLimit {
+    fn push(&self, bytes: &mut Vec<u8>) {
+        let max_length = self.max_length;
+        bytes.push(self.protocol);
+        bytes.push(self.direction);
+        bytes.extend_from_slice(&max_length.to_le_bytes());
+    }
+}
+
+fn parse_verdict(reader: &mut Reader) -> Option<Verdict> {
+    Some(Verdict {
+        id: reader.u64()?,
+        verdict: reader.u8()?,
+    })
+}
+
+fn parse_update_v4(reader: &mut Reader) -> Option<UpdateV4> {
+    Some(UpdateV4 {
+        protocol: reader.u8()?,
+        local_address: reader.array()?,
+        local_port: reader.u16()?,
+        remote_address: reader.array()?,
+        remote_port: reader.u16()?,
+        verdict: reader.u8()?,
+    })
+}
+
+fn parse_update_v6(reader: &mut Reader) -> Option<UpdateV6> {
+    Some(UpdateV6 {
+        protocol: reader.u8()?,
+        local_address: reader.array()?,
+        local_port: reader.u16()?,
+        remote_address: reader.array()?,
+        remote_port: reader.u16()?,
+        verdict: reader.u8()?,
+    })
+}
+
+fn parse_handshake(reader: &mut Reader) -> Option<Handshake> {
+    Some(Handshake {
+        version: reader.u32()?,
+        info_types: reader.u64()?,
+    })
+}
+
+fn parse_verdict_batch(reader: &mut Reader) -> Option<Vec<Verdict>> {
+    let count = reader.u32()? as usize;
+    // Don't trust the count for the allocation.
+    let mut verdicts =
+        Vec::with_capacity(count.min(reader.len() / core::mem::size_of::<Verdict>()));
+    for _ in 0..count {
+        verdicts.push(parse_verdict(reader)?);
+    }
+    Some(verdicts)
+}
+
+fn parse_load_verdict_v4(reader: &mut Reader) -> Option<LoadVerdictV4> {
+    Some(LoadVerdictV4 {
+        protocol: reader.u8()?,
+        local_address: reader.array()?,
+        local_port: reader.u16()?,
+        remote_address: reader.array()?,
+        remote_port: reader.u16()?,
+        direction: reader.u8()?,
+        verdict: reader.u8()?,
+        process_id: reader.u64()?,
+    })
+}
+
+fn parse_load_verdict_v6(reader: &mut Reader) -> Option<LoadVerdictV6> {
+    Some(LoadVerdictV6 {
+        protocol: reader.u8()?,
+        local_address: reader.array()?,
+        local_port: reader.u16()?,
+        remote_address: reader.array()?,
+        remote_port: reader.u16()?,
+        direction: reader.u8()?,
+        verdict: reader.u8()?,
+        process_id: reader.u64()?,
+    })
+}
+
+fn parse_load_verdicts(reader: &mut Reader) -> Option<LoadVerdicts> {
+    // Don't trust the counts for the allocation.
+    let count = reader.u32()? as usize;
+    let mut v4 =
+        Vec::with_capacity(count.min(reader.len() / core::mem::size_of::<LoadVerdictV4>()));
+    for _ in 0..count {
+        v4.push(parse_load_verdict_v4(reader)?);
+    }
+    let count = reader.u32()? as usize;
+    let mut v6 =
+        Vec::with_capacity(count.min(reader.len() / core::mem::size_of::<LoadVerdictV6>()));
+    for _ in 0..count {
+        v6.push(parse_load_verdict_v6(reader)?);
+    }
+    Some(LoadVerdicts { v4, v6 })
+}
+
+fn parse_remove_rule(reader: &mut Reader) -> Option<RemoveRule> {
+    Some(RemoveRule { id: reader.u32()? })
+}
+
+fn parse_set_process_verdict(reader: &mut Reader) -> Option<SetProcessVerdict> {
+    Some(SetProcessVerdict {
+        process_id: reader.u64()?,
+        verdict: reader.u8()?,
+    })
+}
+
+fn parse_set_path_verdict(reader: &mut Reader) -> Result<SetPathVerdict, ParseError> {
+    let too_short = ParseError::TooShort(CommandType::SetPathVerdict);
+    let verdict = reader.u8().ok_or(too_short)?;
+    let len = reader.u32().ok_or(too_short)? as usize;
+    let path = reader.take(len).ok_or(too_short)?;
+    let path = String::from_utf8(path.to_vec())
+        .map_err(|_| ParseError::InvalidUtf8(CommandType::SetPathVerdict))?;
+    Ok(SetPathVerdict { verdict, path })
+}
+
+fn parse_set_pending_timeout(reader: &mut Reader) -> Option<SetPendingTimeout> {
+    Some(SetPendingTimeout {
+        timeout_ms: reader.u32()?,
+        verdict: reader.u8()?,
+    })
+}
+
+fn parse_set_queue_limits(reader: &mut Reader) -> Option<SetQueueLimits> {
+    Some(SetQueueLimits {
+        max_pending_packets: reader.u32()?,
+        max_events: reader.u32()?,
+        overflow_policy: reader.u8()?,
+    })
+}
+
+fn parse_set_bandwidth_push(reader: &mut Reader) -> Option<SetBandwidthPush> {
+    Some(SetBandwidthPush {
+        interval_ms: reader.u32()?,
+        max_frame_size: reader.u32()?,
+    })
+}
+
+fn parse_set_process_rate_limit(reader: &mut Reader) -> Option<SetProcessRateLimit> {
+    Some(SetProcessRateLimit {
+        process_id: reader.u64()?,
+        bytes_per_second: reader.u64()?,
+    })
+}
+
+fn parse_set_connection_rate_limit_v4(reader: &mut Reader) -> Option<SetConnectionRateLimitV4> {
+    Some(SetConnectionRateLimitV4 {
+        protocol: reader.u8()?,
+        local_address: reader.array()?,
+        local_port: reader.u16()?,
+        remote_address: reader.array()?,
+        remote_port: reader.u16()?,
+        bytes_per_second: reader.u64()?,
+    })
+}
+
+fn parse_set_connection_rate_limit_v6(reader: &mut Reader) -> Option<SetConnectionRateLimitV6> {
+    Some(SetConnectionRateLimitV6 {
+        protocol: reader.u8()?,
+        local_address: reader.array()?,
+        local_port: reader.u16()?,
+        remote_address: reader.array()?,
+        remote_port: reader.u16()?,
+        bytes_per_second: reader.u64()?,
+    })
+}
+
+fn parse_set_payload_limit(reader: &mut Reader) -> Option<SetPayloadLimit> {
+    Some(SetPayloadLimit {
+        protocol: reader.u8()?,
+        direction: reader.u8()?,
+        max_length: reader.u32()?,
+    })
+}
+
+fn parse_set_log_level(reader: &mut Reader) -> Option<SetLogLevel> {
+    Some(SetLogLevel {
+        severity: reader.u8()?,
+    })
+}
+
+fn parse_set_log_streaming(reader: &mut Reader) -> Option<SetLogStreaming> {
+    Some(SetLogStreaming {
+        max_lines_per_second: reader.u32()?,
+    })
+}
+
+/// Size of the command value. `value` is only read for variable size commands,
+/// a missing count is treated as 0.
 #[cfg(test)]
-use std::fs::File;
-#[cfg(test)]
-use std::io::Read;
-#[cfg(test)]
-use std::mem::size_of;
+fn value_size(command_type: CommandType, value: &[u8]) -> usize {
+    use core::mem::size_of;
+    match command_type {
+        CommandType::VerdictBatch => {
+            let count = match value.get(..4) {
+                Some(count) => u32::from_le_bytes(count.try_into().unwrap()) as usize,
+                None => 0,
+            };
+            4 + count * size_of::<Verdict>()
+        }
+        CommandType::LoadVerdicts => {
+            let count = |offset: usize| match value.get(offset..offset + 4) {
+                Some(count) => u32::from_le_bytes(count.try_into().unwrap()) as usize,
+                None => 0,
+            };
+            let size_v4 = 4 + count(0) * size_of::<LoadVerdictV4>();
+            size_v4 + 4 + count(size_v4) * size_of::<LoadVerdictV6>()
+        }
+        CommandType::Verdict => size_of::<Verdict>(),
+        CommandType::UpdateV4 => size_of::<UpdateV4>(),
+        CommandType::UpdateV6 => size_of::<UpdateV6>(),
+        CommandType::Handshake => size_of::<Handshake>(),
+        CommandType::SetPayloadLimit => size_of::<SetPayloadLimit>(),
+        CommandType::SetLogLevel => size_of::<SetLogLevel>(),
+        CommandType::SetLogStreaming => size_of::<SetLogStreaming>(),
+        CommandType::AddRule => size_of::<Rule>(),
+        CommandType::RemoveRule => size_of::<RemoveRule>(),
+        CommandType::SetProcessVerdict => size_of::<SetProcessVerdict>(),
+        CommandType::SetPendingTimeout => size_of::<SetPendingTimeout>(),
+        CommandType::SetQueueLimits => size_of::<SetQueueLimits>(),
+        CommandType::SetBandwidthPush => size_of::<SetBandwidthPush>(),
+        CommandType::SetProcessRateLimit => size_of::<SetProcessRateLimit>(),
+        CommandType::SetConnectionRateLimitV4 => size_of::<SetConnectionRateLimitV4>(),
+        CommandType::SetConnectionRateLimitV6 => size_of::<SetConnectionRateLimitV6>(),
+        CommandType::SetPathVerdict => {
+            let len = match value.get(1..5) {
+                Some(len) => u32::from_le_bytes(len.try_into().unwrap()) as usize,
+                None => 0,
+            };
+            5 + len
+        }
+        _ => 0,
+    }
+}
+
 #[cfg(test)]
-use std::panic;
+use proptest::prelude::*;
 
 #[test]
 fn test_go_command_file() {
-    let mut file = File::open("../kext_interface/go_command_test.bin").unwrap();
-    loop {
-        let mut command: [u8; 1] = [0];
-        let bytes_count = file.read(&mut command).unwrap();
-        if bytes_count == 0 {
-            return;
+    let bytes = std::fs::read("../kext_interface/go_command_test.bin").unwrap();
+    let mut remaining = &bytes[..];
+    while let Some(&command_type) = remaining.first() {
+        let Some(command_type) = CommandType::from_u8(command_type) else {
+            panic!("Unknown command: {}", command_type);
+        };
+        let size = 1 + value_size(command_type, &remaining[1..]);
+        let command = Command::parse(&remaining[..size]).unwrap();
+        // The Rust encoder must produce the same bytes as the Go one.
+        assert_eq!(command.to_bytes(), &remaining[..size]);
+        remaining = &remaining[size..];
+
+        match command {
+            ParsedCommand::Verdict(verdict) => {
+                assert_eq!(verdict, Verdict { id: 1, verdict: 2 })
+            }
+            ParsedCommand::UpdateV4(update) => assert_eq!(
+                update,
+                UpdateV4 {
+                    protocol: 1,
+                    local_address: [1, 2, 3, 4],
+                    local_port: 2,
+                    remote_address: [2, 3, 4, 5],
+                    remote_port: 3,
+                    verdict: 4
+                }
+            ),
+            ParsedCommand::UpdateV6(update) => assert_eq!(
+                update,
+                UpdateV6 {
+                    protocol: 1,
+                    local_address: [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16],
+                    local_port: 2,
+                    remote_address: [2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17],
+                    remote_port: 3,
+                    verdict: 4
+                }
+            ),
+            ParsedCommand::Handshake(handshake) => assert_eq!(
+                handshake,
+                Handshake {
+                    version: 1,
+                    info_types: 2
+                }
+            ),
+            ParsedCommand::SetPayloadLimit(limit) => assert_eq!(
+                limit,
+                SetPayloadLimit {
+                    protocol: 17,
+                    direction: PAYLOAD_LIMIT_ANY,
+                    max_length: 100
+                }
+            ),
+            ParsedCommand::SetLogLevel(level) => {
+                assert_eq!(level, SetLogLevel { severity: 2 })
+            }
+            ParsedCommand::SetLogStreaming(streaming) => assert_eq!(
+                streaming,
+                SetLogStreaming {
+                    max_lines_per_second: 100
+                }
+            ),
+            ParsedCommand::VerdictBatch(verdicts) => {
+                assert_eq!(
+                    verdicts,
+                    [Verdict { id: 1, verdict: 2 }, Verdict { id: 3, verdict: 4 }]
+                )
+            }
+            ParsedCommand::LoadVerdicts(load) => assert_eq!(load, test_load_verdicts()),
+            ParsedCommand::AddRule(rule) => assert_eq!(rule, crate::rules::test_rule()),
+            ParsedCommand::RemoveRule(remove) => assert_eq!(remove, RemoveRule { id: 3 }),
+            ParsedCommand::SetProcessVerdict(process) => assert_eq!(
+                process,
+                SetProcessVerdict {
+                    process_id: 4,
+                    verdict: 5
+                }
+            ),
+            ParsedCommand::SetPathVerdict(path) => assert_eq!(
+                path,
+                SetPathVerdict {
+                    verdict: 3,
+                    path: String::from("\\device\\harddiskvolume1\\windows\\system32\\svchost.exe")
+                }
+            ),
+            ParsedCommand::SetPendingTimeout(timeout) => assert_eq!(
+                timeout,
+                SetPendingTimeout {
+                    timeout_ms: 5000,
+                    verdict: 1
+                }
+            ),
+            ParsedCommand::SetQueueLimits(limits) => assert_eq!(
+                limits,
+                SetQueueLimits {
+                    max_pending_packets: 1000,
+                    max_events: 2000,
+                    overflow_policy: OverflowPolicy::FailClosed as u8
+                }
+            ),
+            ParsedCommand::SetBandwidthPush(push) => assert_eq!(
+                push,
+                SetBandwidthPush {
+                    interval_ms: 1000,
+                    max_frame_size: 4096
+                }
+            ),
+            ParsedCommand::SetProcessRateLimit(limit) => assert_eq!(
+                limit,
+                SetProcessRateLimit {
+                    process_id: 4,
+                    bytes_per_second: 100_000
+                }
+            ),
+            ParsedCommand::SetConnectionRateLimitV4(limit) => assert_eq!(
+                limit,
+                SetConnectionRateLimitV4 {
+                    protocol: 6,
+                    local_address: [1, 2, 3, 4],
+                    local_port: 2,
+                    remote_address: [2, 3, 4, 5],
+                    remote_port: 3,
+                    bytes_per_second: 100_000
+                }
+            ),
+            ParsedCommand::SetConnectionRateLimitV6(limit) => assert_eq!(
+                limit,
+                SetConnectionRateLimitV6 {
+                    protocol: 17,
+                    local_address: [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16],
+                    local_port: 2,
+                    remote_address: [2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17],
+                    remote_port: 3,
+                    bytes_per_second: 100_000
+                }
+            ),
+            _ => assert_eq!(value_size(command.command_type(), &[]), 0),
+        }
+    }
+}
+
+#[cfg(test)]
+fn test_load_verdicts() -> LoadVerdicts {
+    LoadVerdicts {
+        v4: alloc::vec![LoadVerdictV4 {
+            protocol: 6,
+            local_address: [1, 2, 3, 4],
+            local_port: 2,
+            remote_address: [2, 3, 4, 5],
+            remote_port: 3,
+            direction: 1,
+            verdict: 2,
+            process_id: 4,
+        }],
+        v6: alloc::vec![
+            LoadVerdictV6 {
+                protocol: 17,
+                local_address: [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16],
+                local_port: 2,
+                remote_address: [2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17],
+                remote_port: 3,
+                direction: 0,
+                verdict: 4,
+                process_id: 5,
+            },
+            LoadVerdictV6 {
+                protocol: 6,
+                local_address: [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16],
+                local_port: 6,
+                remote_address: [2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17],
+                remote_port: 7,
+                direction: 1,
+                verdict: 5,
+                process_id: 8,
+            },
+        ],
+    }
+}
+
+#[test]
+fn test_parse_errors() {
+    assert_eq!(Command::parse(&[]), Err(ParseError::Empty));
+    assert_eq!(Command::parse(&[200]), Err(ParseError::UnknownCommand(200)));
+    assert_eq!(
+        Command::parse(&[CommandType::Verdict as u8, 1, 0, 0]),
+        Err(ParseError::TooShort(CommandType::Verdict))
+    );
+    assert_eq!(
+        Command::parse(&[CommandType::Shutdown as u8, 0]),
+        Err(ParseError::TrailingBytes(CommandType::Shutdown))
+    );
+    // The path must be valid utf-8.
+    assert_eq!(
+        Command::parse(&[CommandType::SetPathVerdict as u8, 3, 2, 0, 0, 0, 0xc3, 0x28]),
+        Err(ParseError::InvalidUtf8(CommandType::SetPathVerdict))
+    );
+    assert_eq!(
+        Command::parse(&[CommandType::SetPathVerdict as u8, 3, 3, 0, 0, 0, b'a', b'b']),
+        Err(ParseError::TooShort(CommandType::SetPathVerdict))
+    );
+}
+
+#[test]
+fn test_parse_request_errors() {
+    // The id is kept when the wrapped command is not valid.
+    let mut bytes = ParsedCommand::Shutdown.to_request_bytes(7);
+    bytes.push(0);
+    assert_eq!(
+        Command::parse_request(&bytes),
+        Request {
+            id: Some(7),
+            command: Err(ParseError::TrailingBytes(CommandType::Shutdown))
+        }
+    );
+    assert_eq!(
+        Command::parse_request(&bytes[..9]),
+        Request {
+            id: Some(7),
+            command: Err(ParseError::Empty)
+        }
+    );
+    assert_eq!(
+        Command::parse_request(&bytes[..5]),
+        Request {
+            id: None,
+            command: Err(ParseError::TooShort(CommandType::Request))
+        }
+    );
+
+    let nested = ParsedCommand::Shutdown.to_request_bytes(1);
+    let mut bytes = std::vec![CommandType::Request as u8, 2, 0, 0, 0, 0, 0, 0, 0];
+    bytes.extend_from_slice(&nested);
+    assert_eq!(
+        Command::parse_request(&bytes),
+        Request {
+            id: Some(2),
+            command: Err(ParseError::NestedRequest)
         }
-        if let Some(command) = parse_type(&command) {
-            match command {
-                CommandType::Shutdown => {}
-                CommandType::Verdict => {
-                    let mut buf = [0; size_of::<Verdict>()];
-                    let bytes_count = file.read(&mut buf).unwrap();
-                    if bytes_count != size_of::<Verdict>() {
-                        panic!("unexpected bytes count")
-                    }
-
-                    assert_eq!(parse_verdict(&buf), &Verdict { id: 1, verdict: 2 })
-                }
-                CommandType::UpdateV4 => {
-                    let mut buf = [0; size_of::<UpdateV4>()];
-                    let bytes_count = file.read(&mut buf).unwrap();
-                    if bytes_count != size_of::<UpdateV4>() {
-                        panic!("unexpected bytes count")
-                    }
-
-                    assert_eq!(
-                        parse_update_v4(&buf),
-                        &UpdateV4 {
-                            protocol: 1,
-                            local_address: [1, 2, 3, 4],
-                            local_port: 2,
-                            remote_address: [2, 3, 4, 5],
-                            remote_port: 3,
-                            verdict: 4
-                        }
-                    )
-                }
-                CommandType::UpdateV6 => {
-                    let mut buf = [0; size_of::<UpdateV6>()];
-                    let bytes_count = file.read(&mut buf).unwrap();
-                    if bytes_count != size_of::<UpdateV6>() {
-                        panic!("unexpected bytes count")
-                    }
-
-                    assert_eq!(
-                        parse_update_v6(&buf),
-                        &UpdateV6 {
-                            protocol: 1,
-                            local_address: [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16],
-                            local_port: 2,
-                            remote_address: [
-                                2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17
-                            ],
-                            remote_port: 3,
-                            verdict: 4
-                        }
-                    )
-                }
-                CommandType::ClearCache => {}
-                CommandType::GetLogs => {}
-                CommandType::GetBandwidthStats => {}
-                CommandType::PrintMemoryStats => {}
-                CommandType::CleanEndedConnections => {}
-            }
-        } else {
-            panic!("Unknown command: {}", command[0]);
+    );
+}
+
+#[cfg(test)]
+fn any_command() -> impl Strategy<Value = ParsedCommand> {
+    let update_v4 = (
+        any::<u8>(),
+        any::<[u8; 4]>(),
+        any::<u16>(),
+        any::<[u8; 4]>(),
+        any::<u16>(),
+        any::<u8>(),
+    )
+        .prop_map(
+            |(protocol, local_address, local_port, remote_address, remote_port, verdict)| {
+                ParsedCommand::UpdateV4(UpdateV4 {
+                    protocol,
+                    local_address,
+                    local_port,
+                    remote_address,
+                    remote_port,
+                    verdict,
+                })
+            },
+        );
+    let update_v6 = (
+        any::<u8>(),
+        any::<[u8; 16]>(),
+        any::<u16>(),
+        any::<[u8; 16]>(),
+        any::<u16>(),
+        any::<u8>(),
+    )
+        .prop_map(
+            |(protocol, local_address, local_port, remote_address, remote_port, verdict)| {
+                ParsedCommand::UpdateV6(UpdateV6 {
+                    protocol,
+                    local_address,
+                    local_port,
+                    remote_address,
+                    remote_port,
+                    verdict,
+                })
+            },
+        );
+    let load_verdict_v4 = (
+        any::<u8>(),
+        any::<[u8; 4]>(),
+        any::<u16>(),
+        any::<[u8; 4]>(),
+        any::<u16>(),
+        (any::<u8>(), any::<u8>(), any::<u64>()),
+    )
+        .prop_map(
+            |(
+                protocol,
+                local_address,
+                local_port,
+                remote_address,
+                remote_port,
+                (direction, verdict, process_id),
+            )| LoadVerdictV4 {
+                protocol,
+                local_address,
+                local_port,
+                remote_address,
+                remote_port,
+                direction,
+                verdict,
+                process_id,
+            },
+        );
+    let load_verdict_v6 = (
+        any::<u8>(),
+        any::<[u8; 16]>(),
+        any::<u16>(),
+        any::<[u8; 16]>(),
+        any::<u16>(),
+        (any::<u8>(), any::<u8>(), any::<u64>()),
+    )
+        .prop_map(
+            |(
+                protocol,
+                local_address,
+                local_port,
+                remote_address,
+                remote_port,
+                (direction, verdict, process_id),
+            )| LoadVerdictV6 {
+                protocol,
+                local_address,
+                local_port,
+                remote_address,
+                remote_port,
+                direction,
+                verdict,
+                process_id,
+            },
+        );
+    let rule = (
+        any::<u32>(),
+        (any::<u8>(), any::<u8>(), any::<u8>()),
+        any::<[u8; 16]>(),
+        any::<u8>(),
+        (any::<u16>(), any::<u16>()),
+        any::<u8>(),
+    )
+        .prop_map(
+            |(
+                id,
+                (protocol, direction, ip_version),
+                remote_address,
+                prefix_length,
+                (remote_port_start, remote_port_end),
+                verdict,
+            )| Rule {
+                id,
+                protocol,
+                direction,
+                ip_version,
+                remote_address,
+                prefix_length,
+                remote_port_start,
+                remote_port_end,
+                verdict,
+            },
+        );
+    let connection_rate_limit_v4 = (
+        any::<u8>(),
+        any::<[u8; 4]>(),
+        any::<u16>(),
+        any::<[u8; 4]>(),
+        any::<u16>(),
+        any::<u64>(),
+    )
+        .prop_map(
+            |(
+                protocol,
+                local_address,
+                local_port,
+                remote_address,
+                remote_port,
+                bytes_per_second,
+            )| {
+                ParsedCommand::SetConnectionRateLimitV4(SetConnectionRateLimitV4 {
+                    protocol,
+                    local_address,
+                    local_port,
+                    remote_address,
+                    remote_port,
+                    bytes_per_second,
+                })
+            },
+        );
+    let connection_rate_limit_v6 = (
+        any::<u8>(),
+        any::<[u8; 16]>(),
+        any::<u16>(),
+        any::<[u8; 16]>(),
+        any::<u16>(),
+        any::<u64>(),
+    )
+        .prop_map(
+            |(
+                protocol,
+                local_address,
+                local_port,
+                remote_address,
+                remote_port,
+                bytes_per_second,
+            )| {
+                ParsedCommand::SetConnectionRateLimitV6(SetConnectionRateLimitV6 {
+                    protocol,
+                    local_address,
+                    local_port,
+                    remote_address,
+                    remote_port,
+                    bytes_per_second,
+                })
+            },
+        );
+    prop_oneof![
+        Just(ParsedCommand::Shutdown),
+        (any::<u64>(), any::<u8>())
+            .prop_map(|(id, verdict)| ParsedCommand::Verdict(Verdict { id, verdict })),
+        update_v4,
+        update_v6,
+        Just(ParsedCommand::ClearCache),
+        Just(ParsedCommand::GetLogs),
+        Just(ParsedCommand::GetBandwidthStats),
+        Just(ParsedCommand::PrintMemoryStats),
+        Just(ParsedCommand::CleanEndedConnections),
+        Just(ParsedCommand::DumpConnections),
+        (any::<u32>(), any::<u64>()).prop_map(|(version, info_types)| {
+            ParsedCommand::Handshake(Handshake {
+                version,
+                info_types,
+            })
+        }),
+        proptest::collection::vec(
+            (any::<u64>(), any::<u8>()).prop_map(|(id, verdict)| Verdict { id, verdict }),
+            0..32
+        )
+        .prop_map(ParsedCommand::VerdictBatch),
+        (any::<u8>(), any::<u8>(), any::<u32>()).prop_map(|(protocol, direction, max_length)| {
+            ParsedCommand::SetPayloadLimit(SetPayloadLimit {
+                protocol,
+                direction,
+                max_length,
+            })
+        }),
+        any::<u8>().prop_map(|severity| ParsedCommand::SetLogLevel(SetLogLevel { severity })),
+        any::<u32>().prop_map(|max_lines_per_second| {
+            ParsedCommand::SetLogStreaming(SetLogStreaming {
+                max_lines_per_second,
+            })
+        }),
+        (
+            proptest::collection::vec(load_verdict_v4, 0..8),
+            proptest::collection::vec(load_verdict_v6, 0..8)
+        )
+            .prop_map(|(v4, v6)| ParsedCommand::LoadVerdicts(LoadVerdicts { v4, v6 })),
+        rule.prop_map(ParsedCommand::AddRule),
+        any::<u32>().prop_map(|id| ParsedCommand::RemoveRule(RemoveRule { id })),
+        Just(ParsedCommand::ListRules),
+        (any::<u64>(), any::<u8>()).prop_map(|(process_id, verdict)| {
+            ParsedCommand::SetProcessVerdict(SetProcessVerdict {
+                process_id,
+                verdict,
+            })
+        }),
+        (any::<u8>(), ".{0,64}").prop_map(|(verdict, path)| ParsedCommand::SetPathVerdict(
+            SetPathVerdict { verdict, path }
+        )),
+        (any::<u32>(), any::<u8>()).prop_map(|(timeout_ms, verdict)| {
+            ParsedCommand::SetPendingTimeout(SetPendingTimeout {
+                timeout_ms,
+                verdict,
+            })
+        }),
+        (any::<u32>(), any::<u32>(), any::<u8>()).prop_map(
+            |(max_pending_packets, max_events, overflow_policy)| {
+                ParsedCommand::SetQueueLimits(SetQueueLimits {
+                    max_pending_packets,
+                    max_events,
+                    overflow_policy,
+                })
+            }
+        ),
+        (any::<u32>(), any::<u32>()).prop_map(|(interval_ms, max_frame_size)| {
+            ParsedCommand::SetBandwidthPush(SetBandwidthPush {
+                interval_ms,
+                max_frame_size,
+            })
+        }),
+        (any::<u64>(), any::<u64>()).prop_map(|(process_id, bytes_per_second)| {
+            ParsedCommand::SetProcessRateLimit(SetProcessRateLimit {
+                process_id,
+                bytes_per_second,
+            })
+        }),
+        connection_rate_limit_v4,
+        connection_rate_limit_v6,
+    ]
+}
+
+#[cfg(test)]
+proptest! {
+    #[test]
+    fn command_round_trip(command in any_command()) {
+        let bytes = command.to_bytes();
+        prop_assert_eq!(bytes.len(), 1 + value_size(command.command_type(), &bytes[1..]));
+        prop_assert_eq!(Command::parse(&bytes), Ok(command));
+    }
+
+    #[test]
+    fn request_round_trip(id in any::<u64>(), command in any_command()) {
+        prop_assert_eq!(
+            Command::parse_request(&command.to_request_bytes(id)),
+            Request { id: Some(id), command: Ok(command.clone()) }
+        );
+        prop_assert_eq!(
+            Command::parse_request(&command.to_bytes()),
+            Request { id: None, command: Ok(command) }
+        );
+    }
+
+    #[test]
+    fn parse_never_panics(bytes in proptest::collection::vec(any::<u8>(), 0..128)) {
+        let _ = Command::parse(&bytes);
+    }
+
+    #[test]
+    fn parse_checks_length(
+        command_type in (0..=CommandType::SetConnectionRateLimitV6 as u8)
+            .prop_filter("the request envelope is tested separately", |t| *t != CommandType::Request as u8),
+        extra in 1..64_usize
+    ) {
+        let command_type = CommandType::from_u8(command_type).unwrap();
+        let size = value_size(command_type, &[]);
+
+        let mut bytes = std::vec![0; 1 + size + extra];
+        bytes[0] = command_type as u8;
+        prop_assert!(Command::parse(&bytes[..1 + size]).is_ok());
+        prop_assert_eq!(
+            Command::parse(&bytes),
+            Err(ParseError::TrailingBytes(command_type))
+        );
+        if size > 0 {
+            let short = (1 + size).saturating_sub(extra).max(1);
+            prop_assert_eq!(
+                Command::parse(&bytes[..short]),
+                Err(ParseError::TooShort(command_type))
+            );
         }
     }
+
+    #[test]
+    fn parse_verdict_fields(id in any::<u64>(), verdict in any::<u8>()) {
+        let mut bytes = std::vec![CommandType::Verdict as u8];
+        bytes.extend_from_slice(&id.to_le_bytes());
+        bytes.push(verdict);
+        prop_assert_eq!(
+            Command::parse(&bytes),
+            Ok(ParsedCommand::Verdict(Verdict { id, verdict }))
+        );
+    }
+}
+
+#[test]
+fn test_parse_verdict_batch() {
+    let batch = ParsedCommand::VerdictBatch(alloc::vec![
+        Verdict { id: 1, verdict: 2 },
+        Verdict { id: 3, verdict: 4 },
+    ]);
+    let bytes = batch.to_bytes();
+    assert_eq!(bytes.len(), 1 + 4 + 2 * 9);
+    assert_eq!(Command::parse(&bytes), Ok(batch));
+
+    // The count must match the number of entries.
+    let mut bytes = bytes;
+    bytes[1] = 3;
+    assert_eq!(
+        Command::parse(&bytes),
+        Err(ParseError::TooShort(CommandType::VerdictBatch))
+    );
+    bytes[1] = 1;
+    assert_eq!(
+        Command::parse(&bytes),
+        Err(ParseError::TrailingBytes(CommandType::VerdictBatch))
+    );
+}
+
+#[test]
+fn test_parse_load_verdicts() {
+    let load = ParsedCommand::LoadVerdicts(test_load_verdicts());
+    let bytes = load.to_bytes();
+    assert_eq!(bytes.len(), 1 + 4 + 23 + 4 + 2 * 47);
+    assert_eq!(Command::parse(&bytes), Ok(load));
+
+    // The ipv6 count follows the ipv4 entries and must match the number of entries.
+    let mut bytes = bytes;
+    bytes[1 + 4 + 23] = 3;
+    assert_eq!(
+        Command::parse(&bytes),
+        Err(ParseError::TooShort(CommandType::LoadVerdicts))
+    );
+    bytes[1 + 4 + 23] = 1;
+    assert_eq!(
+        Command::parse(&bytes),
+        Err(ParseError::TrailingBytes(CommandType::LoadVerdicts))
+    );
 }
@@ -0,0 +1,226 @@
+// Storage for values that wait for an answer with their id, like the pending packets of the driver.
+
+use alloc::{collections::VecDeque, vec::Vec};
+use hashbrown::HashMap;
+
+struct Entry<T> {
+    value: T,
+    // When the value was pushed, in milliseconds.
+    timestamp: u64,
+}
+
+/// Queue of values with sequential ids. Values can be removed by id in any order in O(1),
+/// the oldest values can be removed for expiry.
+/// Memory use depends on the number of values, not on the ids between the oldest and the newest value.
+pub struct IdQueue<T> {
+    values: HashMap<u64, Entry<T>>,
+    // Ids in push order. Can contain ids that were already removed with `pop_id`,
+    // they are skipped when the front is read and dropped by `compact`.
+    order: VecDeque<u64>,
+    next_id: u64,
+}
+
+impl<T> IdQueue<T> {
+    pub fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            values: HashMap::with_capacity(capacity),
+            order: VecDeque::with_capacity(capacity),
+            next_id: 1, // 0 is invalid id
+        }
+    }
+
+    /// Returns the id that the next pushed value gets.
+    pub fn next_id(&self) -> u64 {
+        self.next_id
+    }
+
+    /// Adds a value and returns its id.
+    pub fn push(&mut self, value: T, timestamp: u64) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.values.insert(id, Entry { value, timestamp });
+        self.order.push_back(id);
+        self.compact();
+        id
+    }
+
+    pub fn pop_id(&mut self, id: u64) -> Option<T> {
+        let entry = self.values.remove(&id)?;
+        self.trim_front();
+        Some(entry.value)
+    }
+
+    /// Removes the value that was pushed first.
+    pub fn pop_oldest(&mut self) -> Option<(u64, T)> {
+        // The first id is never removed, see `trim_front`.
+        let id = self.order.pop_front()?;
+        let entry = self.values.remove(&id)?;
+        self.trim_front();
+        Some((id, entry.value))
+    }
+
+    /// Removes the values that were pushed more than `timeout` before `now`, oldest first.
+    pub fn pop_expired(&mut self, now: u64, timeout: u64) -> Vec<(u64, T)> {
+        let mut expired = Vec::new();
+        while let Some(entry) = self.order.front().and_then(|id| self.values.get(id)) {
+            if now.saturating_sub(entry.timestamp) < timeout {
+                break;
+            }
+            expired.extend(self.pop_oldest());
+        }
+        expired
+    }
+
+    /// Number of values in the queue.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    // Removes the removed ids at the front, so the first id is always the oldest value.
+    fn trim_front(&mut self) {
+        while let Some(id) = self.order.front() {
+            if self.values.contains_key(id) {
+                break;
+            }
+            self.order.pop_front();
+        }
+    }
+
+    // Drops the removed ids from the order when they are the majority. A value that stays in
+    // the queue keeps every newer id in the order until then.
+    fn compact(&mut self) {
+        if self.order.len() > 2 * self.values.len() + 32 {
+            let values = &self.values;
+            self.order.retain(|id| values.contains_key(id));
+        }
+    }
+
+    #[cfg(test)]
+    fn order_len(&self) -> usize {
+        self.order.len()
+    }
+}
+
+impl<T> Default for IdQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[test]
+fn test_pop_id() {
+    let mut queue = IdQueue::new();
+    let ids: Vec<u64> = (0..5).map(|i| queue.push(i, 0)).collect();
+    assert_eq!(ids, [1, 2, 3, 4, 5]);
+    assert_eq!(queue.pop_id(3), Some(2));
+    assert_eq!(queue.pop_id(3), None);
+    assert_eq!(queue.pop_id(0), None);
+    assert_eq!(queue.pop_id(6), None);
+    assert_eq!(queue.len(), 4);
+
+    // Removing the oldest values skips the removed id in between.
+    assert_eq!(queue.pop_id(1), Some(0));
+    assert_eq!(queue.pop_id(2), Some(1));
+    assert_eq!(queue.order_len(), 2);
+    assert_eq!(queue.push(5, 0), 6);
+    assert_eq!(queue.pop_oldest(), Some((4, 3)));
+    assert_eq!(queue.pop_oldest(), Some((5, 4)));
+    assert_eq!(queue.pop_oldest(), Some((6, 5)));
+    assert_eq!(queue.pop_oldest(), None);
+    assert!(queue.is_empty());
+    assert_eq!(queue.next_id(), 7);
+}
+
+#[test]
+fn test_pop_expired() {
+    let mut queue = IdQueue::new();
+    for timestamp in [100, 200, 300, 400] {
+        queue.push(timestamp, timestamp);
+    }
+    queue.pop_id(2);
+    assert_eq!(queue.pop_expired(400, 100), [(1, 100), (3, 300)]);
+    assert_eq!(queue.pop_expired(400, 100), []);
+    assert_eq!(queue.pop_expired(500, 100), [(4, 400)]);
+    assert!(queue.is_empty());
+}
+
+#[test]
+fn test_stuck_value() {
+    // A value that never gets an answer does not keep the removed values after it.
+    let mut queue = IdQueue::new();
+    assert_eq!(queue.push(0, 0), 1);
+    for i in 1..10_000 {
+        let id = queue.push(i, 0);
+        assert_eq!(queue.pop_id(id), Some(i));
+    }
+    assert_eq!(queue.len(), 1);
+    assert!(queue.order_len() <= 34);
+    assert_eq!(queue.pop_oldest(), Some((1, 0)));
+    assert_eq!(queue.pop_oldest(), None);
+    assert_eq!(queue.next_id(), 10_001);
+}
+
+#[cfg(test)]
+proptest::proptest! {
+    #[test]
+    fn pop_in_any_order(order in proptest::sample::subsequence((1..=64_u64).collect::<Vec<_>>(), 0..=64)) {
+        let mut queue = IdQueue::new();
+        for id in 1..=64_u64 {
+            proptest::prop_assert_eq!(queue.push(id, 0), id);
+        }
+        for id in &order {
+            proptest::prop_assert_eq!(queue.pop_id(*id), Some(*id));
+        }
+        // The rest comes out in push order.
+        let rest: Vec<u64> = core::iter::from_fn(|| queue.pop_oldest().map(|(_, value)| value)).collect();
+        let expected: Vec<u64> = (1..=64).filter(|id| !order.contains(id)).collect();
+        proptest::prop_assert_eq!(rest, expected);
+        proptest::prop_assert_eq!(queue.order_len(), 0);
+    }
+}
+
+/// Compares `IdQueue` with the sorted `VecDeque` that the driver used before, with verdicts
+/// that arrive out of order. Run with `cargo test --release -- --ignored --nocapture`.
+#[test]
+#[ignore]
+fn bench_pop_out_of_order() {
+    use rand::seq::SliceRandom;
+    use std::time::Instant;
+
+    const PENDING: u64 = 10_000;
+    let mut order: Vec<u64> = (1..=PENDING).collect();
+    order.shuffle(&mut rand::thread_rng());
+
+    let start = Instant::now();
+    let mut sorted: VecDeque<(u64, u64)> = (1..=PENDING).map(|id| (id, id)).collect();
+    for id in &order {
+        let index = sorted.binary_search_by_key(id, |(id, _)| *id).unwrap();
+        assert_eq!(sorted.remove(index), Some((*id, *id)));
+    }
+    let sorted_time = start.elapsed();
+
+    let start = Instant::now();
+    let mut queue = IdQueue::with_capacity(PENDING as usize);
+    for id in 1..=PENDING {
+        queue.push(id, 0);
+    }
+    for id in &order {
+        assert_eq!(queue.pop_id(*id), Some(*id));
+    }
+    let queue_time = start.elapsed();
+
+    std::println!(
+        "{} pending, random order: sorted VecDeque {:?}, IdQueue {:?}",
+        PENDING,
+        sorted_time,
+        queue_time
+    );
+}
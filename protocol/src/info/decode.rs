@@ -0,0 +1,973 @@
+// Decoder for the info stream produced by the kernel extension.
+// Every frame has the form: [InfoType: u8, data_size_in_bytes: u32, data: ...]
+
+use alloc::{string::String, vec::Vec};
+use num_traits::FromPrimitive;
+
+use super::{InfoType, MemoryStats};
+use crate::{reader::Reader, rules::Rule};
+
+/// Size of the frame header: InfoType + data size.
+const HEADER_SIZE: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpAddress {
+    V4([u8; 4]),
+    V6([u8; 16]),
+}
+
+impl core::fmt::Display for IpAddress {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            IpAddress::V4(ip) => write!(f, "{}.{}.{}.{}", ip[0], ip[1], ip[2], ip[3]),
+            IpAddress::V6(ip) => {
+                for (i, group) in ip.chunks(2).enumerate() {
+                    if i > 0 {
+                        write!(f, ":")?;
+                    }
+                    write!(f, "{:x}", u16::from_be_bytes([group[0], group[1]]))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Connection {
+    pub id: u64,
+    pub process_id: u64,
+    pub direction: u8,
+    pub protocol: u8,
+    pub local_ip: IpAddress,
+    pub remote_ip: IpAddress,
+    pub local_port: u16,
+    pub remote_port: u16,
+    pub payload_layer: u8,
+    /// 0 (unspecified) for frames without interface information.
+    pub interface_index: u32,
+    pub sub_interface_index: u32,
+    /// Length of the payload before it was cut to the payload limit.
+    pub original_length: u32,
+    pub payload: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionEnd {
+    pub process_id: u64,
+    pub direction: u8,
+    pub protocol: u8,
+    pub local_ip: IpAddress,
+    pub remote_ip: IpAddress,
+    pub local_port: u16,
+    pub remote_port: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BandwidthValue {
+    pub local_ip: IpAddress,
+    pub local_port: u16,
+    pub remote_ip: IpAddress,
+    pub remote_port: u16,
+    pub transmitted_bytes: u64,
+    pub received_bytes: u64,
+    pub transmitted_packets: u64,
+    pub received_packets: u64,
+    pub first_seen_timestamp: u64,
+    pub last_seen_timestamp: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BandwidthStats {
+    pub protocol: u8,
+    pub values: Vec<BandwidthValue>,
+}
+
+/// Bytes sent and received by a process over one protocol.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BandwidthProcessValue {
+    pub process_id: u64,
+    pub protocol: u8,
+    pub transmitted_bytes: u64,
+    pub received_bytes: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogLine {
+    pub severity: u8,
+    pub line: String,
+}
+
+/// Value of a log record field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LogFieldValue {
+    U64(u64),
+    I64(i64),
+    Str(String),
+    Connection(super::LogConnection),
+}
+
+impl core::fmt::Display for LogFieldValue {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            LogFieldValue::U64(value) => write!(f, "{}", value),
+            LogFieldValue::I64(value) => write!(f, "{}", value),
+            LogFieldValue::Str(value) => write!(f, "{}", value),
+            LogFieldValue::Connection(c) => write!(
+                f,
+                "p: {} l: {}:{} r: {}:{}",
+                c.protocol, c.local_ip, c.local_port, c.remote_ip, c.remote_port
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogField {
+    pub key: String,
+    pub value: LogFieldValue,
+}
+
+/// Connection from a connection cache dump. Timestamps are the Windows system time in milliseconds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionDumpValue {
+    pub protocol: u8,
+    pub local_ip: IpAddress,
+    pub local_port: u16,
+    pub remote_ip: IpAddress,
+    pub remote_port: u16,
+    pub direction: u8,
+    pub verdict: u8,
+    pub process_id: u64,
+    pub last_accessed_timestamp: u64,
+    /// 0 if the connection has not ended.
+    pub end_timestamp: u64,
+}
+
+/// Structured log line. `timestamp` is the Windows system time in milliseconds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogRecord {
+    pub timestamp: u64,
+    pub severity: u8,
+    /// The message or a field was cut by the driver.
+    pub truncated: bool,
+    pub cpu: u32,
+    pub irql: u8,
+    pub file: String,
+    pub line: u32,
+    pub message: String,
+    pub fields: Vec<LogField>,
+}
+
+/// What the driver supports. Bit `n` of `commands` and `info_types` is set for the value `n`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Handshake {
+    pub version: u32,
+    pub commands: u64,
+    pub info_types: u64,
+}
+
+/// Entry of a verdict batch that was not applied. `error` is a `VerdictError` value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerdictFailure {
+    pub id: u64,
+    pub error: u8,
+}
+
+/// Reply to a command that was sent with a request id. `code` is a `ResultCode` value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandResult {
+    pub request_id: u64,
+    pub code: u8,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessInfo {
+    pub process_id: u64,
+    pub path: String,
+}
+
+/// Pending packets that got no verdict before the timeout. `verdict` was applied to all of them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingTimeout {
+    pub verdict: u8,
+    pub ids: Vec<u64>,
+}
+
+/// Number of pending packets and infos that were dropped since the last report.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueueOverflow {
+    pub pending_packets: u32,
+    pub events: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    Connection(Connection),
+    ConnectionEnd(ConnectionEnd),
+    Bandwidth(BandwidthStats),
+    LogLine(LogLine),
+    Handshake(Handshake),
+    VerdictBatchResult(Vec<VerdictFailure>),
+    CommandResult(CommandResult),
+    ProcessInfo(ProcessInfo),
+    /// Number of log lines that were lost before they were sent.
+    LogLinesDropped(u32),
+    LogRecord(LogRecord),
+    /// Part of a connection cache dump.
+    ConnectionDump(Vec<ConnectionDumpValue>),
+    /// End of a connection cache dump with the number of connections in all parts.
+    ConnectionDumpEnd(u32),
+    MemoryStats(MemoryStats),
+    /// Reply to the list rules command. The rules are sorted by id.
+    RuleList(Vec<Rule>),
+    PendingTimeout(PendingTimeout),
+    QueueOverflow(QueueOverflow),
+    BandwidthPerProcess(Vec<BandwidthProcessValue>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// Not enough bytes for a full frame. Nothing was consumed.
+    Incomplete,
+    /// The frame has an info type that this decoder does not know. The frame was skipped.
+    UnknownInfoType(u8),
+    /// The frame content does not match its declared size.
+    InvalidFrame(u8),
+}
+
+impl core::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            DecodeError::Incomplete => write!(f, "incomplete frame"),
+            DecodeError::UnknownInfoType(t) => write!(f, "unknown info type: {}", t),
+            DecodeError::InvalidFrame(t) => write!(f, "invalid frame for info type: {}", t),
+        }
+    }
+}
+
+fn read_ipv4(reader: &mut Reader) -> Option<IpAddress> {
+    Some(IpAddress::V4(reader.array()?))
+}
+
+fn read_ipv6(reader: &mut Reader) -> Option<IpAddress> {
+    Some(IpAddress::V6(reader.array()?))
+}
+
+/// Decodes the first frame in `bytes`.
+/// Returns the event and the number of bytes the frame occupied.
+/// `DecodeError::Incomplete` is returned if `bytes` does not hold a full frame yet.
+pub fn decode_frame(bytes: &[u8]) -> Result<(Event, usize), DecodeError> {
+    if bytes.len() < HEADER_SIZE {
+        return Err(DecodeError::Incomplete);
+    }
+    let info_type = bytes[0];
+    let size = u32::from_le_bytes([bytes[1], bytes[2], bytes[3], bytes[4]]) as usize;
+    let Some(frame_size) = size.checked_add(HEADER_SIZE) else {
+        return Err(DecodeError::InvalidFrame(info_type));
+    };
+    if bytes.len() < frame_size {
+        return Err(DecodeError::Incomplete);
+    }
+
+    let Some(kind) = InfoType::from_u8(info_type) else {
+        return Err(DecodeError::UnknownInfoType(info_type));
+    };
+
+    let mut reader = Reader::new(&bytes[HEADER_SIZE..frame_size]);
+    let event = match kind {
+        InfoType::LogLine => decode_log_line(&mut reader),
+        InfoType::ConnectionIpv4 => decode_connection(&mut reader, false, false),
+        InfoType::ConnectionIpv6 => decode_connection(&mut reader, true, false),
+        InfoType::ConnectionIpv4Ext => decode_connection(&mut reader, false, true),
+        InfoType::ConnectionIpv6Ext => decode_connection(&mut reader, true, true),
+        InfoType::ConnectionEndEventV4 => decode_connection_end(&mut reader, false),
+        InfoType::ConnectionEndEventV6 => decode_connection_end(&mut reader, true),
+        InfoType::BandwidthStatsV4 => decode_bandwidth(&mut reader, false, false),
+        InfoType::BandwidthStatsV6 => decode_bandwidth(&mut reader, true, false),
+        InfoType::Handshake => decode_handshake(&mut reader),
+        InfoType::VerdictBatchResult => decode_verdict_batch_result(&mut reader),
+        InfoType::CommandResult => decode_command_result(&mut reader),
+        InfoType::ProcessInfo => decode_process_info(&mut reader),
+        InfoType::LogLinesDropped => reader.u32().map(Event::LogLinesDropped),
+        InfoType::LogRecord => decode_log_record(&mut reader),
+        InfoType::ConnectionDumpV4 => decode_connection_dump(&mut reader, false),
+        InfoType::ConnectionDumpV6 => decode_connection_dump(&mut reader, true),
+        InfoType::ConnectionDumpEnd => reader.u32().map(Event::ConnectionDumpEnd),
+        InfoType::MemoryStats => decode_memory_stats(&mut reader),
+        InfoType::RuleList => decode_rule_list(&mut reader),
+        InfoType::PendingTimeout => decode_pending_timeout(&mut reader),
+        InfoType::QueueOverflow => decode_queue_overflow(&mut reader),
+        InfoType::BandwidthStatsPerProcess => decode_bandwidth_per_process(&mut reader),
+        InfoType::BandwidthStatsV4Ext => decode_bandwidth(&mut reader, false, true),
+        InfoType::BandwidthStatsV6Ext => decode_bandwidth(&mut reader, true, true),
+    };
+
+    match event {
+        Some(event) if reader.is_empty() => Ok((event, frame_size)),
+        _ => Err(DecodeError::InvalidFrame(info_type)),
+    }
+}
+
+/// Returns the size of the first frame in `bytes`, if the header is available.
+fn frame_size(bytes: &[u8]) -> Option<usize> {
+    if bytes.len() < HEADER_SIZE {
+        return None;
+    }
+    let size = u32::from_le_bytes([bytes[1], bytes[2], bytes[3], bytes[4]]) as usize;
+    size.checked_add(HEADER_SIZE)
+}
+
+fn decode_log_line(reader: &mut Reader) -> Option<Event> {
+    let severity = reader.u8()?;
+    let line = reader.rest();
+    Some(Event::LogLine(LogLine {
+        severity,
+        line: String::from_utf8_lossy(line).into_owned(),
+    }))
+}
+
+fn read_string(reader: &mut Reader, size: usize) -> Option<String> {
+    Some(String::from_utf8_lossy(reader.take(size)?).into_owned())
+}
+
+fn read_ip(reader: &mut Reader) -> Option<IpAddress> {
+    match reader.u8()? {
+        4 => read_ipv4(reader),
+        6 => read_ipv6(reader),
+        _ => None,
+    }
+}
+
+fn decode_log_record(reader: &mut Reader) -> Option<Event> {
+    let timestamp = reader.u64()?;
+    let severity = reader.u8()?;
+    let flags = reader.u8()?;
+    let cpu = reader.u32()?;
+    let irql = reader.u8()?;
+    let line = reader.u32()?;
+    let file_size = reader.u16()? as usize;
+    let file = read_string(reader, file_size)?;
+    let message_size = reader.u16()? as usize;
+    let message = read_string(reader, message_size)?;
+    let field_count = reader.u8()?;
+    let mut fields = Vec::with_capacity(field_count as usize);
+    for _ in 0..field_count {
+        let key_size = reader.u8()? as usize;
+        let key = read_string(reader, key_size)?;
+        let value = match reader.u8()? {
+            0 => LogFieldValue::U64(reader.u64()?),
+            1 => LogFieldValue::I64(reader.u64()? as i64),
+            2 => {
+                let size = reader.u16()? as usize;
+                LogFieldValue::Str(read_string(reader, size)?)
+            }
+            3 => LogFieldValue::Connection(super::LogConnection {
+                protocol: reader.u8()?,
+                local_ip: read_ip(reader)?,
+                local_port: reader.u16()?,
+                remote_ip: read_ip(reader)?,
+                remote_port: reader.u16()?,
+            }),
+            _ => return None,
+        };
+        fields.push(LogField { key, value });
+    }
+
+    Some(Event::LogRecord(LogRecord {
+        timestamp,
+        severity,
+        truncated: flags & super::LOG_RECORD_TRUNCATED != 0,
+        cpu,
+        irql,
+        file,
+        line,
+        message,
+        fields,
+    }))
+}
+
+fn decode_connection(reader: &mut Reader, ipv6: bool, extended: bool) -> Option<Event> {
+    let id = reader.u64()?;
+    let process_id = reader.u64()?;
+    let direction = reader.u8()?;
+    let protocol = reader.u8()?;
+    let (local_ip, remote_ip) = if ipv6 {
+        (read_ipv6(reader)?, read_ipv6(reader)?)
+    } else {
+        (read_ipv4(reader)?, read_ipv4(reader)?)
+    };
+    let local_port = reader.u16()?;
+    let remote_port = reader.u16()?;
+    let payload_layer = reader.u8()?;
+    let (interface_index, sub_interface_index, original_length) = if extended {
+        (reader.u32()?, reader.u32()?, Some(reader.u32()?))
+    } else {
+        (0, 0, None)
+    };
+    let payload_size = reader.u32()?;
+    let payload = reader.take(payload_size as usize)?.to_vec();
+    let original_length = original_length.unwrap_or(payload_size);
+
+    Some(Event::Connection(Connection {
+        id,
+        process_id,
+        direction,
+        protocol,
+        local_ip,
+        remote_ip,
+        local_port,
+        remote_port,
+        payload_layer,
+        interface_index,
+        sub_interface_index,
+        original_length,
+        payload,
+    }))
+}
+
+fn decode_connection_end(reader: &mut Reader, ipv6: bool) -> Option<Event> {
+    let process_id = reader.u64()?;
+    let direction = reader.u8()?;
+    let protocol = reader.u8()?;
+    let (local_ip, remote_ip) = if ipv6 {
+        (read_ipv6(reader)?, read_ipv6(reader)?)
+    } else {
+        (read_ipv4(reader)?, read_ipv4(reader)?)
+    };
+    let local_port = reader.u16()?;
+    let remote_port = reader.u16()?;
+
+    Some(Event::ConnectionEnd(ConnectionEnd {
+        process_id,
+        direction,
+        protocol,
+        local_ip,
+        remote_ip,
+        local_port,
+        remote_port,
+    }))
+}
+
+/// The packet counts and timestamps are only in the extended format, they are 0 for the basic one.
+fn decode_bandwidth(reader: &mut Reader, ipv6: bool, ext: bool) -> Option<Event> {
+    let protocol = reader.u8()?;
+    let count = reader.u32()? as usize;
+    // Don't trust the count for the allocation, every value is at least 28 bytes.
+    let mut values = Vec::with_capacity(count.min(reader.len() / 28));
+    for _ in 0..count {
+        let local_ip = if ipv6 {
+            read_ipv6(reader)?
+        } else {
+            read_ipv4(reader)?
+        };
+        let local_port = reader.u16()?;
+        let remote_ip = if ipv6 {
+            read_ipv6(reader)?
+        } else {
+            read_ipv4(reader)?
+        };
+        let remote_port = reader.u16()?;
+        let mut value = BandwidthValue {
+            local_ip,
+            local_port,
+            remote_ip,
+            remote_port,
+            transmitted_bytes: reader.u64()?,
+            received_bytes: reader.u64()?,
+            transmitted_packets: 0,
+            received_packets: 0,
+            first_seen_timestamp: 0,
+            last_seen_timestamp: 0,
+        };
+        if ext {
+            value.transmitted_packets = reader.u64()?;
+            value.received_packets = reader.u64()?;
+            value.first_seen_timestamp = reader.u64()?;
+            value.last_seen_timestamp = reader.u64()?;
+        }
+        values.push(value);
+    }
+
+    Some(Event::Bandwidth(BandwidthStats { protocol, values }))
+}
+
+fn decode_bandwidth_per_process(reader: &mut Reader) -> Option<Event> {
+    let count = reader.u32()? as usize;
+    // Don't trust the count for the allocation, every value is 25 bytes.
+    let mut values = Vec::with_capacity(count.min(reader.len() / 25));
+    for _ in 0..count {
+        values.push(BandwidthProcessValue {
+            process_id: reader.u64()?,
+            protocol: reader.u8()?,
+            transmitted_bytes: reader.u64()?,
+            received_bytes: reader.u64()?,
+        });
+    }
+    Some(Event::BandwidthPerProcess(values))
+}
+
+fn decode_connection_dump(reader: &mut Reader, ipv6: bool) -> Option<Event> {
+    let count = reader.u32()? as usize;
+    // Don't trust the count for the allocation, every value is at least 39 bytes.
+    let mut values = Vec::with_capacity(count.min(reader.len() / 39));
+    for _ in 0..count {
+        let protocol = reader.u8()?;
+        let local_ip = if ipv6 {
+            read_ipv6(reader)?
+        } else {
+            read_ipv4(reader)?
+        };
+        let local_port = reader.u16()?;
+        let remote_ip = if ipv6 {
+            read_ipv6(reader)?
+        } else {
+            read_ipv4(reader)?
+        };
+        values.push(ConnectionDumpValue {
+            protocol,
+            local_ip,
+            local_port,
+            remote_ip,
+            remote_port: reader.u16()?,
+            direction: reader.u8()?,
+            verdict: reader.u8()?,
+            process_id: reader.u64()?,
+            last_accessed_timestamp: reader.u64()?,
+            end_timestamp: reader.u64()?,
+        });
+    }
+
+    Some(Event::ConnectionDump(values))
+}
+
+fn decode_memory_stats(reader: &mut Reader) -> Option<Event> {
+    Some(Event::MemoryStats(MemoryStats {
+        timestamp: reader.u64()?,
+        id_cache_entries: reader.u64()?,
+        connections_v4: reader.u64()?,
+        connections_v6: reader.u64()?,
+        bandwidth_tcp_v4: reader.u64()?,
+        bandwidth_tcp_v6: reader.u64()?,
+        bandwidth_udp_v4: reader.u64()?,
+        bandwidth_udp_v6: reader.u64()?,
+        event_queue_depth: reader.u64()?,
+        read_leftover_bytes: reader.u64()?,
+        allocated_bytes: reader.u64()?,
+        allocation_count: reader.u64()?,
+        total_allocations: reader.u64()?,
+        bandwidth_process: reader.u64()?,
+        bandwidth_other_v4: reader.u64()?,
+        bandwidth_other_v6: reader.u64()?,
+    }))
+}
+
+fn decode_rule_list(reader: &mut Reader) -> Option<Event> {
+    let count = reader.u32()? as usize;
+    // Don't trust the count for the allocation.
+    let mut rules = Vec::with_capacity(count.min(reader.len() / core::mem::size_of::<Rule>()));
+    for _ in 0..count {
+        rules.push(Rule::parse(reader)?);
+    }
+    Some(Event::RuleList(rules))
+}
+
+fn decode_pending_timeout(reader: &mut Reader) -> Option<Event> {
+    let verdict = reader.u8()?;
+    let count = reader.u32()? as usize;
+    // Don't trust the count for the allocation.
+    let mut ids = Vec::with_capacity(count.min(reader.len() / 8));
+    for _ in 0..count {
+        ids.push(reader.u64()?);
+    }
+    Some(Event::PendingTimeout(PendingTimeout { verdict, ids }))
+}
+
+fn decode_queue_overflow(reader: &mut Reader) -> Option<Event> {
+    Some(Event::QueueOverflow(QueueOverflow {
+        pending_packets: reader.u32()?,
+        events: reader.u32()?,
+    }))
+}
+
+fn decode_handshake(reader: &mut Reader) -> Option<Event> {
+    Some(Event::Handshake(Handshake {
+        version: reader.u32()?,
+        commands: reader.u64()?,
+        info_types: reader.u64()?,
+    }))
+}
+
+fn decode_verdict_batch_result(reader: &mut Reader) -> Option<Event> {
+    let count = reader.u32()? as usize;
+    // Don't trust the count for the allocation, every entry is 9 bytes.
+    let mut failed = Vec::with_capacity(count.min(reader.len() / 9));
+    for _ in 0..count {
+        failed.push(VerdictFailure {
+            id: reader.u64()?,
+            error: reader.u8()?,
+        });
+    }
+    Some(Event::VerdictBatchResult(failed))
+}
+
+fn decode_command_result(reader: &mut Reader) -> Option<Event> {
+    let request_id = reader.u64()?;
+    let code = reader.u8()?;
+    let reason = reader.rest();
+    Some(Event::CommandResult(CommandResult {
+        request_id,
+        code,
+        reason: String::from_utf8_lossy(reason).into_owned(),
+    }))
+}
+
+fn decode_process_info(reader: &mut Reader) -> Option<Event> {
+    let process_id = reader.u64()?;
+    let path = reader.rest();
+    Some(Event::ProcessInfo(ProcessInfo {
+        process_id,
+        path: String::from_utf8_lossy(path).into_owned(),
+    }))
+}
+
+/// Streaming decoder. The driver splits frames between read requests (see `Device::read`),
+/// so the bytes of every read are pushed as they come and complete frames are returned.
+#[derive(Default)]
+pub struct Decoder {
+    buffer: Vec<u8>,
+    start: usize,
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends bytes that were read from the driver.
+    pub fn push(&mut self, bytes: &[u8]) {
+        if self.start > 0 {
+            // Drop already decoded frames before growing the buffer.
+            self.buffer.drain(..self.start);
+            self.start = 0;
+        }
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Returns the next event. `None` means more bytes are needed.
+    /// Frames that fail to decode are skipped and the error is returned.
+    pub fn next_event(&mut self) -> Option<Result<Event, DecodeError>> {
+        let bytes = &self.buffer[self.start..];
+        match decode_frame(bytes) {
+            Ok((event, size)) => {
+                self.start += size;
+                Some(Ok(event))
+            }
+            Err(DecodeError::Incomplete) => None,
+            Err(err) => {
+                // The header is valid, skip the whole frame.
+                self.start += frame_size(bytes).unwrap_or(bytes.len());
+                Some(Err(err))
+            }
+        }
+    }
+
+    /// Number of buffered bytes that are not decoded yet.
+    pub fn pending_bytes(&self) -> usize {
+        self.buffer.len() - self.start
+    }
+}
+
+#[cfg(test)]
+use rand::Rng;
+
+#[cfg(test)]
+fn expected_event(info_type: u8) -> Event {
+    let ipv4_local = IpAddress::V4([1, 2, 3, 4]);
+    let ipv4_remote = IpAddress::V4([2, 3, 4, 5]);
+    let ipv6_local = IpAddress::V6([1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16]);
+    let ipv6_remote = IpAddress::V6([2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17]);
+    let connection =
+        |local_ip, remote_ip, interface_index, sub_interface_index, original_length| {
+            Event::Connection(Connection {
+                id: 1,
+                process_id: 2,
+                direction: 3,
+                protocol: 4,
+                local_ip,
+                remote_ip,
+                local_port: 5,
+                remote_port: 6,
+                payload_layer: 7,
+                interface_index,
+                sub_interface_index,
+                original_length,
+                payload: alloc::vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10],
+            })
+        };
+    let connection_end = |local_ip, remote_ip| {
+        Event::ConnectionEnd(ConnectionEnd {
+            process_id: 1,
+            direction: 2,
+            protocol: 3,
+            local_ip,
+            remote_ip,
+            local_port: 4,
+            remote_port: 5,
+        })
+    };
+    let bandwidth = |local_ip, remote_ip, ext: bool| {
+        // The basic format has no packet counts and timestamps.
+        let ext = |value: u64| if ext { value } else { 0 };
+        Event::Bandwidth(BandwidthStats {
+            protocol: 1,
+            values: alloc::vec![
+                BandwidthValue {
+                    local_ip,
+                    local_port: 1,
+                    remote_ip,
+                    remote_port: 2,
+                    transmitted_bytes: 3,
+                    received_bytes: 4,
+                    transmitted_packets: ext(9),
+                    received_packets: ext(10),
+                    first_seen_timestamp: ext(11),
+                    last_seen_timestamp: ext(12),
+                },
+                BandwidthValue {
+                    local_ip,
+                    local_port: 5,
+                    remote_ip,
+                    remote_port: 6,
+                    transmitted_bytes: 7,
+                    received_bytes: 8,
+                    transmitted_packets: ext(13),
+                    received_packets: ext(14),
+                    first_seen_timestamp: ext(15),
+                    last_seen_timestamp: ext(16),
+                },
+            ],
+        })
+    };
+    let connection_dump = |local_ip, remote_ip| {
+        let value = |end_timestamp| ConnectionDumpValue {
+            protocol: 1,
+            local_ip,
+            local_port: 2,
+            remote_ip,
+            remote_port: 3,
+            direction: 4,
+            verdict: 5,
+            process_id: 6,
+            last_accessed_timestamp: 7,
+            end_timestamp,
+        };
+        Event::ConnectionDump(alloc::vec![value(8), value(0)])
+    };
+
+    match InfoType::from_u8(info_type).unwrap() {
+        InfoType::LogLine => Event::LogLine(LogLine {
+            severity: 1,
+            line: String::from("prefix: test log"),
+        }),
+        InfoType::ConnectionIpv4 => connection(ipv4_local, ipv4_remote, 0, 0, 10),
+        InfoType::ConnectionIpv6 => connection(ipv6_local, ipv6_remote, 0, 0, 10),
+        InfoType::ConnectionIpv4Ext => connection(ipv4_local, ipv4_remote, 8, 9, 20),
+        InfoType::ConnectionIpv6Ext => connection(ipv6_local, ipv6_remote, 8, 9, 20),
+        InfoType::ConnectionEndEventV4 => connection_end(ipv4_local, ipv4_remote),
+        InfoType::ConnectionEndEventV6 => connection_end(ipv6_local, ipv6_remote),
+        InfoType::BandwidthStatsV4 => bandwidth(ipv4_local, ipv4_remote, false),
+        InfoType::BandwidthStatsV6 => bandwidth(ipv6_local, ipv6_remote, false),
+        InfoType::BandwidthStatsV4Ext => bandwidth(ipv4_local, ipv4_remote, true),
+        InfoType::BandwidthStatsV6Ext => bandwidth(ipv6_local, ipv6_remote, true),
+        InfoType::Handshake => Event::Handshake(Handshake {
+            version: 1,
+            commands: 2,
+            info_types: 3,
+        }),
+        InfoType::VerdictBatchResult => Event::VerdictBatchResult(alloc::vec![
+            VerdictFailure { id: 1, error: 1 },
+            VerdictFailure { id: 2, error: 2 },
+            VerdictFailure { id: 3, error: 3 },
+        ]),
+        InfoType::CommandResult => Event::CommandResult(CommandResult {
+            request_id: 1,
+            code: 2,
+            reason: String::from("test reason"),
+        }),
+        InfoType::ProcessInfo => Event::ProcessInfo(ProcessInfo {
+            process_id: 1,
+            path: String::from("C:\\Windows\\System32\\svchost.exe"),
+        }),
+        InfoType::LogLinesDropped => Event::LogLinesDropped(1),
+        InfoType::ConnectionDumpV4 => connection_dump(ipv4_local, ipv4_remote),
+        InfoType::ConnectionDumpV6 => connection_dump(ipv6_local, ipv6_remote),
+        InfoType::ConnectionDumpEnd => Event::ConnectionDumpEnd(4),
+        InfoType::MemoryStats => Event::MemoryStats(super::test_memory_stats()),
+        InfoType::RuleList => Event::RuleList(alloc::vec![
+            crate::rules::test_rule(),
+            crate::rules::test_rule()
+        ]),
+        InfoType::PendingTimeout => Event::PendingTimeout(PendingTimeout {
+            verdict: 2,
+            ids: alloc::vec![1, 2, 3],
+        }),
+        InfoType::QueueOverflow => Event::QueueOverflow(QueueOverflow {
+            pending_packets: 1,
+            events: 2,
+        }),
+        InfoType::BandwidthStatsPerProcess => Event::BandwidthPerProcess(alloc::vec![
+            BandwidthProcessValue {
+                process_id: 1,
+                protocol: 6,
+                transmitted_bytes: 2,
+                received_bytes: 3,
+            },
+            BandwidthProcessValue {
+                process_id: 4,
+                protocol: 17,
+                transmitted_bytes: 5,
+                received_bytes: 6,
+            },
+        ]),
+        InfoType::LogRecord => Event::LogRecord(LogRecord {
+            timestamp: 1,
+            severity: super::Severity::Warning as u8,
+            truncated: false,
+            cpu: 2,
+            irql: 3,
+            file: String::from("file.rs"),
+            line: 4,
+            message: String::from("test message"),
+            fields: alloc::vec![
+                LogField {
+                    key: String::from("pid"),
+                    value: LogFieldValue::U64(5),
+                },
+                LogField {
+                    key: String::from("delta"),
+                    value: LogFieldValue::I64(-6),
+                },
+                LogField {
+                    key: String::from("verdict"),
+                    value: LogFieldValue::Str(String::from("Accept")),
+                },
+                LogField {
+                    key: String::from("key"),
+                    value: LogFieldValue::Connection(super::LogConnection {
+                        protocol: 6,
+                        local_ip: ipv4_local,
+                        local_port: 7,
+                        remote_ip: ipv6_remote,
+                        remote_port: 8,
+                    }),
+                },
+            ],
+        }),
+    }
+}
+
+#[test]
+fn test_decode_rust_info_file() {
+    let bytes = {
+        let _guard = super::TEST_FILE_LOCK.lock().unwrap();
+        std::fs::read("rust_info_test.bin").unwrap()
+    };
+
+    // Decode the whole file at once.
+    let mut offset = 0;
+    let mut count = 0;
+    while offset < bytes.len() {
+        let (event, size) = decode_frame(&bytes[offset..]).unwrap();
+        assert_eq!(event, expected_event(bytes[offset]));
+        offset += size;
+        count += 1;
+    }
+    assert!(count > 0);
+
+    // Feed the same bytes in random chunks, the way the driver splits them between reads.
+    let mut decoder = Decoder::new();
+    let mut rng = rand::thread_rng();
+    let mut decoded = 0;
+    let mut remaining = &bytes[..];
+    while !remaining.is_empty() {
+        let chunk = rng.gen_range(1..=remaining.len().min(64));
+        decoder.push(&remaining[..chunk]);
+        remaining = &remaining[chunk..];
+        while let Some(event) = decoder.next_event() {
+            assert!(event.is_ok());
+            decoded += 1;
+        }
+    }
+    assert_eq!(decoded, count);
+    assert_eq!(decoder.pending_bytes(), 0);
+}
+
+#[test]
+fn test_decode_errors() {
+    let mut info = super::log_line(super::Severity::Error, 10);
+    use core::fmt::Write;
+    _ = write!(info, "line");
+    let bytes = info.as_bytes();
+
+    // Every prefix of a frame is incomplete.
+    for i in 0..bytes.len() {
+        assert_eq!(decode_frame(&bytes[..i]), Err(DecodeError::Incomplete));
+    }
+
+    // Unknown frames are skipped by the decoder.
+    let mut decoder = Decoder::new();
+    decoder.push(&[200, 3, 0, 0, 0, 1, 2, 3]);
+    decoder.push(bytes);
+    assert_eq!(
+        decoder.next_event(),
+        Some(Err(DecodeError::UnknownInfoType(200)))
+    );
+    assert_eq!(
+        decoder.next_event(),
+        Some(Ok(Event::LogLine(LogLine {
+            severity: super::Severity::Error as u8,
+            line: String::from("line"),
+        })))
+    );
+    assert_eq!(decoder.next_event(), None);
+
+    // Size that does not match the content.
+    let end = super::connection_end_event_v4_info(1, 2, 3, [1, 2, 3, 4], [2, 3, 4, 5], 4, 5);
+    let mut bytes = end.as_bytes().to_vec();
+    bytes[1] += 1;
+    bytes.push(0);
+    assert_eq!(
+        decode_frame(&bytes),
+        Err(DecodeError::InvalidFrame(
+            InfoType::ConnectionEndEventV4 as u8
+        ))
+    );
+}
+
+#[test]
+fn test_decode_process_info() {
+    // Paths are converted from UTF-16 by the driver and can contain any character.
+    let path = "C:\\Users\\Jürgen\\AppData\\Local\\应用\\app.exe";
+    let info = super::process_info(1234, path);
+    assert_eq!(
+        decode_frame(info.as_bytes()),
+        Ok((
+            Event::ProcessInfo(ProcessInfo {
+                process_id: 1234,
+                path: String::from(path),
+            }),
+            info.as_bytes().len()
+        ))
+    );
+
+    let info = super::process_info(1, "");
+    assert_eq!(
+        decode_frame(info.as_bytes()).map(|(event, _)| event),
+        Ok(Event::ProcessInfo(ProcessInfo {
+            process_id: 1,
+            path: String::new(),
+        }))
+    );
+}
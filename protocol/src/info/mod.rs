@@ -0,0 +1,1938 @@
+use alloc::vec::Vec;
+use num_derive::FromPrimitive;
+use num_traits::FromPrimitive;
+
+use crate::rules::Rule;
+
+pub mod decode;
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, FromPrimitive)]
+pub enum InfoType {
+    LogLine = 0,
+    ConnectionIpv4 = 1,
+    ConnectionIpv6 = 2,
+    ConnectionEndEventV4 = 3,
+    ConnectionEndEventV6 = 4,
+    BandwidthStatsV4 = 5,
+    BandwidthStatsV6 = 6,
+    Handshake = 7,
+    VerdictBatchResult = 8,
+    CommandResult = 9,
+    ProcessInfo = 10,
+    ConnectionIpv4Ext = 11,
+    ConnectionIpv6Ext = 12,
+    LogLinesDropped = 13,
+    LogRecord = 14,
+    ConnectionDumpV4 = 15,
+    ConnectionDumpV6 = 16,
+    ConnectionDumpEnd = 17,
+    MemoryStats = 18,
+    RuleList = 19,
+    PendingTimeout = 20,
+    QueueOverflow = 21,
+    BandwidthStatsPerProcess = 22,
+    BandwidthStatsV4Ext = 23,
+    BandwidthStatsV6Ext = 24,
+}
+
+/// Bitset of the info types this build can produce. Bit `n` is set for the `InfoType` with value `n`.
+pub const SUPPORTED_INFO_TYPES: u64 = (1 << (InfoType::BandwidthStatsV6Ext as u64 + 1)) - 1;
+
+/// Info types that clients understood before the handshake was introduced.
+/// Used for clients that don't send a handshake.
+pub const LEGACY_INFO_TYPES: u64 = (1 << (InfoType::BandwidthStatsV6 as u64 + 1)) - 1;
+
+// Fallow this pattern when adding new packets: [InfoType: u8, data_size_in_bytes: u32, data: ...]
+
+trait PushBytes {
+    fn push(self, vec: &mut Vec<u8>);
+}
+
+impl PushBytes for u8 {
+    fn push(self, vec: &mut Vec<u8>) {
+        vec.push(self);
+    }
+}
+
+impl PushBytes for InfoType {
+    fn push(self, vec: &mut Vec<u8>) {
+        vec.push(self as u8);
+    }
+}
+
+impl PushBytes for u16 {
+    fn push(self, vec: &mut Vec<u8>) {
+        vec.extend_from_slice(&u16::to_le_bytes(self));
+    }
+}
+
+impl PushBytes for u32 {
+    fn push(self, vec: &mut Vec<u8>) {
+        vec.extend_from_slice(&u32::to_le_bytes(self));
+    }
+}
+
+impl PushBytes for u64 {
+    fn push(self, vec: &mut Vec<u8>) {
+        vec.extend_from_slice(&u64::to_le_bytes(self));
+    }
+}
+
+impl PushBytes for usize {
+    fn push(self, vec: &mut Vec<u8>) {
+        vec.extend_from_slice(&usize::to_le_bytes(self));
+    }
+}
+
+impl PushBytes for [u8; 4] {
+    fn push(self, vec: &mut Vec<u8>) {
+        vec.extend_from_slice(&self);
+    }
+}
+
+impl PushBytes for [u8; 16] {
+    fn push(self, vec: &mut Vec<u8>) {
+        vec.extend_from_slice(&self);
+    }
+}
+
+impl PushBytes for &[u8] {
+    fn push(self, vec: &mut Vec<u8>) {
+        vec.extend_from_slice(self);
+    }
+}
+
+macro_rules! push_bytes {
+    ($vec:expr,$value:expr) => {
+        PushBytes::push($value, $vec);
+    };
+}
+
+macro_rules! get_combined_size{
+    ($($a:expr),*)=>{{0 $(+core::mem::size_of_val(&$a))*}}
+}
+
+pub struct Info(Vec<u8>);
+
+impl Info {
+    fn new(info_type: InfoType, size: usize) -> Self {
+        let mut vec = Vec::with_capacity(size + 5); // +1 for the info type +4 for the size.
+        push_bytes!(&mut vec, info_type);
+        push_bytes!(&mut vec, size as u32);
+        Self(vec)
+    }
+
+    fn with_capacity(info_type: InfoType, capacity: usize) -> Self {
+        let mut vec = Vec::with_capacity(capacity + 5); // +1 for the info type + 4 for the size.
+        push_bytes!(&mut vec, info_type);
+        push_bytes!(&mut vec, 0_u32);
+        Self(vec)
+    }
+
+    #[cfg(test)]
+    fn assert_size(&self) {
+        let size = u32::from_le_bytes([self.0[1], self.0[2], self.0[3], self.0[4]]) as usize;
+        assert_eq!(size, self.0.len() - 5);
+    }
+
+    fn update_size(&mut self) {
+        let size = self.0.len() - 5;
+        let bytes = &mut self.0;
+        bytes[1] = size as u8;
+        bytes[2] = (size >> 8) as u8;
+        bytes[3] = (size >> 16) as u8;
+        bytes[4] = (size >> 24) as u8;
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        self.0.as_slice()
+    }
+
+    pub fn info_type(&self) -> u8 {
+        self.0[0]
+    }
+
+    /// Returns the info in a format that a client that declared `info_types` can decode.
+    /// Extended connection and bandwidth infos are converted to the basic ones for older clients.
+    /// Returns `None` if the client does not support any format of this info.
+    pub fn for_client(mut self, info_types: u64) -> Option<Info> {
+        if self.is_supported(info_types) {
+            return Some(self);
+        }
+
+        let (basic_type, ip_size) = match InfoType::from_u8(self.info_type()) {
+            Some(InfoType::ConnectionIpv4Ext) => (InfoType::ConnectionIpv4, 4),
+            Some(InfoType::ConnectionIpv6Ext) => (InfoType::ConnectionIpv6, 16),
+            Some(InfoType::LogRecord) if info_types & (1 << InfoType::LogLine as u64) != 0 => {
+                return self.log_record_to_line();
+            }
+            Some(InfoType::BandwidthStatsV4Ext) => {
+                return self.bandwidth_to_basic(InfoType::BandwidthStatsV4, 4, info_types);
+            }
+            Some(InfoType::BandwidthStatsV6Ext) => {
+                return self.bandwidth_to_basic(InfoType::BandwidthStatsV6, 16, info_types);
+            }
+            _ => return None,
+        };
+        if info_types & (1 << basic_type as u64) == 0 {
+            return None;
+        }
+
+        // Remove the interface indexes and the original length: they come after id, process_id,
+        // direction, protocol, local and remote ip, local and remote port and payload_layer.
+        let offset = 5 + 8 + 8 + 1 + 1 + 2 * ip_size + 2 + 2 + 1;
+        self.0.drain(offset..offset + 12);
+        self.0[0] = basic_type as u8;
+        self.update_size();
+        Some(self)
+    }
+
+    /// Removes the packet counts and timestamps from the values of an extended bandwidth info.
+    fn bandwidth_to_basic(
+        mut self,
+        basic_type: InfoType,
+        ip_size: usize,
+        info_types: u64,
+    ) -> Option<Info> {
+        if info_types & (1 << basic_type as u64) == 0 {
+            return None;
+        }
+
+        // Info header, protocol and count. A value is the addresses, the ports, the byte counts
+        // and then the 4 u64 that the basic format doesn't have.
+        let header_size = 5 + 1 + 4;
+        let basic_size = 2 * ip_size + 2 + 2 + 8 + 8;
+        let ext_size = basic_size + 4 * 8;
+        let count = (self.0.len() - header_size) / ext_size;
+        for i in 0..count {
+            let start = header_size + i * ext_size;
+            self.0
+                .copy_within(start..start + basic_size, header_size + i * basic_size);
+        }
+        self.0.truncate(header_size + count * basic_size);
+        self.0[0] = basic_type as u8;
+        self.update_size();
+        Some(self)
+    }
+
+    /// Converts a log record to a log line with the old text format: "file:line message key=value".
+    fn log_record_to_line(&self) -> Option<Info> {
+        let Ok((decode::Event::LogRecord(record), _)) = decode::decode_frame(&self.0) else {
+            return None;
+        };
+        let severity = Severity::from_u8(record.severity)?;
+        let mut info = log_line(severity, self.0.len());
+        use core::fmt::Write;
+        _ = write!(info, "{}:{} {}", record.file, record.line, record.message);
+        for field in &record.fields {
+            _ = write!(info, " {}={}", field.key, field.value);
+        }
+        Some(info)
+    }
+
+    /// Returns true if a client that declared `info_types` can decode this info.
+    /// The handshake reply is always supported.
+    pub fn is_supported(&self, info_types: u64) -> bool {
+        let info_type = self.info_type();
+        if info_type == InfoType::Handshake as u8 {
+            return true;
+        }
+        match 1_u64.checked_shl(info_type as u32) {
+            Some(bit) => info_types & bit != 0,
+            None => false,
+        }
+    }
+}
+
+impl core::fmt::Write for Info {
+    fn write_str(&mut self, s: &str) -> Result<(), core::fmt::Error> {
+        const MAX_CAPACITY: usize = 500;
+
+        let space_left = self.0.capacity() - self.0.len();
+        if s.len() > space_left {
+            if self.0.capacity() < MAX_CAPACITY {
+                self.0.reserve(MAX_CAPACITY);
+            } else {
+                return Ok(());
+            }
+        }
+
+        self.0.extend_from_slice(s.as_bytes());
+        self.update_size();
+        Ok(())
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn connection_info_v4(
+    id: u64,
+    process_id: u64,
+    direction: u8,
+    protocol: u8,
+    local_ip: [u8; 4],
+    remote_ip: [u8; 4],
+    local_port: u16,
+    remote_port: u16,
+    payload_layer: u8,
+    payload: &[u8],
+) -> Info {
+    let mut size = get_combined_size!(
+        id,
+        process_id,
+        direction,
+        protocol,
+        local_ip,
+        remote_ip,
+        local_port,
+        remote_port,
+        payload_layer,
+        payload.len() as u32
+    );
+    size += payload.len();
+
+    let mut info = Info::new(InfoType::ConnectionIpv4, size);
+    let vec = &mut info.0;
+    push_bytes!(vec, id);
+    push_bytes!(vec, process_id);
+    push_bytes!(vec, direction);
+    push_bytes!(vec, protocol);
+    push_bytes!(vec, local_ip);
+    push_bytes!(vec, remote_ip);
+    push_bytes!(vec, local_port);
+    push_bytes!(vec, remote_port);
+    push_bytes!(vec, payload_layer);
+    push_bytes!(vec, payload.len() as u32);
+    push_bytes!(vec, payload);
+    info
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn connection_info_v6(
+    id: u64,
+    process_id: u64,
+    direction: u8,
+    protocol: u8,
+    local_ip: [u8; 16],
+    remote_ip: [u8; 16],
+    local_port: u16,
+    remote_port: u16,
+    payload_layer: u8,
+    payload: &[u8],
+) -> Info {
+    let mut size = get_combined_size!(
+        id,
+        process_id,
+        direction,
+        protocol,
+        local_ip,
+        remote_ip,
+        local_port,
+        remote_port,
+        payload_layer,
+        payload.len() as u32
+    );
+    size += payload.len();
+    let mut info = Info::new(InfoType::ConnectionIpv6, size);
+    let vec = &mut info.0;
+    push_bytes!(vec, id);
+    push_bytes!(vec, process_id);
+    push_bytes!(vec, direction);
+    push_bytes!(vec, protocol);
+    push_bytes!(vec, local_ip);
+    push_bytes!(vec, remote_ip);
+    push_bytes!(vec, local_port);
+    push_bytes!(vec, remote_port);
+    push_bytes!(vec, payload_layer);
+    push_bytes!(vec, payload.len() as u32);
+    if !payload.is_empty() {
+        push_bytes!(vec, payload);
+    }
+    info
+}
+
+/// Same as `connection_info_v4` with the interface on which the packet was seen and the original
+/// payload length. `payload` can be shorter than `original_length` if it was cut to the payload limit.
+/// Format: [connection_info_v4 fields up to payload_layer, interface_index: u32, sub_interface_index: u32,
+/// original_length: u32, payload_size: u32, payload: ...]
+#[allow(clippy::too_many_arguments)]
+pub fn connection_info_v4_ext(
+    id: u64,
+    process_id: u64,
+    direction: u8,
+    protocol: u8,
+    local_ip: [u8; 4],
+    remote_ip: [u8; 4],
+    local_port: u16,
+    remote_port: u16,
+    payload_layer: u8,
+    interface_index: u32,
+    sub_interface_index: u32,
+    original_length: u32,
+    payload: &[u8],
+) -> Info {
+    let mut size = get_combined_size!(
+        id,
+        process_id,
+        direction,
+        protocol,
+        local_ip,
+        remote_ip,
+        local_port,
+        remote_port,
+        payload_layer,
+        interface_index,
+        sub_interface_index,
+        original_length,
+        payload.len() as u32
+    );
+    size += payload.len();
+
+    let mut info = Info::new(InfoType::ConnectionIpv4Ext, size);
+    let vec = &mut info.0;
+    push_bytes!(vec, id);
+    push_bytes!(vec, process_id);
+    push_bytes!(vec, direction);
+    push_bytes!(vec, protocol);
+    push_bytes!(vec, local_ip);
+    push_bytes!(vec, remote_ip);
+    push_bytes!(vec, local_port);
+    push_bytes!(vec, remote_port);
+    push_bytes!(vec, payload_layer);
+    push_bytes!(vec, interface_index);
+    push_bytes!(vec, sub_interface_index);
+    push_bytes!(vec, original_length);
+    push_bytes!(vec, payload.len() as u32);
+    push_bytes!(vec, payload);
+    info
+}
+
+/// Same as `connection_info_v6` with the interface on which the packet was seen.
+/// The format matches `connection_info_v4_ext`.
+#[allow(clippy::too_many_arguments)]
+pub fn connection_info_v6_ext(
+    id: u64,
+    process_id: u64,
+    direction: u8,
+    protocol: u8,
+    local_ip: [u8; 16],
+    remote_ip: [u8; 16],
+    local_port: u16,
+    remote_port: u16,
+    payload_layer: u8,
+    interface_index: u32,
+    sub_interface_index: u32,
+    original_length: u32,
+    payload: &[u8],
+) -> Info {
+    let mut size = get_combined_size!(
+        id,
+        process_id,
+        direction,
+        protocol,
+        local_ip,
+        remote_ip,
+        local_port,
+        remote_port,
+        payload_layer,
+        interface_index,
+        sub_interface_index,
+        original_length,
+        payload.len() as u32
+    );
+    size += payload.len();
+
+    let mut info = Info::new(InfoType::ConnectionIpv6Ext, size);
+    let vec = &mut info.0;
+    push_bytes!(vec, id);
+    push_bytes!(vec, process_id);
+    push_bytes!(vec, direction);
+    push_bytes!(vec, protocol);
+    push_bytes!(vec, local_ip);
+    push_bytes!(vec, remote_ip);
+    push_bytes!(vec, local_port);
+    push_bytes!(vec, remote_port);
+    push_bytes!(vec, payload_layer);
+    push_bytes!(vec, interface_index);
+    push_bytes!(vec, sub_interface_index);
+    push_bytes!(vec, original_length);
+    push_bytes!(vec, payload.len() as u32);
+    push_bytes!(vec, payload);
+    info
+}
+
+pub fn connection_end_event_v4_info(
+    process_id: u64,
+    direction: u8,
+    protocol: u8,
+    local_ip: [u8; 4],
+    remote_ip: [u8; 4],
+    local_port: u16,
+    remote_port: u16,
+) -> Info {
+    let size = get_combined_size!(
+        process_id,
+        direction,
+        protocol,
+        local_ip,
+        remote_ip,
+        local_port,
+        remote_port
+    );
+    let mut info = Info::new(InfoType::ConnectionEndEventV4, size);
+    let vec = &mut info.0;
+    push_bytes!(vec, process_id);
+    push_bytes!(vec, direction);
+    push_bytes!(vec, protocol);
+    push_bytes!(vec, local_ip);
+    push_bytes!(vec, remote_ip);
+    push_bytes!(vec, local_port);
+    push_bytes!(vec, remote_port);
+    info
+}
+
+pub fn connection_end_event_v6_info(
+    process_id: u64,
+    direction: u8,
+    protocol: u8,
+    local_ip: [u8; 16],
+    remote_ip: [u8; 16],
+    local_port: u16,
+    remote_port: u16,
+) -> Info {
+    let size = get_combined_size!(
+        process_id,
+        direction,
+        protocol,
+        local_ip,
+        remote_ip,
+        local_port,
+        remote_port
+    );
+    let mut info = Info::new(InfoType::ConnectionEndEventV6, size);
+    let vec = &mut info.0;
+    push_bytes!(vec, process_id);
+    push_bytes!(vec, direction);
+    push_bytes!(vec, protocol);
+    push_bytes!(vec, local_ip);
+    push_bytes!(vec, remote_ip);
+    push_bytes!(vec, local_port);
+    push_bytes!(vec, remote_port);
+    info
+}
+
+/// Sent before the first connection of a process: [process_id: u64, path: ...]
+/// The path is the process image path as reported by the ALE layer, UTF-8 encoded.
+pub fn process_info(process_id: u64, path: &str) -> Info {
+    let mut size = get_combined_size!(process_id);
+    size += path.len();
+
+    let mut info = Info::new(InfoType::ProcessInfo, size);
+    let vec = &mut info.0;
+    push_bytes!(vec, process_id);
+    push_bytes!(vec, path.as_bytes());
+    info
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, FromPrimitive)]
+pub enum Severity {
+    Trace = 1,
+    Debug = 2,
+    Info = 3,
+    Warning = 4,
+    Error = 5,
+    Critical = 6,
+    Disabled = 7,
+}
+
+// pub fn log_line(severity: Severity, prefix: String, line: String) -> Info {
+//     let mut size = get_combined_size!(severity);
+//     size += prefix.len() + line.len();
+
+//     let mut info = Info::new(InfoType::LogLine, size);
+//     let vec = &mut info.0;
+//     push_bytes!(vec, severity as u8);
+//     push_bytes!(vec, prefix.as_bytes());
+//     push_bytes!(vec, line.as_bytes());
+//     info
+// }
+
+pub fn log_line(severity: Severity, capacity: usize) -> Info {
+    let mut info = Info::with_capacity(InfoType::LogLine, capacity);
+    let vec = &mut info.0;
+    push_bytes!(vec, severity as u8);
+    info
+}
+
+/// Set in the flags of a log record if the message or a field was cut.
+pub const LOG_RECORD_TRUNCATED: u8 = 1;
+
+/// Value of a log record field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogValue<'a> {
+    U64(u64),
+    I64(i64),
+    Str(&'a str),
+    Connection(LogConnection),
+}
+
+impl LogValue<'_> {
+    fn kind(&self) -> u8 {
+        match self {
+            LogValue::U64(_) => 0,
+            LogValue::I64(_) => 1,
+            LogValue::Str(_) => 2,
+            LogValue::Connection(_) => 3,
+        }
+    }
+}
+
+/// Connection key as a log record field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LogConnection {
+    pub protocol: u8,
+    pub local_ip: decode::IpAddress,
+    pub local_port: u16,
+    pub remote_ip: decode::IpAddress,
+    pub remote_port: u16,
+}
+
+fn push_ip(vec: &mut Vec<u8>, ip: decode::IpAddress) {
+    match ip {
+        decode::IpAddress::V4(ip) => {
+            push_bytes!(vec, 4_u8);
+            push_bytes!(vec, ip);
+        }
+        decode::IpAddress::V6(ip) => {
+            push_bytes!(vec, 6_u8);
+            push_bytes!(vec, ip);
+        }
+    }
+}
+
+/// Returns the longest prefix of `s` that fits in `max_len` bytes without splitting a character.
+fn truncate_str(s: &str, max_len: usize) -> &str {
+    if s.len() <= max_len {
+        return s;
+    }
+    let mut end = max_len;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+/// Structured log line. The message is written with `core::fmt::Write`, fields are added after it.
+/// Format: [timestamp: u64, severity: u8, flags: u8, cpu: u32, irql: u8, line: u32,
+/// file_size: u16, file: ..., message_size: u16, message: ..., field_count: u8, fields: ...]
+/// Field: [key_size: u8, key: ..., kind: u8, value: ...]
+/// Values: 0 u64, 1 i64, 2 [size: u16, str: ...],
+/// 3 [protocol: u8, local_ip, local_port: u16, remote_ip, remote_port: u16]
+/// with ip: [version: u8 (4 or 6), address: ...]
+/// `irql` is 0xFF if the driver can't read it.
+pub struct LogRecord {
+    info: Info,
+    max_message_size: usize,
+    message_size_offset: usize,
+    // Offset of the field count, set once the message is closed.
+    field_count_offset: Option<usize>,
+}
+
+// Offset of the flags byte: header, timestamp and severity.
+const LOG_RECORD_FLAGS_OFFSET: usize = 5 + 8 + 1;
+
+/// Starts a log record. `timestamp` is the Windows system time in milliseconds.
+/// Messages longer than `max_message_size` are cut and the record is marked as truncated.
+pub fn log_record(
+    timestamp: u64,
+    severity: Severity,
+    cpu: u32,
+    irql: u8,
+    file: &str,
+    line: u32,
+    max_message_size: usize,
+) -> LogRecord {
+    let file = truncate_str(file, u16::MAX as usize);
+    let max_message_size = max_message_size.min(u16::MAX as usize);
+    let mut size = get_combined_size!(timestamp, severity as u8, 0_u8, cpu, irql, line);
+    size += 2 + file.len() + 2 + max_message_size + 1;
+
+    let mut info = Info::with_capacity(InfoType::LogRecord, size);
+    let vec = &mut info.0;
+    push_bytes!(vec, timestamp);
+    push_bytes!(vec, severity as u8);
+    push_bytes!(vec, 0_u8);
+    push_bytes!(vec, cpu);
+    push_bytes!(vec, irql);
+    push_bytes!(vec, line);
+    push_bytes!(vec, file.len() as u16);
+    push_bytes!(vec, file.as_bytes());
+    let message_size_offset = vec.len();
+    push_bytes!(vec, 0_u16);
+    LogRecord {
+        info,
+        max_message_size,
+        message_size_offset,
+        field_count_offset: None,
+    }
+}
+
+impl LogRecord {
+    fn set_truncated(&mut self) {
+        self.info.0[LOG_RECORD_FLAGS_OFFSET] |= LOG_RECORD_TRUNCATED;
+    }
+
+    fn message_size(&self) -> usize {
+        self.info.0.len() - self.message_size_offset - 2
+    }
+
+    fn close_message(&mut self) -> usize {
+        if let Some(offset) = self.field_count_offset {
+            return offset;
+        }
+        let size = (self.message_size() as u16).to_le_bytes();
+        self.info.0[self.message_size_offset..self.message_size_offset + 2].copy_from_slice(&size);
+        let offset = self.info.0.len();
+        push_bytes!(&mut self.info.0, 0_u8);
+        self.field_count_offset = Some(offset);
+        offset
+    }
+
+    /// Adds a field. The message can't be written after the first field.
+    /// Fields over the maximum count of 255 are dropped and the record is marked as truncated.
+    pub fn field(&mut self, key: &str, value: LogValue) {
+        let count_offset = self.close_message();
+        let count = self.info.0[count_offset];
+        if count == u8::MAX {
+            self.set_truncated();
+            return;
+        }
+        self.info.0[count_offset] = count + 1;
+
+        let cut_key = truncate_str(key, u8::MAX as usize);
+        let mut truncated = cut_key.len() < key.len();
+        let key = cut_key;
+        let vec = &mut self.info.0;
+        push_bytes!(vec, key.len() as u8);
+        push_bytes!(vec, key.as_bytes());
+        push_bytes!(vec, value.kind());
+        match value {
+            LogValue::U64(value) => {
+                push_bytes!(vec, value);
+            }
+            LogValue::I64(value) => {
+                push_bytes!(vec, value as u64);
+            }
+            LogValue::Str(value) => {
+                let cut = truncate_str(value, u16::MAX as usize);
+                truncated |= cut.len() < value.len();
+                push_bytes!(vec, cut.len() as u16);
+                push_bytes!(vec, cut.as_bytes());
+            }
+            LogValue::Connection(connection) => {
+                push_bytes!(vec, connection.protocol);
+                push_ip(vec, connection.local_ip);
+                push_bytes!(vec, connection.local_port);
+                push_ip(vec, connection.remote_ip);
+                push_bytes!(vec, connection.remote_port);
+            }
+        }
+        if truncated {
+            self.set_truncated();
+        }
+    }
+
+    pub fn finish(mut self) -> Info {
+        self.close_message();
+        self.info.update_size();
+        self.info
+    }
+}
+
+impl core::fmt::Write for LogRecord {
+    fn write_str(&mut self, s: &str) -> Result<(), core::fmt::Error> {
+        if self.field_count_offset.is_some() {
+            return Err(core::fmt::Error);
+        }
+        let space_left = self.max_message_size - self.message_size();
+        let cut = truncate_str(s, space_left);
+        if cut.len() < s.len() {
+            self.set_truncated();
+        }
+        self.info.0.extend_from_slice(cut.as_bytes());
+        Ok(())
+    }
+}
+
+/// Number of log lines that were lost because the log buffer was full: [count: u32]
+pub fn log_lines_dropped_info(count: u32) -> Info {
+    let size = get_combined_size!(count);
+    let mut info = Info::new(InfoType::LogLinesDropped, size);
+    let vec = &mut info.0;
+    push_bytes!(vec, count);
+    info
+}
+
+// Special struct for Bandwidth stats. Packets are the IP packets for datagrams and the segments
+// with data for TCP. Timestamps are the Windows system time in milliseconds.
+pub struct BandwidthValueV4 {
+    pub local_ip: [u8; 4],
+    pub local_port: u16,
+    pub remote_ip: [u8; 4],
+    pub remote_port: u16,
+    pub transmitted_bytes: u64,
+    pub received_bytes: u64,
+    pub transmitted_packets: u64,
+    pub received_packets: u64,
+    pub first_seen_timestamp: u64,
+    pub last_seen_timestamp: u64,
+}
+
+impl BandwidthValueV4 {
+    fn get_size(&self) -> usize {
+        get_combined_size!(
+            self.local_ip,
+            self.local_port,
+            self.remote_ip,
+            self.remote_port,
+            self.transmitted_bytes,
+            self.received_bytes,
+            self.transmitted_packets,
+            self.received_packets,
+            self.first_seen_timestamp,
+            self.last_seen_timestamp
+        )
+    }
+}
+
+impl PushBytes for BandwidthValueV4 {
+    fn push(self, vec: &mut Vec<u8>) {
+        push_bytes!(vec, self.local_ip);
+        push_bytes!(vec, self.local_port);
+        push_bytes!(vec, self.remote_ip);
+        push_bytes!(vec, self.remote_port);
+        push_bytes!(vec, self.transmitted_bytes);
+        push_bytes!(vec, self.received_bytes);
+        push_bytes!(vec, self.transmitted_packets);
+        push_bytes!(vec, self.received_packets);
+        push_bytes!(vec, self.first_seen_timestamp);
+        push_bytes!(vec, self.last_seen_timestamp);
+    }
+}
+
+pub struct BandwidthValueV6 {
+    pub local_ip: [u8; 16],
+    pub local_port: u16,
+    pub remote_ip: [u8; 16],
+    pub remote_port: u16,
+    pub transmitted_bytes: u64,
+    pub received_bytes: u64,
+    pub transmitted_packets: u64,
+    pub received_packets: u64,
+    pub first_seen_timestamp: u64,
+    pub last_seen_timestamp: u64,
+}
+
+impl BandwidthValueV6 {
+    fn get_size(&self) -> usize {
+        get_combined_size!(
+            self.local_ip,
+            self.local_port,
+            self.remote_ip,
+            self.remote_port,
+            self.transmitted_bytes,
+            self.received_bytes,
+            self.transmitted_packets,
+            self.received_packets,
+            self.first_seen_timestamp,
+            self.last_seen_timestamp
+        )
+    }
+}
+
+impl PushBytes for BandwidthValueV6 {
+    fn push(self, vec: &mut Vec<u8>) {
+        push_bytes!(vec, self.local_ip);
+        push_bytes!(vec, self.local_port);
+        push_bytes!(vec, self.remote_ip);
+        push_bytes!(vec, self.remote_port);
+        push_bytes!(vec, self.transmitted_bytes);
+        push_bytes!(vec, self.received_bytes);
+        push_bytes!(vec, self.transmitted_packets);
+        push_bytes!(vec, self.received_packets);
+        push_bytes!(vec, self.first_seen_timestamp);
+        push_bytes!(vec, self.last_seen_timestamp);
+    }
+}
+
+/// Bandwidth stats of ipv4 connections: [protocol: u8, count: u32, count * BandwidthValueV4]
+/// Sent as `BandwidthStatsV4Ext`, older clients get `BandwidthStatsV4` without the packet counts
+/// and timestamps.
+pub fn bandiwth_stats_array_v4(protocol: u8, values: Vec<BandwidthValueV4>) -> Info {
+    let mut size = get_combined_size!(protocol, values.len() as u32);
+
+    if !values.is_empty() {
+        size += values[0].get_size() * values.len();
+    }
+
+    let mut info = Info::new(InfoType::BandwidthStatsV4Ext, size);
+    let vec = &mut info.0;
+    push_bytes!(vec, protocol);
+    push_bytes!(vec, values.len() as u32);
+    for v in values {
+        push_bytes!(vec, v);
+    }
+    info
+}
+
+/// Same as `bandiwth_stats_array_v4` for ipv6 connections.
+pub fn bandiwth_stats_array_v6(protocol: u8, values: Vec<BandwidthValueV6>) -> Info {
+    let mut size = get_combined_size!(protocol, values.len() as u32);
+
+    if !values.is_empty() {
+        size += values[0].get_size() * values.len();
+    }
+
+    let mut info = Info::new(InfoType::BandwidthStatsV6Ext, size);
+    let vec = &mut info.0;
+    push_bytes!(vec, protocol);
+    push_bytes!(vec, values.len() as u32);
+    for v in values {
+        push_bytes!(vec, v);
+    }
+    info
+}
+
+/// Splits the values in bandwidth stats infos of at most `max_frame_size` bytes.
+/// A `max_frame_size` of 0 puts all values in one info. An info has at least one value.
+pub fn bandwidth_stats_frames_v4(
+    protocol: u8,
+    values: Vec<BandwidthValueV4>,
+    max_frame_size: usize,
+) -> Vec<Info> {
+    let value_size = values.first().map_or(1, |value| value.get_size());
+    // Info header, protocol and value count.
+    let header_size = 5 + get_combined_size!(protocol, 0_u32);
+    split_bandwidth_values(values, header_size, value_size, max_frame_size)
+        .into_iter()
+        .map(|values| bandiwth_stats_array_v4(protocol, values))
+        .collect()
+}
+
+/// Splits the values in bandwidth stats infos of at most `max_frame_size` bytes.
+/// A `max_frame_size` of 0 puts all values in one info. An info has at least one value.
+pub fn bandwidth_stats_frames_v6(
+    protocol: u8,
+    values: Vec<BandwidthValueV6>,
+    max_frame_size: usize,
+) -> Vec<Info> {
+    let value_size = values.first().map_or(1, |value| value.get_size());
+    // Info header, protocol and value count.
+    let header_size = 5 + get_combined_size!(protocol, 0_u32);
+    split_bandwidth_values(values, header_size, value_size, max_frame_size)
+        .into_iter()
+        .map(|values| bandiwth_stats_array_v6(protocol, values))
+        .collect()
+}
+
+fn split_bandwidth_values<T>(
+    values: Vec<T>,
+    header_size: usize,
+    value_size: usize,
+    max_frame_size: usize,
+) -> Vec<Vec<T>> {
+    if values.is_empty() {
+        return Vec::new();
+    }
+    if max_frame_size == 0 {
+        return alloc::vec![values];
+    }
+    let per_frame = (max_frame_size.saturating_sub(header_size) / value_size).max(1);
+    let mut frames = Vec::with_capacity(values.len().div_ceil(per_frame));
+    let mut values = values.into_iter();
+    loop {
+        let frame: Vec<T> = values.by_ref().take(per_frame).collect();
+        if frame.is_empty() {
+            return frames;
+        }
+        frames.push(frame);
+    }
+}
+
+// Special struct for the per process bandwidth stats
+pub struct BandwidthValueProcess {
+    pub process_id: u64,
+    pub protocol: u8,
+    pub transmitted_bytes: u64,
+    pub received_bytes: u64,
+}
+
+impl BandwidthValueProcess {
+    fn get_size(&self) -> usize {
+        get_combined_size!(
+            self.process_id,
+            self.protocol,
+            self.transmitted_bytes,
+            self.received_bytes
+        )
+    }
+}
+
+impl PushBytes for BandwidthValueProcess {
+    fn push(self, vec: &mut Vec<u8>) {
+        push_bytes!(vec, self.process_id);
+        push_bytes!(vec, self.protocol);
+        push_bytes!(vec, self.transmitted_bytes);
+        push_bytes!(vec, self.received_bytes);
+    }
+}
+
+/// Bytes sent and received per process and protocol since the last bandwidth stats:
+/// [count: u32, count * [process_id: u64, protocol: u8, transmitted_bytes: u64, received_bytes: u64]]
+pub fn bandwidth_stats_per_process(values: Vec<BandwidthValueProcess>) -> Info {
+    let mut size = get_combined_size!(values.len() as u32);
+
+    if !values.is_empty() {
+        size += values[0].get_size() * values.len();
+    }
+
+    let mut info = Info::new(InfoType::BandwidthStatsPerProcess, size);
+    let vec = &mut info.0;
+    push_bytes!(vec, values.len() as u32);
+    for v in values {
+        push_bytes!(vec, v);
+    }
+    info
+}
+
+/// Splits the values in per process bandwidth stats infos of at most `max_frame_size` bytes.
+/// A `max_frame_size` of 0 puts all values in one info. An info has at least one value.
+pub fn bandwidth_stats_per_process_frames(
+    values: Vec<BandwidthValueProcess>,
+    max_frame_size: usize,
+) -> Vec<Info> {
+    let value_size = values.first().map_or(1, |value| value.get_size());
+    // Info header and value count.
+    let header_size = 5 + get_combined_size!(0_u32);
+    split_bandwidth_values(values, header_size, value_size, max_frame_size)
+        .into_iter()
+        .map(bandwidth_stats_per_process)
+        .collect()
+}
+
+// Special struct for the connection dump
+pub struct ConnectionDumpValueV4 {
+    pub protocol: u8,
+    pub local_ip: [u8; 4],
+    pub local_port: u16,
+    pub remote_ip: [u8; 4],
+    pub remote_port: u16,
+    pub direction: u8,
+    pub verdict: u8,
+    pub process_id: u64,
+    pub last_accessed_timestamp: u64,
+    /// 0 if the connection has not ended.
+    pub end_timestamp: u64,
+}
+
+impl ConnectionDumpValueV4 {
+    fn get_size(&self) -> usize {
+        get_combined_size!(
+            self.protocol,
+            self.local_ip,
+            self.local_port,
+            self.remote_ip,
+            self.remote_port,
+            self.direction,
+            self.verdict,
+            self.process_id,
+            self.last_accessed_timestamp,
+            self.end_timestamp
+        )
+    }
+}
+
+impl PushBytes for ConnectionDumpValueV4 {
+    fn push(self, vec: &mut Vec<u8>) {
+        push_bytes!(vec, self.protocol);
+        push_bytes!(vec, self.local_ip);
+        push_bytes!(vec, self.local_port);
+        push_bytes!(vec, self.remote_ip);
+        push_bytes!(vec, self.remote_port);
+        push_bytes!(vec, self.direction);
+        push_bytes!(vec, self.verdict);
+        push_bytes!(vec, self.process_id);
+        push_bytes!(vec, self.last_accessed_timestamp);
+        push_bytes!(vec, self.end_timestamp);
+    }
+}
+
+pub struct ConnectionDumpValueV6 {
+    pub protocol: u8,
+    pub local_ip: [u8; 16],
+    pub local_port: u16,
+    pub remote_ip: [u8; 16],
+    pub remote_port: u16,
+    pub direction: u8,
+    pub verdict: u8,
+    pub process_id: u64,
+    pub last_accessed_timestamp: u64,
+    /// 0 if the connection has not ended.
+    pub end_timestamp: u64,
+}
+
+impl ConnectionDumpValueV6 {
+    fn get_size(&self) -> usize {
+        get_combined_size!(
+            self.protocol,
+            self.local_ip,
+            self.local_port,
+            self.remote_ip,
+            self.remote_port,
+            self.direction,
+            self.verdict,
+            self.process_id,
+            self.last_accessed_timestamp,
+            self.end_timestamp
+        )
+    }
+}
+
+impl PushBytes for ConnectionDumpValueV6 {
+    fn push(self, vec: &mut Vec<u8>) {
+        push_bytes!(vec, self.protocol);
+        push_bytes!(vec, self.local_ip);
+        push_bytes!(vec, self.local_port);
+        push_bytes!(vec, self.remote_ip);
+        push_bytes!(vec, self.remote_port);
+        push_bytes!(vec, self.direction);
+        push_bytes!(vec, self.verdict);
+        push_bytes!(vec, self.process_id);
+        push_bytes!(vec, self.last_accessed_timestamp);
+        push_bytes!(vec, self.end_timestamp);
+    }
+}
+
+/// Part of a connection cache dump: [count: u32, count * ConnectionDumpValueV4]
+pub fn connection_dump_v4(values: Vec<ConnectionDumpValueV4>) -> Info {
+    let mut size = get_combined_size!(values.len() as u32);
+
+    if !values.is_empty() {
+        size += values[0].get_size() * values.len();
+    }
+
+    let mut info = Info::new(InfoType::ConnectionDumpV4, size);
+    let vec = &mut info.0;
+    push_bytes!(vec, values.len() as u32);
+    for v in values {
+        push_bytes!(vec, v);
+    }
+    info
+}
+
+/// Part of a connection cache dump: [count: u32, count * ConnectionDumpValueV6]
+pub fn connection_dump_v6(values: Vec<ConnectionDumpValueV6>) -> Info {
+    let mut size = get_combined_size!(values.len() as u32);
+
+    if !values.is_empty() {
+        size += values[0].get_size() * values.len();
+    }
+
+    let mut info = Info::new(InfoType::ConnectionDumpV6, size);
+    let vec = &mut info.0;
+    push_bytes!(vec, values.len() as u32);
+    for v in values {
+        push_bytes!(vec, v);
+    }
+    info
+}
+
+/// Sent after the last part of a connection cache dump: [count: u32]
+/// `count` is the number of connections in all parts of the dump.
+pub fn connection_dump_end_info(count: u32) -> Info {
+    let size = get_combined_size!(count);
+    let mut info = Info::new(InfoType::ConnectionDumpEnd, size);
+    let vec = &mut info.0;
+    push_bytes!(vec, count);
+    info
+}
+
+/// Entry counts of the driver caches and queues and the allocator totals.
+/// Reply to the `PrintMemoryStats` command. `timestamp` is the Windows system time in milliseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MemoryStats {
+    pub timestamp: u64,
+    /// Packets waiting for a verdict.
+    pub id_cache_entries: u64,
+    pub connections_v4: u64,
+    pub connections_v6: u64,
+    pub bandwidth_tcp_v4: u64,
+    pub bandwidth_tcp_v6: u64,
+    pub bandwidth_udp_v4: u64,
+    pub bandwidth_udp_v6: u64,
+    /// Infos waiting to be read by the client.
+    pub event_queue_depth: u64,
+    /// Bytes of a partially read info.
+    pub read_leftover_bytes: u64,
+    /// Bytes currently allocated by the driver.
+    pub allocated_bytes: u64,
+    /// Number of allocations that were not freed yet.
+    pub allocation_count: u64,
+    /// Number of allocations since the driver was loaded.
+    pub total_allocations: u64,
+    /// Bandwidth entries per process.
+    pub bandwidth_process: u64,
+    /// Bandwidth entries of protocols other than TCP and UDP.
+    pub bandwidth_other_v4: u64,
+    pub bandwidth_other_v6: u64,
+}
+
+impl MemoryStats {
+    fn get_size(&self) -> usize {
+        get_combined_size!(
+            self.timestamp,
+            self.id_cache_entries,
+            self.connections_v4,
+            self.connections_v6,
+            self.bandwidth_tcp_v4,
+            self.bandwidth_tcp_v6,
+            self.bandwidth_udp_v4,
+            self.bandwidth_udp_v6,
+            self.event_queue_depth,
+            self.read_leftover_bytes,
+            self.allocated_bytes,
+            self.allocation_count,
+            self.total_allocations,
+            self.bandwidth_process,
+            self.bandwidth_other_v4,
+            self.bandwidth_other_v6
+        )
+    }
+}
+
+impl PushBytes for MemoryStats {
+    fn push(self, vec: &mut Vec<u8>) {
+        push_bytes!(vec, self.timestamp);
+        push_bytes!(vec, self.id_cache_entries);
+        push_bytes!(vec, self.connections_v4);
+        push_bytes!(vec, self.connections_v6);
+        push_bytes!(vec, self.bandwidth_tcp_v4);
+        push_bytes!(vec, self.bandwidth_tcp_v6);
+        push_bytes!(vec, self.bandwidth_udp_v4);
+        push_bytes!(vec, self.bandwidth_udp_v6);
+        push_bytes!(vec, self.event_queue_depth);
+        push_bytes!(vec, self.read_leftover_bytes);
+        push_bytes!(vec, self.allocated_bytes);
+        push_bytes!(vec, self.allocation_count);
+        push_bytes!(vec, self.total_allocations);
+        push_bytes!(vec, self.bandwidth_process);
+        push_bytes!(vec, self.bandwidth_other_v4);
+        push_bytes!(vec, self.bandwidth_other_v6);
+    }
+}
+
+/// Memory usage of the driver: [16 * u64] in the order of the `MemoryStats` fields.
+pub fn memory_stats_info(stats: MemoryStats) -> Info {
+    let size = stats.get_size();
+    let mut info = Info::new(InfoType::MemoryStats, size);
+    let vec = &mut info.0;
+    push_bytes!(vec, stats);
+    info
+}
+
+/// Reply to the list rules command: [count: u32, count * Rule]
+pub fn rule_list_info(rules: &[Rule]) -> Info {
+    let mut size = get_combined_size!(rules.len() as u32);
+    size += core::mem::size_of_val(rules);
+
+    let mut info = Info::new(InfoType::RuleList, size);
+    let vec = &mut info.0;
+    push_bytes!(vec, rules.len() as u32);
+    for rule in rules {
+        rule.push(vec);
+    }
+    info
+}
+
+/// Pending packets that got no verdict in time. They were handled with `verdict`: [verdict: u8, count: u32, count * id: u64]
+pub fn pending_timeout_info(verdict: u8, ids: &[u64]) -> Info {
+    let mut size = get_combined_size!(verdict, ids.len() as u32);
+    size += core::mem::size_of_val(ids);
+
+    let mut info = Info::new(InfoType::PendingTimeout, size);
+    let vec = &mut info.0;
+    push_bytes!(vec, verdict);
+    push_bytes!(vec, ids.len() as u32);
+    for id in ids {
+        push_bytes!(vec, *id);
+    }
+    info
+}
+
+/// Number of pending packets and infos that were dropped because a queue limit was reached,
+/// since the last queue overflow info: [pending_packets: u32, events: u32]
+pub fn queue_overflow_info(pending_packets: u32, events: u32) -> Info {
+    let size = get_combined_size!(pending_packets, events);
+    let mut info = Info::new(InfoType::QueueOverflow, size);
+    let vec = &mut info.0;
+    push_bytes!(vec, pending_packets);
+    push_bytes!(vec, events);
+    info
+}
+
+/// Reply to the handshake command. Advertises what this build of the driver supports.
+pub fn handshake_info(version: u32, commands: u64, info_types: u64) -> Info {
+    let size = get_combined_size!(version, commands, info_types);
+    let mut info = Info::new(InfoType::Handshake, size);
+    let vec = &mut info.0;
+    push_bytes!(vec, version);
+    push_bytes!(vec, commands);
+    push_bytes!(vec, info_types);
+    info
+}
+
+/// Reason a verdict from a batch was not applied.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, FromPrimitive)]
+pub enum VerdictError {
+    /// No pending packet with this id. It was already handled or never existed.
+    UnknownId = 1,
+    /// The verdict value is not valid. The packet stays pending.
+    InvalidVerdict = 2,
+    /// The verdict was saved but the packet could not be injected or redirected.
+    InjectFailed = 3,
+}
+
+/// Result of a verdict batch. Lists only the entries that failed: [count: u32, count * (id: u64, error: u8)]
+pub fn verdict_batch_result_info(failed: &[(u64, VerdictError)]) -> Info {
+    let mut size = get_combined_size!(failed.len() as u32);
+    size += failed.len() * get_combined_size!(0_u64, 0_u8);
+
+    let mut info = Info::new(InfoType::VerdictBatchResult, size);
+    let vec = &mut info.0;
+    push_bytes!(vec, failed.len() as u32);
+    for (id, error) in failed {
+        push_bytes!(vec, *id);
+        push_bytes!(vec, *error as u8);
+    }
+    info
+}
+
+/// Outcome of a command that was sent with a request id.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, FromPrimitive)]
+pub enum ResultCode {
+    Success = 0,
+    /// The command could not be parsed.
+    InvalidCommand = 1,
+    /// No pending packet with the given id.
+    UnknownId = 2,
+    /// The verdict value is not valid.
+    InvalidVerdict = 3,
+    /// The packet could not be injected or redirected.
+    InjectFailed = 4,
+    /// The command was executed but failed. See the reason.
+    Failed = 5,
+}
+
+/// Reply to a command with a request id: [request_id: u64, code: u8, reason: ...]
+pub fn command_result_info(request_id: u64, code: ResultCode, reason: &str) -> Info {
+    let mut size = get_combined_size!(request_id, code as u8);
+    size += reason.len();
+
+    let mut info = Info::new(InfoType::CommandResult, size);
+    let vec = &mut info.0;
+    push_bytes!(vec, request_id);
+    push_bytes!(vec, code as u8);
+    push_bytes!(vec, reason.as_bytes());
+    info
+}
+
+#[cfg(test)]
+use std::fs::File;
+#[cfg(test)]
+use std::io::Write;
+
+#[cfg(test)]
+use rand::seq::SliceRandom;
+
+// Tests run in parallel, the decoder test reads the file that is generated here.
+#[cfg(test)]
+static TEST_FILE_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+#[cfg(test)]
+fn test_log_record() -> Info {
+    use core::fmt::Write;
+    let mut record = log_record(1, Severity::Warning, 2, 3, "file.rs", 4, 100);
+    _ = write!(record, "test message");
+    record.field("pid", LogValue::U64(5));
+    record.field("delta", LogValue::I64(-6));
+    record.field("verdict", LogValue::Str("Accept"));
+    record.field(
+        "key",
+        LogValue::Connection(LogConnection {
+            protocol: 6,
+            local_ip: decode::IpAddress::V4([1, 2, 3, 4]),
+            local_port: 7,
+            remote_ip: decode::IpAddress::V6([
+                2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17,
+            ]),
+            remote_port: 8,
+        }),
+    );
+    record.finish()
+}
+
+#[cfg(test)]
+pub(crate) fn test_memory_stats() -> MemoryStats {
+    MemoryStats {
+        timestamp: 1,
+        id_cache_entries: 2,
+        connections_v4: 3,
+        connections_v6: 4,
+        bandwidth_tcp_v4: 5,
+        bandwidth_tcp_v6: 6,
+        bandwidth_udp_v4: 7,
+        bandwidth_udp_v6: 8,
+        event_queue_depth: 9,
+        read_leftover_bytes: 10,
+        allocated_bytes: 11,
+        allocation_count: 12,
+        total_allocations: 13,
+        bandwidth_process: 14,
+        bandwidth_other_v4: 15,
+        bandwidth_other_v6: 16,
+    }
+}
+
+#[test]
+fn generate_test_info_file() -> Result<(), std::io::Error> {
+    let _guard = TEST_FILE_LOCK.lock().unwrap();
+    let mut file = File::create("rust_info_test.bin")?;
+    let enums = [
+        InfoType::LogLine,
+        InfoType::ConnectionIpv4,
+        InfoType::ConnectionIpv6,
+        InfoType::ConnectionEndEventV4,
+        InfoType::ConnectionEndEventV6,
+        InfoType::BandwidthStatsV4,
+        InfoType::BandwidthStatsV6,
+        InfoType::Handshake,
+        InfoType::VerdictBatchResult,
+        InfoType::CommandResult,
+        InfoType::ProcessInfo,
+        InfoType::ConnectionIpv4Ext,
+        InfoType::ConnectionIpv6Ext,
+        InfoType::LogLinesDropped,
+        InfoType::LogRecord,
+        InfoType::ConnectionDumpV4,
+        InfoType::ConnectionDumpV6,
+        InfoType::ConnectionDumpEnd,
+        InfoType::MemoryStats,
+        InfoType::RuleList,
+        InfoType::PendingTimeout,
+        InfoType::QueueOverflow,
+        InfoType::BandwidthStatsPerProcess,
+        InfoType::BandwidthStatsV4Ext,
+        InfoType::BandwidthStatsV6Ext,
+    ];
+
+    let mut selected: Vec<InfoType> = Vec::with_capacity(1000);
+    let mut rng = rand::thread_rng();
+    for _ in 0..selected.capacity() {
+        selected.push(*enums.choose(&mut rng).unwrap());
+    }
+
+    for value in selected {
+        file.write_all(&match value {
+            InfoType::LogLine => {
+                let mut info = log_line(Severity::Trace, 5);
+                use std::fmt::Write;
+                _ = write!(info, "prefix: test log");
+                info.assert_size();
+                info.0
+            }
+            InfoType::ConnectionIpv4 => {
+                let info = connection_info_v4(
+                    1,
+                    2,
+                    3,
+                    4,
+                    [1, 2, 3, 4],
+                    [2, 3, 4, 5],
+                    5,
+                    6,
+                    7,
+                    &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10],
+                );
+                info.assert_size();
+                info.0
+            }
+
+            InfoType::ConnectionIpv6 => {
+                let info = connection_info_v6(
+                    1,
+                    2,
+                    3,
+                    4,
+                    [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16],
+                    [2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17],
+                    5,
+                    6,
+                    7,
+                    &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10],
+                );
+                info.assert_size();
+                info.0
+            }
+            InfoType::ConnectionEndEventV4 => {
+                let info = connection_end_event_v4_info(1, 2, 3, [1, 2, 3, 4], [2, 3, 4, 5], 4, 5);
+                info.assert_size();
+                info.0
+            }
+            InfoType::ConnectionEndEventV6 => {
+                let info = connection_end_event_v6_info(
+                    1,
+                    2,
+                    3,
+                    [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16],
+                    [2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17],
+                    4,
+                    5,
+                );
+                info.assert_size();
+                info.0
+            }
+            InfoType::BandwidthStatsV4 | InfoType::BandwidthStatsV4Ext => {
+                let vec = alloc::vec![
+                    BandwidthValueV4 {
+                        local_ip: [1, 2, 3, 4],
+                        local_port: 1,
+                        remote_ip: [2, 3, 4, 5],
+                        remote_port: 2,
+                        transmitted_bytes: 3,
+                        received_bytes: 4,
+                        transmitted_packets: 9,
+                        received_packets: 10,
+                        first_seen_timestamp: 11,
+                        last_seen_timestamp: 12,
+                    },
+                    BandwidthValueV4 {
+                        local_ip: [1, 2, 3, 4],
+                        local_port: 5,
+                        remote_ip: [2, 3, 4, 5],
+                        remote_port: 6,
+                        transmitted_bytes: 7,
+                        received_bytes: 8,
+                        transmitted_packets: 13,
+                        received_packets: 14,
+                        first_seen_timestamp: 15,
+                        last_seen_timestamp: 16,
+                    },
+                ];
+                let mut info = bandiwth_stats_array_v4(1, vec);
+                if value == InfoType::BandwidthStatsV4 {
+                    info = info.for_client(LEGACY_INFO_TYPES).unwrap();
+                }
+                info.assert_size();
+                info.0
+            }
+            InfoType::BandwidthStatsV6 | InfoType::BandwidthStatsV6Ext => {
+                let vec = alloc::vec![
+                    BandwidthValueV6 {
+                        local_ip: [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16],
+                        local_port: 1,
+                        remote_ip: [2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17],
+                        remote_port: 2,
+                        transmitted_bytes: 3,
+                        received_bytes: 4,
+                        transmitted_packets: 9,
+                        received_packets: 10,
+                        first_seen_timestamp: 11,
+                        last_seen_timestamp: 12,
+                    },
+                    BandwidthValueV6 {
+                        local_ip: [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16],
+                        local_port: 5,
+                        remote_ip: [2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17],
+                        remote_port: 6,
+                        transmitted_bytes: 7,
+                        received_bytes: 8,
+                        transmitted_packets: 13,
+                        received_packets: 14,
+                        first_seen_timestamp: 15,
+                        last_seen_timestamp: 16,
+                    },
+                ];
+                let mut info = bandiwth_stats_array_v6(1, vec);
+                if value == InfoType::BandwidthStatsV6 {
+                    info = info.for_client(LEGACY_INFO_TYPES).unwrap();
+                }
+                info.assert_size();
+                info.0
+            }
+            InfoType::Handshake => {
+                let info = handshake_info(1, 2, 3);
+                info.assert_size();
+                info.0
+            }
+            InfoType::VerdictBatchResult => {
+                let info = verdict_batch_result_info(&[
+                    (1, VerdictError::UnknownId),
+                    (2, VerdictError::InvalidVerdict),
+                    (3, VerdictError::InjectFailed),
+                ]);
+                info.assert_size();
+                info.0
+            }
+            InfoType::CommandResult => {
+                let info = command_result_info(1, ResultCode::UnknownId, "test reason");
+                info.assert_size();
+                info.0
+            }
+            InfoType::ConnectionIpv4Ext => {
+                let info = connection_info_v4_ext(
+                    1,
+                    2,
+                    3,
+                    4,
+                    [1, 2, 3, 4],
+                    [2, 3, 4, 5],
+                    5,
+                    6,
+                    7,
+                    8,
+                    9,
+                    20,
+                    &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10],
+                );
+                info.assert_size();
+                info.0
+            }
+            InfoType::ConnectionIpv6Ext => {
+                let info = connection_info_v6_ext(
+                    1,
+                    2,
+                    3,
+                    4,
+                    [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16],
+                    [2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17],
+                    5,
+                    6,
+                    7,
+                    8,
+                    9,
+                    20,
+                    &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10],
+                );
+                info.assert_size();
+                info.0
+            }
+            InfoType::ProcessInfo => {
+                let info = process_info(1, "C:\\Windows\\System32\\svchost.exe");
+                info.assert_size();
+                info.0
+            }
+            InfoType::LogLinesDropped => {
+                let info = log_lines_dropped_info(1);
+                info.assert_size();
+                info.0
+            }
+            InfoType::LogRecord => {
+                let info = test_log_record();
+                info.assert_size();
+                info.0
+            }
+            InfoType::ConnectionDumpV4 => {
+                let vec = alloc::vec![
+                    ConnectionDumpValueV4 {
+                        protocol: 1,
+                        local_ip: [1, 2, 3, 4],
+                        local_port: 2,
+                        remote_ip: [2, 3, 4, 5],
+                        remote_port: 3,
+                        direction: 4,
+                        verdict: 5,
+                        process_id: 6,
+                        last_accessed_timestamp: 7,
+                        end_timestamp: 8,
+                    },
+                    ConnectionDumpValueV4 {
+                        protocol: 1,
+                        local_ip: [1, 2, 3, 4],
+                        local_port: 2,
+                        remote_ip: [2, 3, 4, 5],
+                        remote_port: 3,
+                        direction: 4,
+                        verdict: 5,
+                        process_id: 6,
+                        last_accessed_timestamp: 7,
+                        end_timestamp: 0,
+                    },
+                ];
+                let info = connection_dump_v4(vec);
+                info.assert_size();
+                info.0
+            }
+            InfoType::ConnectionDumpV6 => {
+                let vec = alloc::vec![
+                    ConnectionDumpValueV6 {
+                        protocol: 1,
+                        local_ip: [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16],
+                        local_port: 2,
+                        remote_ip: [2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17],
+                        remote_port: 3,
+                        direction: 4,
+                        verdict: 5,
+                        process_id: 6,
+                        last_accessed_timestamp: 7,
+                        end_timestamp: 8,
+                    },
+                    ConnectionDumpValueV6 {
+                        protocol: 1,
+                        local_ip: [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16],
+                        local_port: 2,
+                        remote_ip: [2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17],
+                        remote_port: 3,
+                        direction: 4,
+                        verdict: 5,
+                        process_id: 6,
+                        last_accessed_timestamp: 7,
+                        end_timestamp: 0,
+                    },
+                ];
+                let info = connection_dump_v6(vec);
+                info.assert_size();
+                info.0
+            }
+            InfoType::ConnectionDumpEnd => {
+                let info = connection_dump_end_info(4);
+                info.assert_size();
+                info.0
+            }
+            InfoType::MemoryStats => {
+                let info = memory_stats_info(test_memory_stats());
+                info.assert_size();
+                info.0
+            }
+            InfoType::RuleList => {
+                let info = rule_list_info(&[crate::rules::test_rule(), crate::rules::test_rule()]);
+                info.assert_size();
+                info.0
+            }
+            InfoType::PendingTimeout => {
+                let info = pending_timeout_info(2, &[1, 2, 3]);
+                info.assert_size();
+                info.0
+            }
+            InfoType::QueueOverflow => {
+                let info = queue_overflow_info(1, 2);
+                info.assert_size();
+                info.0
+            }
+            InfoType::BandwidthStatsPerProcess => {
+                let vec = alloc::vec![
+                    BandwidthValueProcess {
+                        process_id: 1,
+                        protocol: 6,
+                        transmitted_bytes: 2,
+                        received_bytes: 3,
+                    },
+                    BandwidthValueProcess {
+                        process_id: 4,
+                        protocol: 17,
+                        transmitted_bytes: 5,
+                        received_bytes: 6,
+                    },
+                ];
+                let info = bandwidth_stats_per_process(vec);
+                info.assert_size();
+                info.0
+            }
+        })?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_bandwidth_stats_frames() {
+    let values = |count: u16| {
+        (0..count)
+            .map(|port| BandwidthValueV4 {
+                local_ip: [1, 2, 3, 4],
+                local_port: port,
+                remote_ip: [2, 3, 4, 5],
+                remote_port: 443,
+                transmitted_bytes: 1,
+                received_bytes: 2,
+                transmitted_packets: 3,
+                received_packets: 4,
+                first_seen_timestamp: 5,
+                last_seen_timestamp: 6,
+            })
+            .collect::<Vec<_>>()
+    };
+    let count = |info: &Info| u32::from_le_bytes(info.as_bytes()[6..10].try_into().unwrap());
+
+    // 10 bytes of header and 60 bytes per value: 3 values fit in 200 bytes.
+    let frames = bandwidth_stats_frames_v4(6, values(10), 200);
+    assert_eq!(frames.iter().map(count).collect::<Vec<_>>(), [3, 3, 3, 1]);
+    for frame in &frames {
+        frame.assert_size();
+        assert!(frame.as_bytes().len() <= 200);
+    }
+
+    assert_eq!(bandwidth_stats_frames_v4(6, values(10), 0).len(), 1);
+    // A value that does not fit still gets its own info.
+    assert_eq!(bandwidth_stats_frames_v4(6, values(2), 1).len(), 2);
+    assert!(bandwidth_stats_frames_v4(6, values(0), 100).is_empty());
+
+    // Older clients get the values without the packet counts and timestamps, 28 bytes each.
+    let basic = frames
+        .into_iter()
+        .next()
+        .unwrap()
+        .for_client(LEGACY_INFO_TYPES)
+        .unwrap();
+    basic.assert_size();
+    assert_eq!(basic.info_type(), InfoType::BandwidthStatsV4 as u8);
+    assert_eq!(basic.as_bytes().len(), 10 + 3 * 28);
+    let Ok((decode::Event::Bandwidth(stats), _)) = decode::decode_frame(basic.as_bytes()) else {
+        panic!("failed to decode basic bandwidth stats");
+    };
+    let ports: Vec<u16> = stats.values.iter().map(|value| value.local_port).collect();
+    assert_eq!(ports, [0, 1, 2]);
+    assert!(stats.values.iter().all(|value| value.received_bytes == 2
+        && value.transmitted_packets == 0
+        && value.last_seen_timestamp == 0));
+
+    // 9 bytes of header and 25 bytes per value.
+    let process_values = (0..5)
+        .map(|process_id| BandwidthValueProcess {
+            process_id,
+            protocol: 6,
+            transmitted_bytes: 1,
+            received_bytes: 2,
+        })
+        .collect();
+    let frames = bandwidth_stats_per_process_frames(process_values, 60);
+    assert_eq!(frames.len(), 3);
+    assert!(frames.iter().all(|frame| frame.as_bytes().len() <= 60));
+}
+
+#[test]
+fn test_is_supported() {
+    let connection = connection_end_event_v4_info(1, 2, 3, [1, 2, 3, 4], [2, 3, 4, 5], 4, 5);
+    assert!(connection.is_supported(LEGACY_INFO_TYPES));
+    assert!(
+        !connection.is_supported(LEGACY_INFO_TYPES & !(1 << InfoType::ConnectionEndEventV4 as u64))
+    );
+
+    // The reply to the handshake is sent even if the client did not list it.
+    let handshake = handshake_info(1, 2, 3);
+    assert!(handshake.is_supported(0));
+}
+
+#[test]
+fn test_for_client() {
+    let payload = [1, 2, 3];
+    let ext_v4 = || {
+        connection_info_v4_ext(
+            1,
+            2,
+            3,
+            4,
+            [1, 2, 3, 4],
+            [2, 3, 4, 5],
+            5,
+            6,
+            7,
+            8,
+            9,
+            10,
+            &payload,
+        )
+    };
+    let ext_v6 =
+        || connection_info_v6_ext(1, 2, 3, 4, [1; 16], [2; 16], 5, 6, 7, 8, 9, 10, &payload);
+
+    // Clients that know the extended format get it unchanged.
+    assert_eq!(
+        ext_v4().for_client(SUPPORTED_INFO_TYPES).unwrap().0,
+        ext_v4().0
+    );
+
+    // Older clients get the basic format.
+    let basic = ext_v4().for_client(LEGACY_INFO_TYPES).unwrap();
+    basic.assert_size();
+    assert_eq!(
+        basic.0,
+        connection_info_v4(1, 2, 3, 4, [1, 2, 3, 4], [2, 3, 4, 5], 5, 6, 7, &payload).0
+    );
+    let basic = ext_v6().for_client(LEGACY_INFO_TYPES).unwrap();
+    basic.assert_size();
+    assert_eq!(
+        basic.0,
+        connection_info_v6(1, 2, 3, 4, [1; 16], [2; 16], 5, 6, 7, &payload).0
+    );
+
+    // No format is supported.
+    let only_logs = 1 << InfoType::LogLine as u64;
+    assert!(ext_v4().for_client(only_logs).is_none());
+    assert!(process_info(1, "path").for_client(only_logs).is_none());
+}
+
+#[test]
+fn test_log_record_truncation() {
+    use core::fmt::Write;
+    // The message is cut at a character boundary.
+    let mut record = log_record(1, Severity::Info, 0, 0, "file.rs", 1, 5);
+    _ = write!(record, "abcä");
+    _ = write!(record, "d");
+    record.field("pid", LogValue::U64(1));
+    assert!(write!(record, "after the fields").is_err());
+    let info = record.finish();
+    info.assert_size();
+    let Ok((decode::Event::LogRecord(decoded), _)) = decode::decode_frame(info.as_bytes()) else {
+        panic!("failed to decode log record");
+    };
+    assert_eq!(decoded.message, "abcä");
+    assert!(decoded.truncated);
+    assert_eq!(decoded.fields.len(), 1);
+
+    let mut record = log_record(1, Severity::Info, 0, 0, "file.rs", 1, 5);
+    _ = write!(record, "abc");
+    let info = record.finish();
+    let Ok((decode::Event::LogRecord(decoded), _)) = decode::decode_frame(info.as_bytes()) else {
+        panic!("failed to decode log record");
+    };
+    assert!(!decoded.truncated);
+    assert!(decoded.fields.is_empty());
+}
+
+#[test]
+fn test_log_record_for_client() {
+    assert_eq!(
+        test_log_record()
+            .for_client(SUPPORTED_INFO_TYPES)
+            .unwrap()
+            .0,
+        test_log_record().0
+    );
+
+    // Older clients get a log line with the same text as before.
+    let line = test_log_record().for_client(LEGACY_INFO_TYPES).unwrap();
+    line.assert_size();
+    let mut expected = log_line(Severity::Warning, 0);
+    use core::fmt::Write;
+    _ = write!(
+        expected,
+        "file.rs:4 test message pid=5 delta=-6 verdict=Accept key=p: 6 l: 1.2.3.4:7 r: 203:405:607:809:a0b:c0d:e0f:1011:8"
+    );
+    assert_eq!(line.0, expected.0);
+
+    assert!(test_log_record()
+        .for_client(1 << InfoType::ProcessInfo as u64)
+        .is_none());
+}
@@ -1,5 +1,14 @@
 #![cfg_attr(not(test), no_std)]
 extern crate alloc;
 
+/// Version of the command and info protocol. Exchanged with the handshake command.
+pub const PROTOCOL_VERSION: u32 = 3;
+
 pub mod command;
+pub mod id_queue;
 pub mod info;
+pub mod process_policy;
+mod reader;
+pub mod reject;
+pub mod rules;
+pub mod throttle;
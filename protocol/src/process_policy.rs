@@ -0,0 +1,197 @@
+// Default verdicts for new connections of a process, by process id or by executable path.
+
+use alloc::string::String;
+use hashbrown::HashMap;
+
+struct ProcessEntry {
+    // `None` if the process path matched no path entry.
+    verdict: Option<u8>,
+    // Set if the entry was added for the path of the process. Removed when a path entry changes.
+    from_path: bool,
+}
+
+/// Result of `ProcessPolicyTable::get`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum PolicyLookup {
+    /// The verdict for the process, `None` if the process has no verdict.
+    Verdict(Option<u8>),
+    /// The process was not checked against the path entries yet, see `ProcessPolicyTable::match_path`.
+    NeedsPath,
+}
+
+/// Verdicts by process id and by executable path. A process id entry is used before a path entry.
+/// The result of a path lookup is saved for the process id, also if no path matched, so the path is
+/// only needed once per process. Process ids are reused, `process_exited` must be called.
+pub struct ProcessPolicyTable {
+    processes: HashMap<u64, ProcessEntry>,
+    // Lowercase paths.
+    paths: HashMap<String, u8>,
+}
+
+impl ProcessPolicyTable {
+    pub fn new() -> Self {
+        Self {
+            processes: HashMap::new(),
+            paths: HashMap::new(),
+        }
+    }
+
+    /// Sets the verdict for the process. `None` removes the entry.
+    pub fn set_process(&mut self, process_id: u64, verdict: Option<u8>) {
+        match verdict {
+            Some(verdict) => {
+                self.processes.insert(
+                    process_id,
+                    ProcessEntry {
+                        verdict: Some(verdict),
+                        from_path: false,
+                    },
+                );
+            }
+            None => {
+                self.processes.remove(&process_id);
+            }
+        }
+    }
+
+    /// Sets the verdict for the executable path, compared case-insensitively. `None` removes the entry.
+    pub fn set_path(&mut self, path: &str, verdict: Option<u8>) {
+        let path = path.to_lowercase();
+        // Processes that were checked against the old path entries have to be checked again.
+        self.processes.retain(|_, entry| !entry.from_path);
+        match verdict {
+            Some(verdict) => {
+                self.paths.insert(path, verdict);
+            }
+            None => {
+                self.paths.remove(&path);
+            }
+        }
+    }
+
+    /// Returns the verdict for the process id. Does not check the paths.
+    pub fn get_process(&self, process_id: u64) -> Option<u8> {
+        self.processes.get(&process_id)?.verdict
+    }
+
+    /// Returns the verdict for the process, or `NeedsPath` if the path of the process has to be checked.
+    pub fn get(&self, process_id: u64) -> PolicyLookup {
+        if let Some(entry) = self.processes.get(&process_id) {
+            return PolicyLookup::Verdict(entry.verdict);
+        }
+        if self.paths.is_empty() {
+            return PolicyLookup::Verdict(None);
+        }
+        PolicyLookup::NeedsPath
+    }
+
+    /// Returns the verdict for the path of the process and saves it for the process id.
+    /// `path` is `None` if the process has no path, it matches no entry.
+    /// A process id entry is still used before the path.
+    pub fn match_path(&mut self, process_id: u64, path: Option<&str>) -> Option<u8> {
+        if let Some(entry) = self.processes.get(&process_id) {
+            return entry.verdict;
+        }
+        let verdict = path.and_then(|path| self.paths.get(&path.to_lowercase()).copied());
+        // 0 is the id of connections without a process.
+        if process_id != 0 {
+            self.processes.insert(
+                process_id,
+                ProcessEntry {
+                    verdict,
+                    from_path: true,
+                },
+            );
+        }
+        verdict
+    }
+
+    /// Removes the entries of the process id.
+    pub fn process_exited(&mut self, process_id: u64) {
+        self.processes.remove(&process_id);
+    }
+}
+
+impl Default for ProcessPolicyTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+const APP_PATH: &str = "\\Device\\HarddiskVolume3\\Program Files\\App\\app.exe";
+
+#[test]
+fn test_process_overrides_path() {
+    let mut table = ProcessPolicyTable::new();
+    table.set_path(APP_PATH, Some(4));
+    table.set_process(10, Some(5));
+    assert_eq!(table.get(10), PolicyLookup::Verdict(Some(5)));
+    assert_eq!(table.match_path(10, Some(APP_PATH)), Some(5));
+
+    assert_eq!(table.get(11), PolicyLookup::NeedsPath);
+    assert_eq!(table.match_path(11, Some(APP_PATH)), Some(4));
+    assert_eq!(table.get(11), PolicyLookup::Verdict(Some(4)));
+    assert_eq!(table.get_process(11), Some(4));
+
+    // A process entry replaces the saved path verdict.
+    table.set_process(11, Some(5));
+    assert_eq!(table.get(11), PolicyLookup::Verdict(Some(5)));
+    table.set_process(11, None);
+    assert_eq!(table.get(11), PolicyLookup::NeedsPath);
+}
+
+#[test]
+fn test_path_case_insensitive() {
+    let mut table = ProcessPolicyTable::new();
+    table.set_path(APP_PATH, Some(4));
+    assert_eq!(table.match_path(1, Some(&APP_PATH.to_uppercase())), Some(4));
+    assert_eq!(table.match_path(2, Some(&APP_PATH.to_lowercase())), Some(4));
+
+    // Removing uses the same comparison.
+    table.set_path(&APP_PATH.to_uppercase(), None);
+    assert_eq!(table.get(1), PolicyLookup::Verdict(None));
+}
+
+#[test]
+fn test_no_match_saved() {
+    let mut table = ProcessPolicyTable::new();
+    // Without path entries the path is never needed.
+    assert_eq!(table.get(1), PolicyLookup::Verdict(None));
+
+    table.set_path(APP_PATH, Some(4));
+    assert_eq!(table.get(1), PolicyLookup::NeedsPath);
+    assert_eq!(table.match_path(1, Some("\\Device\\other.exe")), None);
+    assert_eq!(table.get(1), PolicyLookup::Verdict(None));
+    assert_eq!(table.get_process(1), None);
+    // A process without a path is not checked again either.
+    assert_eq!(table.match_path(3, None), None);
+    assert_eq!(table.get(3), PolicyLookup::Verdict(None));
+
+    // A changed path entry can match now, the process is checked again.
+    table.set_path("\\Device\\Other.exe", Some(2));
+    assert_eq!(table.get(1), PolicyLookup::NeedsPath);
+    assert_eq!(table.match_path(1, Some("\\Device\\other.exe")), Some(2));
+
+    // Connections without a process are not saved.
+    assert_eq!(table.match_path(0, Some(APP_PATH)), Some(4));
+    assert_eq!(table.get(0), PolicyLookup::NeedsPath);
+}
+
+#[test]
+fn test_process_exited() {
+    let mut table = ProcessPolicyTable::new();
+    table.set_path(APP_PATH, Some(4));
+    table.set_process(10, Some(5));
+    assert_eq!(table.match_path(11, Some(APP_PATH)), Some(4));
+    assert_eq!(table.match_path(12, Some("\\Device\\other.exe")), None);
+
+    table.process_exited(10);
+    table.process_exited(11);
+    table.process_exited(12);
+    // The ids can be reused by other processes.
+    assert_eq!(table.get(10), PolicyLookup::NeedsPath);
+    assert_eq!(table.get(11), PolicyLookup::NeedsPath);
+    assert_eq!(table.get(12), PolicyLookup::NeedsPath);
+    assert_eq!(table.match_path(12, Some(APP_PATH)), Some(4));
+}
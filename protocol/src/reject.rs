@@ -0,0 +1,341 @@
+// Replies for blocked packets. A blocked TCP packet is answered with a reset and a blocked UDP packet
+// with an ICMP port unreachable, so the application fails right away instead of waiting for a timeout.
+// The packets are built by hand so the replies can be tested on the host without the driver.
+
+use alloc::vec::Vec;
+
+/// Number of bytes of the blocked packet that are needed to build the reply.
+/// ICMP replies quote the blocked packet up to this length.
+pub const REJECT_INPUT_LEN: usize = 128;
+
+const IPV4_HEADER_LEN: usize = 20;
+const IPV6_HEADER_LEN: usize = 40;
+const TCP_HEADER_LEN: usize = 20;
+const UDP_HEADER_LEN: usize = 8;
+// Type, code, checksum and the unused 4 bytes of a destination unreachable message.
+const ICMP_HEADER_LEN: usize = 8;
+
+const PROTOCOL_ICMP: u8 = 1;
+const PROTOCOL_TCP: u8 = 6;
+const PROTOCOL_UDP: u8 = 17;
+const PROTOCOL_ICMPV6: u8 = 58;
+
+const ICMPV4_DESTINATION_UNREACHABLE: u8 = 3;
+const ICMPV4_PORT_UNREACHABLE: u8 = 3;
+const ICMPV6_DESTINATION_UNREACHABLE: u8 = 1;
+const ICMPV6_PORT_UNREACHABLE: u8 = 4;
+
+const TCP_FIN: u8 = 0x01;
+const TCP_SYN: u8 = 0x02;
+const TCP_RST: u8 = 0x04;
+const TCP_ACK: u8 = 0x10;
+
+const HOP_LIMIT: u8 = 64;
+
+/// Builds the reply for a blocked IP packet. `packet` is the start of the blocked packet,
+/// it can be cut after `REJECT_INPUT_LEN` bytes. The reply goes back to the sender of the packet.
+/// Returns `None` for packets that must not be answered: resets, ICMP, other protocols and fragments.
+pub fn build_reject_packet(packet: &[u8]) -> Option<Vec<u8>> {
+    match packet.first()? >> 4 {
+        4 => build_reject_v4(packet),
+        6 => build_reject_v6(packet),
+        _ => None,
+    }
+}
+
+fn build_reject_v4(packet: &[u8]) -> Option<Vec<u8>> {
+    if packet.len() < IPV4_HEADER_LEN {
+        return None;
+    }
+    // The packet can be cut, only the header fields are read.
+    let header_len = (packet[0] & 0x0f) as usize * 4;
+    let total_len = read_u16(&packet[2..4]) as usize;
+    if header_len < IPV4_HEADER_LEN || header_len > packet.len() || total_len < header_len {
+        return None;
+    }
+    // Only the first fragment has the transport header.
+    if read_u16(&packet[6..8]) & 0x1fff != 0 {
+        return None;
+    }
+    let transport = &packet[header_len..];
+    let transport_len = total_len - header_len;
+
+    match packet[9] {
+        PROTOCOL_TCP => {
+            let mut reply = alloc::vec![0; IPV4_HEADER_LEN + TCP_HEADER_LEN];
+            write_ipv4_header(&mut reply, packet, PROTOCOL_TCP);
+            let (header, tcp) = reply.split_at_mut(IPV4_HEADER_LEN);
+            write_tcp_reset(tcp, transport, transport_len)?;
+            let sum = pseudo_header_sum(&header[12..16], &header[16..20], PROTOCOL_TCP, tcp.len());
+            fill_checksum(tcp, 16, sum);
+            Some(reply)
+        }
+        PROTOCOL_UDP => {
+            if transport.len() < UDP_HEADER_LEN {
+                return None;
+            }
+            let quote = &packet[..packet.len().min(REJECT_INPUT_LEN).min(total_len)];
+            let mut reply = alloc::vec![0; IPV4_HEADER_LEN + ICMP_HEADER_LEN + quote.len()];
+            write_ipv4_header(&mut reply, packet, PROTOCOL_ICMP);
+            let icmp = &mut reply[IPV4_HEADER_LEN..];
+            icmp[0] = ICMPV4_DESTINATION_UNREACHABLE;
+            icmp[1] = ICMPV4_PORT_UNREACHABLE;
+            icmp[ICMP_HEADER_LEN..].copy_from_slice(quote);
+            // ICMP for ipv4 has no pseudo header.
+            fill_checksum(icmp, 2, 0);
+            Some(reply)
+        }
+        _ => None,
+    }
+}
+
+fn build_reject_v6(packet: &[u8]) -> Option<Vec<u8>> {
+    if packet.len() < IPV6_HEADER_LEN {
+        return None;
+    }
+    // The packet can be cut, only the header fields are read.
+    let transport = &packet[IPV6_HEADER_LEN..];
+    let transport_len = read_u16(&packet[4..6]) as usize;
+
+    // Packets with extension headers are not answered.
+    match packet[6] {
+        PROTOCOL_TCP => {
+            let mut reply = alloc::vec![0; IPV6_HEADER_LEN + TCP_HEADER_LEN];
+            write_ipv6_header(&mut reply, packet, PROTOCOL_TCP);
+            let (header, tcp) = reply.split_at_mut(IPV6_HEADER_LEN);
+            write_tcp_reset(tcp, transport, transport_len)?;
+            let sum = pseudo_header_sum(&header[8..24], &header[24..40], PROTOCOL_TCP, tcp.len());
+            fill_checksum(tcp, 16, sum);
+            Some(reply)
+        }
+        PROTOCOL_UDP => {
+            if transport.len() < UDP_HEADER_LEN {
+                return None;
+            }
+            let quote_len = packet
+                .len()
+                .min(REJECT_INPUT_LEN)
+                .min(IPV6_HEADER_LEN + transport_len);
+            let quote = &packet[..quote_len];
+            let mut reply = alloc::vec![0; IPV6_HEADER_LEN + ICMP_HEADER_LEN + quote.len()];
+            write_ipv6_header(&mut reply, packet, PROTOCOL_ICMPV6);
+            let (header, icmp) = reply.split_at_mut(IPV6_HEADER_LEN);
+            icmp[0] = ICMPV6_DESTINATION_UNREACHABLE;
+            icmp[1] = ICMPV6_PORT_UNREACHABLE;
+            icmp[ICMP_HEADER_LEN..].copy_from_slice(quote);
+            let sum =
+                pseudo_header_sum(&header[8..24], &header[24..40], PROTOCOL_ICMPV6, icmp.len());
+            fill_checksum(icmp, 2, sum);
+            Some(reply)
+        }
+        _ => None,
+    }
+}
+
+/// Writes an ipv4 header for `reply` with the addresses of `blocked` swapped.
+fn write_ipv4_header(reply: &mut [u8], blocked: &[u8], protocol: u8) {
+    let total_len = reply.len() as u16;
+    // Version 4, header length of 5 words.
+    reply[0] = 0x45;
+    reply[2..4].copy_from_slice(&total_len.to_be_bytes());
+    // Don't fragment.
+    reply[6] = 0x40;
+    reply[8] = HOP_LIMIT;
+    reply[9] = protocol;
+    reply[12..16].copy_from_slice(&blocked[16..20]);
+    reply[16..20].copy_from_slice(&blocked[12..16]);
+    fill_checksum(&mut reply[..IPV4_HEADER_LEN], 10, 0);
+}
+
+/// Writes an ipv6 header for `reply` with the addresses of `blocked` swapped.
+fn write_ipv6_header(reply: &mut [u8], blocked: &[u8], protocol: u8) {
+    let payload_len = (reply.len() - IPV6_HEADER_LEN) as u16;
+    reply[0] = 0x60;
+    reply[4..6].copy_from_slice(&payload_len.to_be_bytes());
+    reply[6] = protocol;
+    reply[7] = HOP_LIMIT;
+    reply[8..24].copy_from_slice(&blocked[24..40]);
+    reply[24..40].copy_from_slice(&blocked[8..24]);
+}
+
+/// Writes a reset for the `blocked` segment, as described in RFC 9293 section 3.10.7.1.
+/// `blocked_len` is the segment length from the ip header, `blocked` can be cut.
+/// The checksum is left at 0.
+fn write_tcp_reset(reply: &mut [u8], blocked: &[u8], blocked_len: usize) -> Option<()> {
+    if blocked.len() < TCP_HEADER_LEN {
+        return None;
+    }
+    let header_len = (blocked[12] >> 4) as usize * 4;
+    let flags = blocked[13];
+    // Never answer a reset.
+    if flags & TCP_RST != 0 || header_len < TCP_HEADER_LEN || header_len > blocked_len {
+        return None;
+    }
+
+    // Swapped ports.
+    reply[0..2].copy_from_slice(&blocked[2..4]);
+    reply[2..4].copy_from_slice(&blocked[0..2]);
+    reply[12] = (TCP_HEADER_LEN as u8 / 4) << 4;
+    if flags & TCP_ACK != 0 {
+        reply[4..8].copy_from_slice(&blocked[8..12]);
+        reply[13] = TCP_RST;
+    } else {
+        // SYN and FIN take one sequence number each.
+        let segment_len = blocked_len - header_len
+            + (flags & TCP_SYN != 0) as usize
+            + (flags & TCP_FIN != 0) as usize;
+        let seq_number = u32::from_be_bytes(blocked[4..8].try_into().unwrap());
+        let ack_number = seq_number.wrapping_add(segment_len as u32);
+        reply[8..12].copy_from_slice(&ack_number.to_be_bytes());
+        reply[13] = TCP_RST | TCP_ACK;
+    }
+    // Window and urgent pointer stay 0.
+    Some(())
+}
+
+fn read_u16(bytes: &[u8]) -> u16 {
+    u16::from_be_bytes([bytes[0], bytes[1]])
+}
+
+/// Sum of the pseudo header that the TCP, UDP and ICMPv6 checksums include.
+fn pseudo_header_sum(src: &[u8], dst: &[u8], protocol: u8, len: usize) -> u32 {
+    let sum = add_checksum(add_checksum(0, src), dst);
+    sum + protocol as u32 + (len >> 16) as u32 + (len & 0xffff) as u32
+}
+
+/// Adds the 16 bit words of `data` to `sum`, see RFC 1071. An odd last byte is padded with 0.
+fn add_checksum(mut sum: u32, data: &[u8]) -> u32 {
+    for word in data.chunks(2) {
+        sum += u16::from_be_bytes([word[0], *word.get(1).unwrap_or(&0)]) as u32;
+    }
+    sum
+}
+
+fn finish_checksum(mut sum: u32) -> u16 {
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// Writes the checksum of `data` at `offset`. `sum` is the pseudo header sum, the checksum field must be 0.
+fn fill_checksum(data: &mut [u8], offset: usize, sum: u32) {
+    let checksum = finish_checksum(add_checksum(sum, data));
+    data[offset..offset + 2].copy_from_slice(&checksum.to_be_bytes());
+}
+
+#[cfg(test)]
+fn tcp_v4(flags: u8, ack_number: u32, payload_len: usize) -> Vec<u8> {
+    let mut packet = alloc::vec![0; IPV4_HEADER_LEN + TCP_HEADER_LEN + payload_len];
+    let total_len = packet.len() as u16;
+    packet[0] = 0x45;
+    packet[2..4].copy_from_slice(&total_len.to_be_bytes());
+    packet[8] = 128;
+    packet[9] = PROTOCOL_TCP;
+    packet[12..16].copy_from_slice(&[192, 168, 1, 2]);
+    packet[16..20].copy_from_slice(&[1, 1, 1, 1]);
+    let tcp = &mut packet[IPV4_HEADER_LEN..];
+    tcp[0..2].copy_from_slice(&50000_u16.to_be_bytes());
+    tcp[2..4].copy_from_slice(&443_u16.to_be_bytes());
+    tcp[4..8].copy_from_slice(&1000_u32.to_be_bytes());
+    tcp[8..12].copy_from_slice(&ack_number.to_be_bytes());
+    tcp[12] = (TCP_HEADER_LEN as u8 / 4) << 4;
+    tcp[13] = flags;
+    packet
+}
+
+#[cfg(test)]
+fn checksum_is_valid(sum: u32, data: &[u8]) -> bool {
+    finish_checksum(add_checksum(sum, data)) == 0
+}
+
+#[test]
+fn test_reset_for_syn() {
+    let syn = tcp_v4(TCP_SYN, 0, 0);
+    let reply = build_reject_packet(&syn).unwrap();
+    assert_eq!(reply.len(), IPV4_HEADER_LEN + TCP_HEADER_LEN);
+
+    let (ip, tcp) = reply.split_at(IPV4_HEADER_LEN);
+    assert!(checksum_is_valid(0, ip));
+    assert_eq!(ip[0], 0x45);
+    assert_eq!(read_u16(&ip[2..4]) as usize, reply.len());
+    assert_eq!(&ip[12..16], &[1, 1, 1, 1]);
+    assert_eq!(&ip[16..20], &[192, 168, 1, 2]);
+    assert_eq!(ip[9], PROTOCOL_TCP);
+
+    let sum = pseudo_header_sum(&ip[12..16], &ip[16..20], PROTOCOL_TCP, tcp.len());
+    assert!(checksum_is_valid(sum, tcp));
+    assert_eq!((read_u16(&tcp[0..2]), read_u16(&tcp[2..4])), (443, 50000));
+    assert_eq!(tcp[13], TCP_RST | TCP_ACK);
+    assert_eq!(&tcp[4..8], &0_u32.to_be_bytes());
+    assert_eq!(&tcp[8..12], &1001_u32.to_be_bytes());
+}
+
+#[test]
+fn test_reset_for_ack() {
+    let data = tcp_v4(TCP_ACK, 5000, 100);
+    // Only the start of the packet is needed.
+    let reply = build_reject_packet(&data[..REJECT_INPUT_LEN]).unwrap();
+    let tcp = &reply[IPV4_HEADER_LEN..];
+    assert_eq!(tcp[13], TCP_RST);
+    assert_eq!(&tcp[4..8], &5000_u32.to_be_bytes());
+
+    // Resets are not answered.
+    let reset = tcp_v4(TCP_RST, 0, 0);
+    assert_eq!(build_reject_packet(&reset), None);
+}
+
+#[test]
+fn test_unreachable_for_udp_v6() {
+    let payload_len = 200;
+    let src = [0xfd, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2];
+    let dst = [0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1];
+    let mut packet = alloc::vec![0; IPV6_HEADER_LEN + UDP_HEADER_LEN + payload_len];
+    packet[0] = 0x60;
+    packet[4..6].copy_from_slice(&((UDP_HEADER_LEN + payload_len) as u16).to_be_bytes());
+    packet[6] = PROTOCOL_UDP;
+    packet[7] = 128;
+    packet[8..24].copy_from_slice(&src);
+    packet[24..40].copy_from_slice(&dst);
+    let udp = &mut packet[IPV6_HEADER_LEN..];
+    udp[0..2].copy_from_slice(&50000_u16.to_be_bytes());
+    udp[2..4].copy_from_slice(&53_u16.to_be_bytes());
+    udp[4..6].copy_from_slice(&((UDP_HEADER_LEN + payload_len) as u16).to_be_bytes());
+
+    let reply = build_reject_packet(&packet).unwrap();
+    let (ip, icmp) = reply.split_at(IPV6_HEADER_LEN);
+    assert_eq!(&ip[8..24], &dst);
+    assert_eq!(&ip[24..40], &src);
+    assert_eq!(ip[6], PROTOCOL_ICMPV6);
+    assert_eq!(read_u16(&ip[4..6]) as usize, icmp.len());
+
+    let sum = pseudo_header_sum(&ip[8..24], &ip[24..40], PROTOCOL_ICMPV6, icmp.len());
+    assert!(checksum_is_valid(sum, icmp));
+    assert_eq!(icmp[0], ICMPV6_DESTINATION_UNREACHABLE);
+    assert_eq!(icmp[1], ICMPV6_PORT_UNREACHABLE);
+    // The quote is cut at the input length.
+    assert_eq!(&icmp[ICMP_HEADER_LEN..], &packet[..REJECT_INPUT_LEN]);
+}
+
+#[test]
+fn test_unreachable_for_udp_v4() {
+    let mut packet = tcp_v4(0, 0, 0);
+    packet[9] = PROTOCOL_UDP;
+
+    let reply = build_reject_packet(&packet).unwrap();
+    let (ip, icmp) = reply.split_at(IPV4_HEADER_LEN);
+    assert!(checksum_is_valid(0, ip));
+    assert_eq!(ip[9], PROTOCOL_ICMP);
+    assert!(checksum_is_valid(0, icmp));
+    assert_eq!(icmp[0], ICMPV4_DESTINATION_UNREACHABLE);
+    assert_eq!(icmp[1], ICMPV4_PORT_UNREACHABLE);
+    assert_eq!(&icmp[ICMP_HEADER_LEN..], &packet[..]);
+
+    // ICMP is not answered.
+    packet[9] = PROTOCOL_ICMP;
+    assert_eq!(build_reject_packet(&packet), None);
+    // Fragments after the first one have no transport header.
+    packet[9] = PROTOCOL_UDP;
+    packet[7] = 1;
+    assert_eq!(build_reject_packet(&packet), None);
+}
@@ -0,0 +1,359 @@
+// Verdict rules that are evaluated in the driver, before a new connection is sent to the client.
+
+use alloc::vec::Vec;
+
+use crate::{info::decode::IpAddress, reader::Reader};
+
+/// Matches every protocol or direction in a `Rule`.
+pub const RULE_ANY: u8 = 0xFF;
+
+/// Maximum number of rules in a `RuleTable`.
+pub const MAX_RULES: usize = 1024;
+
+/// Gives a verdict to new connections to a remote network and port range.
+/// `protocol` is an IP protocol number and `direction` is 0 for outbound and 1 for inbound,
+/// either can be `RULE_ANY`. `ip_version` is 4 or 6, an ipv4 address uses the first 4 bytes of `remote_address`.
+/// The port range includes both ends. `verdict` is cached for the connection, a temporary verdict
+/// is cached as its permanent form. Redirects are not accepted.
+#[repr(C, packed)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Rule {
+    /// Chosen by the client. Rules are checked in the order of their ids, the first match is used.
+    pub id: u32,
+    pub protocol: u8,
+    pub direction: u8,
+    pub ip_version: u8,
+    pub remote_address: [u8; 16],
+    pub prefix_length: u8,
+    pub remote_port_start: u16,
+    pub remote_port_end: u16,
+    pub verdict: u8,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RuleError {
+    /// `ip_version` is not 4 or 6.
+    InvalidIpVersion(u8),
+    /// The prefix is longer than the address.
+    InvalidPrefixLength(u8),
+    /// The port range ends before it starts.
+    InvalidPortRange,
+    /// The table already has `MAX_RULES` rules.
+    TableFull,
+}
+
+impl core::fmt::Display for RuleError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            RuleError::InvalidIpVersion(v) => write!(f, "invalid ip version: {}", v),
+            RuleError::InvalidPrefixLength(l) => write!(f, "invalid prefix length: {}", l),
+            RuleError::InvalidPortRange => write!(f, "invalid port range"),
+            RuleError::TableFull => write!(f, "rule table is full"),
+        }
+    }
+}
+
+impl Rule {
+    fn address_len(&self) -> Option<usize> {
+        match self.ip_version {
+            4 => Some(4),
+            6 => Some(16),
+            _ => None,
+        }
+    }
+
+    fn validate(&self) -> Result<(), RuleError> {
+        let Some(address_len) = self.address_len() else {
+            return Err(RuleError::InvalidIpVersion(self.ip_version));
+        };
+        if self.prefix_length as usize > address_len * 8 {
+            return Err(RuleError::InvalidPrefixLength(self.prefix_length));
+        }
+        let (start, end) = (self.remote_port_start, self.remote_port_end);
+        if start > end {
+            return Err(RuleError::InvalidPortRange);
+        }
+        Ok(())
+    }
+
+    /// Returns true if the connection matches the rule.
+    pub fn matches(
+        &self,
+        protocol: u8,
+        direction: u8,
+        remote_ip: &IpAddress,
+        remote_port: u16,
+    ) -> bool {
+        if self.protocol != RULE_ANY && self.protocol != protocol {
+            return false;
+        }
+        if self.direction != RULE_ANY && self.direction != direction {
+            return false;
+        }
+        let (start, end) = (self.remote_port_start, self.remote_port_end);
+        if remote_port < start || remote_port > end {
+            return false;
+        }
+        match remote_ip {
+            IpAddress::V4(ip) if self.ip_version == 4 => {
+                prefix_matches(&self.remote_address[..4], ip, self.prefix_length)
+            }
+            IpAddress::V6(ip) if self.ip_version == 6 => {
+                prefix_matches(&self.remote_address, ip, self.prefix_length)
+            }
+            _ => false,
+        }
+    }
+
+    pub(crate) fn push(&self, bytes: &mut Vec<u8>) {
+        let (id, remote_port_start, remote_port_end) =
+            (self.id, self.remote_port_start, self.remote_port_end);
+        bytes.extend_from_slice(&id.to_le_bytes());
+        bytes.push(self.protocol);
+        bytes.push(self.direction);
+        bytes.push(self.ip_version);
+        bytes.extend_from_slice(&self.remote_address);
+        bytes.push(self.prefix_length);
+        bytes.extend_from_slice(&remote_port_start.to_le_bytes());
+        bytes.extend_from_slice(&remote_port_end.to_le_bytes());
+        bytes.push(self.verdict);
+    }
+
+    pub(crate) fn parse(reader: &mut Reader) -> Option<Rule> {
+        Some(Rule {
+            id: reader.u32()?,
+            protocol: reader.u8()?,
+            direction: reader.u8()?,
+            ip_version: reader.u8()?,
+            remote_address: reader.array()?,
+            prefix_length: reader.u8()?,
+            remote_port_start: reader.u16()?,
+            remote_port_end: reader.u16()?,
+            verdict: reader.u8()?,
+        })
+    }
+}
+
+/// Compares the first `prefix_length` bits of the addresses.
+fn prefix_matches(network: &[u8], ip: &[u8], prefix_length: u8) -> bool {
+    let full_bytes = prefix_length as usize / 8;
+    if network[..full_bytes] != ip[..full_bytes] {
+        return false;
+    }
+    let rest_bits = prefix_length % 8;
+    if rest_bits == 0 {
+        return true;
+    }
+    let mask = 0xFF_u8 << (8 - rest_bits);
+    network[full_bytes] & mask == ip[full_bytes] & mask
+}
+
+/// Rules sorted by id. The verdict of the first matching rule is used.
+pub struct RuleTable {
+    rules: Vec<Rule>,
+}
+
+impl RuleTable {
+    pub const fn new() -> Self {
+        Self { rules: Vec::new() }
+    }
+
+    /// Adds the rule. A rule with the same id is replaced.
+    pub fn add(&mut self, rule: Rule) -> Result<(), RuleError> {
+        rule.validate()?;
+        let id = rule.id;
+        match self.rules.binary_search_by_key(&id, |r| r.id) {
+            Ok(index) => self.rules[index] = rule,
+            Err(index) => {
+                if self.rules.len() >= MAX_RULES {
+                    return Err(RuleError::TableFull);
+                }
+                self.rules.insert(index, rule);
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes the rule with the given id. Returns false if there is no such rule.
+    pub fn remove(&mut self, id: u32) -> bool {
+        match self.rules.binary_search_by_key(&id, |r| r.id) {
+            Ok(index) => {
+                self.rules.remove(index);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.rules.clear();
+    }
+
+    /// Returns the rules sorted by id.
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    /// Returns the first rule that matches the connection.
+    pub fn find(
+        &self,
+        protocol: u8,
+        direction: u8,
+        remote_ip: &IpAddress,
+        remote_port: u16,
+    ) -> Option<&Rule> {
+        self.rules
+            .iter()
+            .find(|rule| rule.matches(protocol, direction, remote_ip, remote_port))
+    }
+}
+
+impl Default for RuleTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Rule used in the tests of the Go and Rust encoders.
+#[cfg(test)]
+pub(crate) fn test_rule() -> Rule {
+    Rule {
+        id: 1,
+        protocol: 6,
+        direction: 0,
+        ip_version: 4,
+        remote_address: [10, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        prefix_length: 16,
+        remote_port_start: 80,
+        remote_port_end: 443,
+        verdict: 5,
+    }
+}
+
+#[cfg(test)]
+fn rule_v4(id: u32, address: [u8; 4], prefix_length: u8, verdict: u8) -> Rule {
+    let mut remote_address = [0; 16];
+    remote_address[..4].copy_from_slice(&address);
+    Rule {
+        id,
+        protocol: RULE_ANY,
+        direction: RULE_ANY,
+        ip_version: 4,
+        remote_address,
+        prefix_length,
+        remote_port_start: 0,
+        remote_port_end: u16::MAX,
+        verdict,
+    }
+}
+
+#[test]
+fn test_prefix_match() {
+    let rule = rule_v4(1, [10, 1, 128, 0], 17, 5);
+    assert!(rule.matches(6, 0, &IpAddress::V4([10, 1, 128, 1]), 80));
+    assert!(rule.matches(6, 0, &IpAddress::V4([10, 1, 255, 255]), 80));
+    assert!(!rule.matches(6, 0, &IpAddress::V4([10, 1, 127, 255]), 80));
+    assert!(!rule.matches(6, 0, &IpAddress::V4([10, 2, 128, 1]), 80));
+    // Ipv4 rules don't match ipv6 addresses.
+    assert!(!rule.matches(
+        6,
+        0,
+        &IpAddress::V6([10, 1, 128, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]),
+        80
+    ));
+
+    let any = rule_v4(2, [1, 2, 3, 4], 0, 5);
+    assert!(any.matches(17, 1, &IpAddress::V4([8, 8, 8, 8]), 53));
+
+    let host = Rule {
+        ip_version: 6,
+        remote_address: [0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
+        prefix_length: 128,
+        ..rule_v4(3, [0; 4], 0, 5)
+    };
+    assert!(host.matches(6, 0, &IpAddress::V6(host.remote_address), 443));
+    let mut other = host.remote_address;
+    other[15] = 2;
+    assert!(!host.matches(6, 0, &IpAddress::V6(other), 443));
+}
+
+#[test]
+fn test_protocol_direction_and_ports() {
+    let rule = Rule {
+        protocol: 6,
+        direction: 0,
+        remote_port_start: 8000,
+        remote_port_end: 8080,
+        ..rule_v4(1, [0; 4], 0, 5)
+    };
+    let ip = IpAddress::V4([1, 2, 3, 4]);
+    assert!(rule.matches(6, 0, &ip, 8000));
+    assert!(rule.matches(6, 0, &ip, 8080));
+    assert!(!rule.matches(6, 0, &ip, 7999));
+    assert!(!rule.matches(6, 0, &ip, 8081));
+    assert!(!rule.matches(17, 0, &ip, 8000));
+    assert!(!rule.matches(6, 1, &ip, 8000));
+}
+
+#[test]
+fn test_rule_table() {
+    let mut table = RuleTable::new();
+    table.add(rule_v4(20, [10, 0, 0, 0], 8, 4)).unwrap();
+    table.add(rule_v4(10, [10, 1, 0, 0], 16, 2)).unwrap();
+
+    // The lowest id is checked first.
+    let ip = IpAddress::V4([10, 1, 2, 3]);
+    assert_eq!(table.find(6, 0, &ip, 80).map(|r| r.id), Some(10));
+    let ip = IpAddress::V4([10, 2, 2, 3]);
+    assert_eq!(table.find(6, 0, &ip, 80).map(|r| r.id), Some(20));
+    assert_eq!(table.find(6, 0, &IpAddress::V4([11, 0, 0, 1]), 80), None);
+
+    // Same id replaces the rule.
+    table.add(rule_v4(20, [11, 0, 0, 0], 8, 4)).unwrap();
+    assert_eq!(table.rules().len(), 2);
+    assert_eq!(table.find(6, 0, &ip, 80), None);
+
+    assert!(table.remove(10));
+    assert!(!table.remove(10));
+    assert_eq!(table.rules().iter().map(|r| r.id).collect::<Vec<_>>(), [20]);
+}
+
+#[test]
+fn test_rule_validation() {
+    let mut table = RuleTable::new();
+    assert_eq!(
+        table.add(rule_v4(1, [0; 4], 33, 4)),
+        Err(RuleError::InvalidPrefixLength(33))
+    );
+    assert_eq!(
+        table.add(Rule {
+            ip_version: 5,
+            ..rule_v4(1, [0; 4], 0, 4)
+        }),
+        Err(RuleError::InvalidIpVersion(5))
+    );
+    assert_eq!(
+        table.add(Rule {
+            remote_port_start: 2,
+            remote_port_end: 1,
+            ..rule_v4(1, [0; 4], 0, 4)
+        }),
+        Err(RuleError::InvalidPortRange)
+    );
+    assert!(table
+        .add(Rule {
+            ip_version: 6,
+            ..rule_v4(1, [0; 4], 128, 4)
+        })
+        .is_ok());
+
+    for id in 0..MAX_RULES as u32 {
+        table.add(rule_v4(id, [0; 4], 0, 4)).unwrap();
+    }
+    assert_eq!(
+        table.add(rule_v4(MAX_RULES as u32, [0; 4], 0, 4)),
+        Err(RuleError::TableFull)
+    );
+    // Replacing still works when the table is full.
+    assert!(table.add(rule_v4(0, [1; 4], 8, 4)).is_ok());
+}
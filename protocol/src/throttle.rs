@@ -0,0 +1,366 @@
+// Bandwidth limits for the packets of the driver. Packets over a limit wait in the queue of the
+// limit until its token bucket has tokens again.
+
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    vec::Vec,
+};
+
+/// Bytes that a limit lets through at once after it was idle, in milliseconds of its rate.
+pub const BURST_MS: u64 = 100;
+
+/// Longest time that a packet waits in a queue. Packets that would wait longer are dropped.
+pub const MAX_DELAY_MS: u64 = 1000;
+
+/// Token bucket with one token per byte. Tokens are counted in thousandths of a byte,
+/// so low rates don't lose the tokens of short intervals.
+pub struct TokenBucket {
+    bytes_per_second: u64,
+    // Negative after a value larger than the tokens left was taken.
+    tokens: i64,
+    // Time of the last refill, in milliseconds.
+    timestamp: u64,
+}
+
+impl TokenBucket {
+    /// Creates a full bucket.
+    pub fn new(bytes_per_second: u64, now: u64) -> Self {
+        let mut bucket = Self {
+            bytes_per_second,
+            tokens: 0,
+            timestamp: now,
+        };
+        bucket.tokens = bucket.capacity();
+        bucket
+    }
+
+    pub fn bytes_per_second(&self) -> u64 {
+        self.bytes_per_second
+    }
+
+    /// Changes the rate. Tokens that were added with the old rate are kept, up to the new capacity.
+    pub fn set_rate(&mut self, bytes_per_second: u64, now: u64) {
+        self.refill(now);
+        self.bytes_per_second = bytes_per_second;
+        self.tokens = self.tokens.min(self.capacity());
+    }
+
+    /// Takes `size` tokens if the bucket has any tokens left. The bucket can go into debt,
+    /// so values larger than the burst get through too.
+    pub fn try_take(&mut self, size: u64, now: u64) -> bool {
+        self.refill(now);
+        if self.tokens <= 0 {
+            return false;
+        }
+        self.tokens = self.tokens.saturating_sub(to_milli(size));
+        true
+    }
+
+    /// Milliseconds until the bucket has tokens again. 0 if it has tokens now.
+    pub fn wait_ms(&mut self, now: u64) -> u64 {
+        self.refill(now);
+        if self.tokens > 0 || self.bytes_per_second == 0 {
+            return 0;
+        }
+        // One token more than the debt.
+        (self.tokens.unsigned_abs() + 1).div_ceil(self.bytes_per_second)
+    }
+
+    fn capacity(&self) -> i64 {
+        to_milli(self.bytes_per_second.saturating_mul(BURST_MS) / 1000).max(1000)
+    }
+
+    fn refill(&mut self, now: u64) {
+        // The system time can go back, the bucket is not refilled until then.
+        let elapsed = now.saturating_sub(self.timestamp);
+        self.timestamp = now;
+        // Milliseconds times bytes per second are thousandths of a byte.
+        let added =
+            i64::try_from(elapsed.saturating_mul(self.bytes_per_second)).unwrap_or(i64::MAX);
+        self.tokens = self.tokens.saturating_add(added).min(self.capacity());
+    }
+}
+
+fn to_milli(bytes: u64) -> i64 {
+    i64::try_from(bytes.saturating_mul(1000)).unwrap_or(i64::MAX)
+}
+
+/// What happens to a new value of a limit, see `Throttle::admit`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Admission {
+    /// The value can be sent now. Its tokens are already taken.
+    Send,
+    /// The value has to wait, it has to be passed to `Throttle::queue`.
+    Queue,
+    /// The queue of the limit is full.
+    Drop,
+}
+
+struct Limit<T> {
+    bucket: TokenBucket,
+    // Values with their size, oldest first.
+    queue: VecDeque<(T, u64)>,
+    queued_bytes: u64,
+}
+
+impl<T> Limit<T> {
+    fn max_queued_bytes(&self) -> u64 {
+        self.bucket.bytes_per_second().saturating_mul(MAX_DELAY_MS) / 1000
+    }
+}
+
+/// Token bucket limits by key, each with a queue of the values that wait for tokens.
+/// Values of a limit are released in the order they were queued.
+pub struct Throttle<K, T> {
+    limits: BTreeMap<K, Limit<T>>,
+    queued: usize,
+}
+
+impl<K: Ord + Copy, T> Throttle<K, T> {
+    pub fn new() -> Self {
+        Self {
+            limits: BTreeMap::new(),
+            queued: 0,
+        }
+    }
+
+    /// Sets the limit of the key in bytes per second. 0 removes the limit and returns the values
+    /// that were waiting, they are not limited anymore. A new rate keeps the queue.
+    pub fn set_limit(&mut self, key: K, bytes_per_second: u64, now: u64) -> Vec<T> {
+        if bytes_per_second == 0 {
+            let Some(limit) = self.limits.remove(&key) else {
+                return Vec::new();
+            };
+            self.queued -= limit.queue.len();
+            return limit.queue.into_iter().map(|(value, _)| value).collect();
+        }
+        match self.limits.get_mut(&key) {
+            Some(limit) => limit.bucket.set_rate(bytes_per_second, now),
+            None => {
+                self.limits.insert(
+                    key,
+                    Limit {
+                        bucket: TokenBucket::new(bytes_per_second, now),
+                        queue: VecDeque::new(),
+                        queued_bytes: 0,
+                    },
+                );
+            }
+        }
+        Vec::new()
+    }
+
+    pub fn has_limit(&self, key: &K) -> bool {
+        self.limits.contains_key(key)
+    }
+
+    /// Removes the limits that `f` returns false for, with the values that were waiting.
+    pub fn retain_limits(&mut self, mut f: impl FnMut(&K) -> bool) {
+        let mut removed = 0;
+        self.limits.retain(|key, limit| {
+            let keep = f(key);
+            if !keep {
+                removed += limit.queue.len();
+            }
+            keep
+        });
+        self.queued -= removed;
+    }
+
+    /// Number of limits.
+    pub fn limit_count(&self) -> usize {
+        self.limits.len()
+    }
+
+    /// Number of values that wait in the queues.
+    pub fn queued_count(&self) -> usize {
+        self.queued
+    }
+
+    /// Decides what happens to a new value of `size` bytes. Values of keys without a limit are
+    /// always sent. A value is only sent if no older value of the limit is waiting.
+    pub fn admit(&mut self, key: &K, size: u64, now: u64) -> Admission {
+        let Some(limit) = self.limits.get_mut(key) else {
+            return Admission::Send;
+        };
+        if limit.queue.is_empty() {
+            if limit.bucket.try_take(size, now) {
+                return Admission::Send;
+            }
+            // An empty queue takes a value of any size.
+            return Admission::Queue;
+        }
+        if limit.queued_bytes.saturating_add(size) > limit.max_queued_bytes() {
+            return Admission::Drop;
+        }
+        Admission::Queue
+    }
+
+    /// Adds a value that got `Admission::Queue`. The value is returned if the limit was removed
+    /// in the meantime, it can be sent.
+    pub fn queue(&mut self, key: &K, value: T, size: u64) -> Result<(), T> {
+        let Some(limit) = self.limits.get_mut(key) else {
+            return Err(value);
+        };
+        limit.queue.push_back((value, size));
+        limit.queued_bytes += size;
+        self.queued += 1;
+        Ok(())
+    }
+
+    /// Removes the values that have tokens now, from every limit.
+    pub fn pop_ready(&mut self, now: u64) -> Vec<T> {
+        let mut ready = Vec::new();
+        if self.queued == 0 {
+            return ready;
+        }
+        for limit in self.limits.values_mut() {
+            while let Some((_, size)) = limit.queue.front() {
+                let size = *size;
+                if !limit.bucket.try_take(size, now) {
+                    break;
+                }
+                limit.queued_bytes -= size;
+                ready.extend(limit.queue.pop_front().map(|(value, _)| value));
+            }
+        }
+        self.queued -= ready.len();
+        ready
+    }
+
+    /// Milliseconds until the next value can be released. `None` if no value is waiting.
+    pub fn next_release_ms(&mut self, now: u64) -> Option<u64> {
+        self.limits
+            .values_mut()
+            .filter(|limit| !limit.queue.is_empty())
+            .map(|limit| limit.bucket.wait_ms(now))
+            .min()
+    }
+}
+
+impl<K: Ord + Copy, T> Default for Throttle<K, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[test]
+fn test_token_bucket() {
+    // 10 KB/s, the burst is 1 KB.
+    let mut bucket = TokenBucket::new(10_000, 0);
+    assert!(bucket.try_take(600, 0));
+    assert!(bucket.try_take(600, 0));
+    // 200 bytes in debt.
+    assert!(!bucket.try_take(1, 0));
+    assert_eq!(bucket.wait_ms(0), 21);
+    assert!(!bucket.try_take(1, 20));
+    assert!(bucket.try_take(1, 21));
+
+    // Idle time only fills the bucket up to the burst.
+    assert!(bucket.try_take(5000, 10_000));
+    assert!(!bucket.try_take(1, 10_000));
+    assert_eq!(bucket.wait_ms(10_000), 401);
+
+    // A clock that goes back doesn't add tokens.
+    assert!(!bucket.try_take(1, 5_000));
+    assert!(bucket.try_take(1, 5_401));
+}
+
+#[test]
+fn test_token_bucket_low_rate() {
+    // 10 bytes per second, the burst is at least one byte.
+    let mut bucket = TokenBucket::new(10, 0);
+    assert!(bucket.try_take(1, 0));
+    assert!(!bucket.try_take(1, 0));
+    // A part of a token is enough, the bucket goes into debt.
+    assert!(bucket.try_take(1, 1));
+    // Intervals that are shorter than one byte still count.
+    for now in 2..=100 {
+        assert!(!bucket.try_take(1, now));
+    }
+    assert!(bucket.try_take(1, 101));
+}
+
+#[test]
+fn test_throttle() {
+    let mut throttle: Throttle<u32, u32> = Throttle::new();
+    // 10 KB/s, the burst is 1 KB and the queue holds 10 KB.
+    assert!(throttle.set_limit(1, 10_000, 0).is_empty());
+    assert_eq!(throttle.admit(&2, 100_000, 0), Admission::Send);
+    assert_eq!(throttle.admit(&1, 1500, 0), Admission::Send);
+    for value in 0..6 {
+        assert_eq!(throttle.admit(&1, 1500, 0), Admission::Queue);
+        assert_eq!(throttle.queue(&1, value, 1500), Ok(()));
+    }
+    assert_eq!(throttle.admit(&1, 1500, 0), Admission::Drop);
+    assert_eq!(throttle.admit(&1, 1000, 0), Admission::Queue);
+    assert_eq!(throttle.queued_count(), 6);
+
+    // The 500 bytes of debt are paid after 50 ms, then 150 ms per value.
+    assert_eq!(throttle.next_release_ms(0), Some(51));
+    assert_eq!(throttle.pop_ready(50), []);
+    assert_eq!(throttle.pop_ready(51), [0]);
+    assert_eq!(throttle.pop_ready(100), []);
+    assert_eq!(throttle.pop_ready(202), [1]);
+    // Idle time only fills the bucket up to the burst.
+    assert_eq!(throttle.pop_ready(500), [2]);
+
+    // A higher rate keeps the queue.
+    throttle.set_limit(1, 100_000, 500);
+    assert_eq!(throttle.pop_ready(536), [3, 4, 5]);
+    assert_eq!(throttle.next_release_ms(536), None);
+
+    // Removing the limit releases the values that wait.
+    throttle.set_limit(1, 1, 1000);
+    assert_eq!(throttle.admit(&1, 10, 1000), Admission::Send);
+    assert_eq!(throttle.admit(&1, 10, 1000), Admission::Queue);
+    assert_eq!(throttle.queue(&1, 6, 10), Ok(()));
+    assert_eq!(throttle.set_limit(1, 0, 1000), [6]);
+    assert!(!throttle.has_limit(&1));
+    assert_eq!(throttle.queue(&1, 7, 10), Err(7));
+    assert_eq!(throttle.queued_count(), 0);
+}
+
+#[test]
+fn test_retain_limits() {
+    let mut throttle: Throttle<u32, u32> = Throttle::new();
+    for key in 1..=3 {
+        throttle.set_limit(key, 1, 0);
+        assert_eq!(throttle.admit(&key, 10, 0), Admission::Send);
+        assert_eq!(throttle.admit(&key, 10, 0), Admission::Queue);
+        assert_eq!(throttle.queue(&key, key, 10), Ok(()));
+    }
+    throttle.retain_limits(|key| *key != 2);
+    assert_eq!(throttle.limit_count(), 2);
+    assert_eq!(throttle.queued_count(), 2);
+    assert_eq!(throttle.set_limit(1, 0, 0), [1]);
+    assert_eq!(throttle.set_limit(3, 0, 0), [3]);
+}
+
+#[cfg(test)]
+proptest::proptest! {
+    /// The bytes that are sent in any interval are at most the rate plus the burst and one value.
+    #[test]
+    fn rate_is_kept(
+        bytes_per_second in 1_000..1_000_000_u64,
+        packets in proptest::collection::vec((0..20_u64, 1..3000_u64), 1..256)
+    ) {
+        let mut throttle: Throttle<u8, u64> = Throttle::new();
+        throttle.set_limit(0, bytes_per_second, 0);
+        let mut now = 0;
+        let mut sent = 0;
+        for (delay, size) in packets {
+            now += delay;
+            for released in throttle.pop_ready(now) {
+                sent += released;
+            }
+            match throttle.admit(&0, size, now) {
+                Admission::Send => sent += size,
+                Admission::Queue => proptest::prop_assert!(throttle.queue(&0, size, size).is_ok()),
+                Admission::Drop => {}
+            }
+            let burst = bytes_per_second * BURST_MS / 1000 + 3000;
+            proptest::prop_assert!(sent <= bytes_per_second * now / 1000 + burst);
+        }
+    }
+}
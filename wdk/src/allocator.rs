@@ -1,6 +1,9 @@
 extern crate alloc;
 
-use core::alloc::{GlobalAlloc, Layout};
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
 use alloc::alloc::handle_alloc_error;
 use windows_sys::Wdk::System::SystemServices::{ExAllocatePool2, ExFreePoolWithTag};
@@ -24,6 +27,29 @@ enum PoolType {
 
 pub struct WindowsAllocator {}
 
+// Updated on every allocation. Atomics so the stats can be read from any thread without a lock.
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATION_COUNT: AtomicUsize = AtomicUsize::new(0);
+static TOTAL_ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+pub struct AllocatorStats {
+    /// Bytes currently allocated.
+    pub allocated_bytes: usize,
+    /// Number of allocations that were not freed yet.
+    pub allocation_count: usize,
+    /// Number of allocations since the driver was loaded.
+    pub total_allocations: usize,
+}
+
+/// Returns the totals of the global allocator.
+pub fn get_stats() -> AllocatorStats {
+    AllocatorStats {
+        allocated_bytes: ALLOCATED_BYTES.load(Ordering::Relaxed),
+        allocation_count: ALLOCATION_COUNT.load(Ordering::Relaxed),
+        total_allocations: TOTAL_ALLOCATIONS.load(Ordering::Relaxed),
+    }
+}
+
 unsafe impl Sync for WindowsAllocator {}
 
 pub(crate) const POOL_TAG: u32 = u32::from_ne_bytes(*b"PMrs");
@@ -35,11 +61,16 @@ unsafe impl GlobalAlloc for WindowsAllocator {
             handle_alloc_error(layout);
         }
 
+        ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        ALLOCATION_COUNT.fetch_add(1, Ordering::Relaxed);
+        TOTAL_ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
         pool as *mut u8
     }
 
-    unsafe fn dealloc(&self, ptr: *mut u8, _: Layout) {
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
         ExFreePoolWithTag(ptr as _, POOL_TAG);
+        ALLOCATED_BYTES.fetch_sub(layout.size(), Ordering::Relaxed);
+        ALLOCATION_COUNT.fetch_sub(1, Ordering::Relaxed);
     }
 
     unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
@@ -532,4 +532,38 @@ extern "C" {
     /// The KeQuerySystemTime routine obtains the current system time.
     /// System time is a count of 100-nanosecond intervals since January 1, 1601. System time is typically updated approximately every ten milliseconds. This value is computed for the GMT time zone.
     pub(crate) fn pm_QuerySystemTime() -> u64;
+
+    /// The KeGetCurrentProcessorNumberEx routine returns the system-assigned number of the current processor.
+    /// `ProcNumber` is optional and can be null.
+    pub(crate) fn KeGetCurrentProcessorNumberEx(ProcNumber: *mut c_void) -> u32;
+
+    /// The PsSetCreateProcessNotifyRoutine routine adds a driver-supplied callback routine to, or removes it from, a list of routines to be called whenever a process is created or deleted.
+    pub(crate) fn PsSetCreateProcessNotifyRoutine(
+        notify_routine: crate::utils::ProcessNotifyFn,
+        remove: u8,
+    ) -> NTSTATUS;
+
+    /// The KeInitializeTimer routine initializes a timer object.
+    pub(crate) fn KeInitializeTimer(timer: *mut crate::timer::KTimer);
+
+    /// The KeInitializeDpc routine initializes a DPC object, and registers a CustomDpc routine for that object.
+    pub(crate) fn KeInitializeDpc(
+        dpc: *mut crate::timer::KDpc,
+        deferred_routine: crate::timer::DeferredRoutine,
+        deferred_context: *const c_void,
+    );
+
+    /// The KeSetTimerEx routine sets the absolute or relative interval at which a timer object is to be set to a signaled state, optionally supplies a CustomTimerDpc routine to be executed when that interval expires, and optionally supplies a recurring interval for the timer.
+    pub(crate) fn KeSetTimerEx(
+        timer: *mut crate::timer::KTimer,
+        due_time: i64,
+        period: i32,
+        dpc: *const crate::timer::KDpc,
+    ) -> u8;
+
+    /// The KeCancelTimer routine dequeues a timer object before the timer interval, if any was set, expires.
+    pub(crate) fn KeCancelTimer(timer: *mut crate::timer::KTimer) -> u8;
+
+    /// The KeFlushQueuedDpcs routine returns after all queued DPCs on all processors have executed.
+    pub(crate) fn KeFlushQueuedDpcs();
 }
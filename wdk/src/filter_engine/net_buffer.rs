@@ -111,6 +111,19 @@ impl NetBufferList {
         }
     }
 
+    /// Creates a net buffer list that owns `data`.
+    pub fn from_data(
+        data: Vec<u8>,
+        net_allocator: &NetworkAllocator,
+    ) -> Result<NetBufferList, String> {
+        let nbl = net_allocator.wrap_packet_in_nbl(&data)?;
+        Ok(NetBufferList {
+            nbl,
+            data: Some(data),
+            advance_on_drop: None,
+        })
+    }
+
     pub fn get_data_mut(&mut self) -> Option<&mut [u8]> {
         if let Some(data) = &mut self.data {
             return Some(data.as_mut_slice());
@@ -34,6 +34,7 @@ pub struct TransportPacketList {
     sub_interface_index: u32,
 }
 
+#[derive(Clone, Copy)]
 pub struct InjectInfo {
     pub ipv6: bool,
     pub inbound: bool,
@@ -1,4 +1,6 @@
 use crate::ffi::{NET_BUFFER, NET_BUFFER_LIST};
+
+use super::net_buffer::NetBufferListIter;
 use windows_sys::Wdk::Foundation::MDL;
 
 const FWPS_STREAM_FLAG_RECEIVE: u32 = 0x00000001;
@@ -56,6 +58,16 @@ impl StreamCalloutIoPacket {
         return 0;
     }
 
+    /// Returns the number of net buffer lists in the stream data. Each one holds a segment.
+    pub fn get_net_buffer_list_count(&self) -> usize {
+        unsafe {
+            if let Some(stream_data) = self.stream_data.as_ref() {
+                return NetBufferListIter::new(stream_data.net_buffer_list_chain).count();
+            }
+        }
+        return 0;
+    }
+
     pub fn is_receive(&self) -> bool {
         unsafe {
             if let Some(stream_data) = self.stream_data.as_ref() {
@@ -5,7 +5,7 @@ use core::{
     marker::PhantomData,
     mem::MaybeUninit,
     pin::Pin,
-    sync::atomic::{AtomicBool, Ordering},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
 };
 
 use crate::dbg;
@@ -19,6 +19,7 @@ pub enum Status {
     Timeout,
     UserAPC,
     Abandoned,
+    Full,
 }
 
 impl Display for Status {
@@ -28,6 +29,7 @@ impl Display for Status {
             Status::Timeout => write!(f, "Timeout"),
             Status::UserAPC => write!(f, "UserAPC"),
             Status::Abandoned => write!(f, "Abandoned"),
+            Status::Full => write!(f, "Full"),
         }
     }
 }
@@ -80,6 +82,11 @@ pub struct IOQueue<T> {
     // The address of the value should not change.
     kernel_queue: Pin<Box<UnsafeCell<KQUEUE>>>,
     initialized: AtomicBool,
+    // Number of entries in the queue. Kept separately, the kernel queue can't be read without a lock.
+    len: AtomicUsize,
+    // Maximum number of entries, 0 for no limit. Entries that don't fit are dropped and counted.
+    max_len: AtomicUsize,
+    dropped: AtomicUsize,
     _type: PhantomData<T>, // 0 size variable. Required for the generic to work properly. Compiler limitation.
 }
 
@@ -95,13 +102,23 @@ impl<T> IOQueue<T> {
             Self {
                 kernel_queue,
                 initialized: AtomicBool::new(true),
+                len: AtomicUsize::new(0),
+                max_len: AtomicUsize::new(0),
+                dropped: AtomicUsize::new(0),
                 _type: PhantomData,
             }
         }
     }
 
-    /// Pushes new entry of any type.
+    /// Pushes new entry of any type. Fails with `Status::Full` if the queue has `max_len` entries.
     pub fn push(&self, entry: T) -> Result<(), Status> {
+        // Pushes on other processors can exceed the limit by a few entries.
+        let max_len = self.max_len.load(Ordering::Relaxed);
+        if max_len != 0 && self.len.load(Ordering::Relaxed) >= max_len {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return Err(Status::Full);
+        }
+
         let kqueue = self.kernel_queue.get();
         // Allocate entry.
         let list_entry = Box::new(Entry {
@@ -113,6 +130,8 @@ impl<T> IOQueue<T> {
         });
         let raw_ptr = Box::into_raw(list_entry);
 
+        // Count the entry before it can be popped.
+        self.len.fetch_add(1, Ordering::Relaxed);
         // Check if initialized.
         let result = if self.initialized.load(Ordering::Acquire) {
             unsafe { KeInsertQueue(kqueue, raw_ptr as *mut c_void) }
@@ -125,6 +144,7 @@ impl<T> IOQueue<T> {
             return Ok(());
         }
 
+        self.len.fetch_sub(1, Ordering::Relaxed);
         _ = unsafe { Box::from_raw(raw_ptr) };
         return Err(Status::Uninitialized);
     }
@@ -146,6 +166,7 @@ impl<T> IOQueue<T> {
                     _ => {
                         // The return value is a pointer.
                         let list_entry = Box::from_raw(list_entry);
+                        self.len.fetch_sub(1, Ordering::Relaxed);
                         let entry = list_entry.entry;
                         return Ok(entry);
                     }
@@ -174,6 +195,21 @@ impl<T> IOQueue<T> {
         self.pop_internal(&timeout_ptr)
     }
 
+    /// Returns the number of elements in the queue.
+    pub fn count(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    /// Sets the maximum number of elements. 0 removes the limit. Elements already in the queue are kept.
+    pub fn set_max_len(&self, max_len: usize) {
+        self.max_len.store(max_len, Ordering::Relaxed);
+    }
+
+    /// Returns the number of elements that were dropped because the queue was full, and resets it.
+    pub fn take_dropped(&self) -> usize {
+        self.dropped.swap(0, Ordering::Relaxed)
+    }
+
     /// Removes all elements and frees all the memory. The object can't be used after this function is called.
     pub fn rundown(&self) {
         unsafe {
@@ -197,6 +233,7 @@ impl<T> IOQueue<T> {
                     dbg!("discarding last entry");
                     let _ = Box::from_raw(entry as *mut Entry<T>);
                 }
+                self.len.store(0, Ordering::Relaxed);
             }
         }
     }
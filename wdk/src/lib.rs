@@ -14,6 +14,7 @@ pub mod ioqueue;
 pub mod irp_helpers;
 pub mod rw_spin_lock;
 pub mod spin_lock;
+pub mod timer;
 pub mod utils;
 
 #[allow(dead_code)]
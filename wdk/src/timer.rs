@@ -0,0 +1,96 @@
+use core::ffi::c_void;
+
+use alloc::{boxed::Box, format, string::String};
+
+use crate::ffi;
+
+// Opaque storage for the KTIMER WDK C struct. 64 bytes on x64 and arm64.
+#[repr(C, align(16))]
+pub(crate) struct KTimer([u8; 64]);
+
+// Opaque storage for the KDPC WDK C struct. 64 bytes on x64 and arm64.
+#[repr(C, align(16))]
+pub(crate) struct KDpc([u8; 64]);
+
+pub(crate) type DeferredRoutine = unsafe extern "system" fn(
+    dpc: *const KDpc,
+    context: *const c_void,
+    argument1: *const c_void,
+    argument2: *const c_void,
+);
+
+// The kernel keeps pointers to the timer and the dpc, they must not move.
+struct TimerObjects {
+    timer: KTimer,
+    dpc: KDpc,
+}
+
+/// Longest period of a `Timer`.
+pub const MAX_PERIOD_MS: u32 = i32::MAX as u32;
+
+/// Periodic kernel timer. The callback is called from a DPC at DISPATCH_LEVEL.
+pub struct Timer {
+    objects: Box<TimerObjects>,
+    running: bool,
+}
+
+impl Timer {
+    pub fn new(callback: fn()) -> Self {
+        let mut objects = Box::new(TimerObjects {
+            timer: KTimer([0; 64]),
+            dpc: KDpc([0; 64]),
+        });
+        unsafe {
+            ffi::KeInitializeTimer(&mut objects.timer);
+            ffi::KeInitializeDpc(&mut objects.dpc, timer_dpc, callback as *const c_void);
+        }
+        Self {
+            objects,
+            running: false,
+        }
+    }
+
+    /// Calls the callback every `period_ms` milliseconds. Replaces the previous period.
+    /// The period must be between 1 and `MAX_PERIOD_MS`, the kernel takes it as a signed value.
+    pub fn start(&mut self, period_ms: u32) -> Result<(), String> {
+        let period = match i32::try_from(period_ms) {
+            Ok(period) if period > 0 => period,
+            _ => return Err(format!("invalid timer period: {} ms", period_ms)),
+        };
+        // Negative due time is relative, in 100 nanosecond units.
+        let due_time = -(period as i64 * 10_000);
+        unsafe {
+            ffi::KeSetTimerEx(&mut self.objects.timer, due_time, period, &self.objects.dpc);
+        }
+        self.running = true;
+        Ok(())
+    }
+
+    /// Stops the timer and waits for a callback that is already queued. Must be called at PASSIVE_LEVEL.
+    pub fn stop(&mut self) {
+        if !self.running {
+            return;
+        }
+        unsafe {
+            ffi::KeCancelTimer(&mut self.objects.timer);
+            ffi::KeFlushQueuedDpcs();
+        }
+        self.running = false;
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+unsafe extern "system" fn timer_dpc(
+    _dpc: *const KDpc,
+    context: *const c_void,
+    _argument1: *const c_void,
+    _argument2: *const c_void,
+) {
+    let callback: fn() = core::mem::transmute(context);
+    callback();
+}
@@ -1,6 +1,6 @@
 use alloc::string::{String, ToString};
 use ntstatus::ntstatus::NtStatus;
-use windows_sys::Win32::Foundation::STATUS_SUCCESS;
+use windows_sys::Win32::Foundation::{HANDLE, STATUS_SUCCESS};
 
 use crate::ffi;
 
@@ -20,3 +20,40 @@ pub fn get_system_timestamp_ms() -> u64 {
     // 100 nano seconds units -> device by 10 -> micro seconds -> divide by 1000 -> milliseconds
     unsafe { ffi::pm_QuerySystemTime() / 10_000 }
 }
+
+/// Returned by `get_current_irql` on architectures where the IRQL is not read.
+pub const IRQL_UNKNOWN: u8 = 0xFF;
+
+/// Returns the current IRQL. `KeGetCurrentIrql` is inline in the WDK headers, on x64 it reads CR8.
+#[cfg(target_arch = "x86_64")]
+pub fn get_current_irql() -> u8 {
+    let irql: u64;
+    unsafe {
+        core::arch::asm!("mov {}, cr8", out(reg) irql, options(nomem, nostack, preserves_flags));
+    }
+    irql as u8
+}
+
+/// On ARM64 the IRQL is kept in the processor control region, it is not read. Returns `IRQL_UNKNOWN`.
+#[cfg(not(target_arch = "x86_64"))]
+pub fn get_current_irql() -> u8 {
+    IRQL_UNKNOWN
+}
+
+pub fn get_current_processor_number() -> u32 {
+    unsafe { ffi::KeGetCurrentProcessorNumberEx(core::ptr::null_mut()) }
+}
+
+/// Called when a process is created or exits. `create` is 0 when the process exits.
+pub type ProcessNotifyFn =
+    unsafe extern "system" fn(parent_id: HANDLE, process_id: HANDLE, create: u8);
+
+/// Registers `callback` to be called on every process creation and exit.
+pub fn set_process_notify(callback: ProcessNotifyFn) -> Result<(), String> {
+    check_ntstatus(unsafe { ffi::PsSetCreateProcessNotifyRoutine(callback, 0) })
+}
+
+/// Removes a callback that was registered with `set_process_notify`.
+pub fn remove_process_notify(callback: ProcessNotifyFn) -> Result<(), String> {
+    check_ntstatus(unsafe { ffi::PsSetCreateProcessNotifyRoutine(callback, 1) })
+}